@@ -0,0 +1,169 @@
+//!Benchmarks of [OscillatorBank::render]. Each group varies one dimension (voices, orbitals, buffer size,
+//! modulation type) and keeps the others at their defaults.
+//!
+//! The throughput is counted in rendered samples per voice, so the inverse of the reported `elem/s` is the
+//! time per sample and voice. Run with `cargo bench -p orbital-core`.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use orbital_core::{
+    osc::{
+        modulator::{ModulatorOsc, ParentIndex},
+        primary::PrimaryOsc,
+        ModulationType, OscillatorBank,
+    },
+    osc_array::{OscVoiceState, VoiceState},
+    state::{ModulatorState, PrimaryState, SolarState},
+    SampleTime,
+};
+
+const SAMPLE_RATE: f32 = 48000.0;
+const DEFAULT_VOICES: usize = 4;
+///Primaries and modulators in the solar system.
+const DEFAULT_ORBITALS: (usize, usize) = (4, 8);
+const DEFAULT_BUFFER: usize = 512;
+
+///Solar system with `primaries` planets. The moons are spread over the planets, every third one orbits
+/// another moon.
+fn solar_state(primaries: usize, modulators: usize) -> SolarState {
+    let mut state = SolarState::default();
+    for slot in 0..primaries {
+        state.primary_states.push(PrimaryState {
+            offset: slot as f32 * 0.3,
+            state: PrimaryOsc {
+                speed_index: slot as f32 * 0.5 - 1.0,
+                volume: 0.5,
+                is_on: true,
+            },
+            slot,
+        });
+    }
+    for slot in 0..modulators {
+        let parent = if slot % 3 == 2 {
+            ParentIndex::Modulator(slot - 1)
+        } else {
+            ParentIndex::Primary(slot % primaries)
+        };
+        state.modulator_states.push(ModulatorState {
+            offset: slot as f32 * 0.1,
+            state: ModulatorOsc {
+                parent_osc_slot: parent,
+                is_on: true,
+                range: 0.3,
+                speed_index: slot as f32 * 0.25 - 1.0,
+            },
+            slot,
+        });
+    }
+    state
+}
+
+struct Setup {
+    voices: usize,
+    orbitals: (usize, usize),
+    buffer: usize,
+    mod_ty: ModulationType,
+}
+
+impl Default for Setup {
+    fn default() -> Self {
+        Setup {
+            voices: DEFAULT_VOICES,
+            orbitals: DEFAULT_ORBITALS,
+            buffer: DEFAULT_BUFFER,
+            mod_ty: ModulationType::Relative,
+        }
+    }
+}
+
+fn bench_setup(c: &mut Criterion, group: &str, param: impl ToString, setup: Setup) {
+    let mut bank = OscillatorBank::default();
+    bank.mod_ty = setup.mod_ty;
+    bank.on_state_change(&solar_state(setup.orbitals.0, setup.orbitals.1));
+
+    let mut voices = [OscVoiceState::default(); OscillatorBank::VOICE_COUNT];
+    for (idx, voice) in voices.iter_mut().take(setup.voices).enumerate() {
+        voice.state = VoiceState::On;
+        voice.freq = 110.0 * (idx + 1) as f32;
+        voice.velocity = 1.0;
+        voice.env.on_press(0, SAMPLE_RATE);
+        voice.filter_env.on_press(0, SAMPLE_RATE);
+    }
+
+    let mut out = vec![0.0; setup.buffer];
+    let mut time: SampleTime = 0;
+    let mut group = c.benchmark_group(group);
+    group.throughput(Throughput::Elements((setup.buffer * setup.voices) as u64));
+    group.bench_function(BenchmarkId::from_parameter(param.to_string()), |b| {
+        b.iter(|| {
+            bank.render(&voices, &mut out, SAMPLE_RATE, time);
+            time += setup.buffer as SampleTime;
+        })
+    });
+    group.finish();
+}
+
+fn voices(c: &mut Criterion) {
+    for voices in [1, 4, OscillatorBank::VOICE_COUNT] {
+        bench_setup(
+            c,
+            "voices",
+            voices,
+            Setup {
+                voices,
+                ..Default::default()
+            },
+        );
+    }
+}
+
+fn orbitals(c: &mut Criterion) {
+    for orbitals in [
+        (1, 1),
+        DEFAULT_ORBITALS,
+        (
+            OscillatorBank::PRIMARY_OSC_COUNT,
+            OscillatorBank::MOD_OSC_COUNT,
+        ),
+    ] {
+        bench_setup(
+            c,
+            "orbitals",
+            format!("{}+{}", orbitals.0, orbitals.1),
+            Setup {
+                orbitals,
+                ..Default::default()
+            },
+        );
+    }
+}
+
+fn buffer_size(c: &mut Criterion) {
+    for buffer in [64, 512, 2048] {
+        bench_setup(
+            c,
+            "buffer_size",
+            buffer,
+            Setup {
+                buffer,
+                ..Default::default()
+            },
+        );
+    }
+}
+
+fn modulation_type(c: &mut Criterion) {
+    for mod_ty in [ModulationType::Relative, ModulationType::Absolute] {
+        bench_setup(
+            c,
+            "modulation_type",
+            format!("{:?}", mod_ty),
+            Setup {
+                mod_ty,
+                ..Default::default()
+            },
+        );
+    }
+}
+
+criterion_group!(benches, voices, orbitals, buffer_size, modulation_type);
+criterion_main!(benches);
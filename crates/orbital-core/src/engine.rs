@@ -0,0 +1,102 @@
+use crate::{osc_array::OscArray, SampleTime};
+
+///Input events of the synth.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Event {
+    ///Velocity in 0..1
+    NoteOn {
+        note: u8,
+        velocity: f32,
+    },
+    NoteOff {
+        note: u8,
+    },
+    PolyPressure {
+        note: u8,
+        pressure: f32,
+    },
+    ChannelPressure(f32),
+    ///Midi CC 1 (0..1)
+    ModWheel(f32),
+}
+
+///Plays the synth with plain sample buffers. Keeps track of the position, so events can be timed relative
+/// to the next rendered block.
+///
+/// The output is the synth's voice sum. The plugin's output stage and effects are not part of the engine.
+#[derive(Clone)]
+pub struct Engine {
+    ///The voices and oscillator bank. Configure the sound through it.
+    pub synth: OscArray,
+    sample_rate: f32,
+    ///Samples rendered so far
+    position: SampleTime,
+}
+
+impl Engine {
+    pub fn new(synth: OscArray, sample_rate: f32) -> Self {
+        Engine {
+            synth,
+            sample_rate,
+            position: 0,
+        }
+    }
+
+    pub fn sample_rate(&self) -> f32 {
+        self.sample_rate
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.sample_rate = sample_rate;
+    }
+
+    ///Samples rendered since the engine was created or reset.
+    pub fn position(&self) -> SampleTime {
+        self.position
+    }
+
+    ///Restarts the sample clock. Should only be called while no note is playing.
+    pub fn reset(&mut self) {
+        self.position = 0;
+    }
+
+    ///Latency of the oscillator oversampling in samples.
+    pub fn latency(&self) -> u32 {
+        self.synth.bank.latency()
+    }
+
+    ///Applies `event` at `offset` samples into the next rendered block.
+    pub fn handle(&mut self, event: Event, offset: usize) {
+        let at = self.position + offset as SampleTime;
+        match event {
+            Event::NoteOn { note, velocity } => {
+                self.synth.note_on(note, velocity, at, self.sample_rate)
+            }
+            Event::NoteOff { note } => self.synth.note_off(note, at),
+            Event::PolyPressure { note, pressure } => self.synth.note_pressure(note, pressure),
+            Event::ChannelPressure(pressure) => self.synth.channel_pressure = pressure,
+            Event::ModWheel(value) => self.synth.mod_wheel = value,
+        }
+    }
+
+    ///Renders the next block into both channels. The synth is mono, so both receive the same signal.
+    /// `right` has to be as long as `left`.
+    pub fn render(&mut self, left: &mut [f32], right: &mut [f32]) {
+        self.render_mono(left);
+        right.copy_from_slice(left);
+    }
+
+    ///Renders the next `out.len()` samples into a single channel.
+    pub fn render_mono(&mut self, out: &mut [f32]) {
+        #[cfg(feature = "profile")]
+        puffin::profile_function!("synth main process");
+        self.synth.render(out, self.sample_rate, self.position);
+        self.position += out.len() as SampleTime;
+    }
+}
+
+impl Default for Engine {
+    fn default() -> Self {
+        Engine::new(OscArray::default(), 44100.0)
+    }
+}
@@ -1,6 +1,6 @@
 use serde::{Deserialize, Serialize};
 
-use crate::Time;
+use crate::{SampleTime, Time};
 
 #[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
 pub struct EnvelopeParams {
@@ -26,7 +26,8 @@ impl Default for EnvelopeParams {
 }
 
 ///Simple 5 stage envelope implementation. There are three state changing functions (via set), and a sample function.
-/// Note that usually the parameters and values are in seconds, but in theory you can use anything.
+/// The parameters are in seconds, events and sampling positions are in samples. Only the distance to the press and
+/// release events is converted to seconds, so the envelope stays sample exact, no matter how long the session runs.
 ///
 ///
 /// A typical envelope lifetime. Note that you can set parts to 0 to remove them
@@ -48,9 +49,12 @@ impl Default for EnvelopeParams {
 /// ```
 #[derive(Serialize, Deserialize, Clone, Copy, Debug)]
 pub struct Envelope {
-    pub press: Option<Time>,
-    pub release: Option<Time>,
+    pub press: Option<SampleTime>,
+    pub release: Option<SampleTime>,
     pub parameters: EnvelopeParams,
+    ///Sample rate the events were recorded at.
+    #[serde(default = "Envelope::default_sample_rate")]
+    pub sample_rate: f32,
 }
 
 impl Default for Envelope {
@@ -59,36 +63,47 @@ impl Default for Envelope {
             press: None,
             release: None,
             parameters: EnvelopeParams::default(),
+            sample_rate: Self::default_sample_rate(),
         }
     }
 }
 
 impl Envelope {
-    ///sets the press event `at` the given time, resets the release event.
-    pub fn on_press(&mut self, at: Time) {
+    fn default_sample_rate() -> f32 {
+        44100.0
+    }
+
+    ///sets the press event `at` the given sample, resets the release event.
+    pub fn on_press(&mut self, at: SampleTime, sample_rate: f32) {
         self.press = Some(at);
         self.release = None;
+        self.sample_rate = sample_rate;
     }
 
-    ///Sets release event `at` the given time. From now on if you sample after `at` you'll be in the release region.
-    pub fn on_release(&mut self, at: Time) {
+    ///Sets release event `at` the given sample. From now on if you sample after `at` you'll be in the release region.
+    pub fn on_release(&mut self, at: SampleTime) {
         self.release = Some(at);
     }
 
+    ///Seconds from sample `from` to sample `to`. Negative if `to` is before `from`.
+    fn seconds_between(&self, from: SampleTime, to: SampleTime) -> Time {
+        (to as i64 - from as i64) as Time / self.sample_rate as Time
+    }
+
     pub fn reset(&mut self) {
         self.press = None;
         self.release = None;
     }
     //steps the delay-attack-hold-decay chain until `at`. If at too big sustain is returned, if to small,
     // 0.0 is returned
-    fn step_linear(&self, at: Time) -> f32 {
+    fn step_linear(&self, at: SampleTime) -> f32 {
         let start = if let Some(s) = self.press {
             s
         } else {
             return 0.0;
         };
 
-        let mut local = at - start;
+        let mut local = self.seconds_between(start, at);
         //short path to decay
         if local
             > (self.parameters.delay
@@ -131,9 +146,9 @@ impl Envelope {
         self.parameters.sustain_level
     }
 
-    pub fn after_sampling(&self, at: Time) -> bool {
+    pub fn after_sampling(&self, at: SampleTime) -> bool {
         if let Some(end) = self.release {
-            (end + self.parameters.release) < at
+            self.seconds_between(end, at) > self.parameters.release
         } else {
             false
         }
@@ -144,14 +159,14 @@ impl Envelope {
     /// since `at` is still in the decay range at that point.
     ///
     /// Note if no press event is set this will always return zero. But consider checking that case in your synth.
-    pub fn sample(&self, at: Time) -> f32 {
+    pub fn sample(&self, at: SampleTime) -> f32 {
         if self.press.is_none() {
             return 0.0;
         }
 
         if let Some(release) = self.release {
             //check where in release we are
-            let relo = at - release;
+            let relo = self.seconds_between(release, at);
             if relo < 0.0 {
                 //not yet released, can happen at offsetted midi events
                 self.step_linear(at)
@@ -0,0 +1,209 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "nih-plug", derive(nih_plug::prelude::Enum))]
+pub enum FilterMode {
+    LowPass,
+    HighPass,
+    BandPass,
+    Notch,
+}
+
+impl FilterMode {
+    pub const ALL: [FilterMode; 4] = [
+        FilterMode::LowPass,
+        FilterMode::HighPass,
+        FilterMode::BandPass,
+        FilterMode::Notch,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            FilterMode::LowPass => "LowPass",
+            FilterMode::HighPass => "HighPass",
+            FilterMode::BandPass => "BandPass",
+            FilterMode::Notch => "Notch",
+        }
+    }
+
+    pub fn next(&self) -> Self {
+        let idx = Self::ALL.iter().position(|m| m == self).unwrap_or(0);
+        Self::ALL[(idx + 1) % Self::ALL.len()]
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "nih-plug", derive(nih_plug::prelude::Enum))]
+pub enum FilterSlope {
+    #[cfg_attr(feature = "nih-plug", name = "12 dB")]
+    Db12,
+    #[cfg_attr(feature = "nih-plug", name = "24 dB")]
+    Db24,
+}
+
+impl FilterSlope {
+    pub fn name(&self) -> &'static str {
+        match self {
+            FilterSlope::Db12 => "12 dB",
+            FilterSlope::Db24 => "24 dB",
+        }
+    }
+
+    pub fn next(&self) -> Self {
+        match self {
+            FilterSlope::Db12 => FilterSlope::Db24,
+            FilterSlope::Db24 => FilterSlope::Db12,
+        }
+    }
+
+    fn stages(&self) -> usize {
+        match self {
+            FilterSlope::Db12 => 1,
+            FilterSlope::Db24 => 2,
+        }
+    }
+}
+
+///Filter settings the oscillator bank works with. The plugin fills them from its host parameters.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FilterSettings {
+    pub enabled: bool,
+    pub mode: FilterMode,
+    pub slope: FilterSlope,
+    ///Base cutoff in Hz
+    pub cutoff: f32,
+    ///0..1
+    pub resonance: f32,
+    pub env_amount: f32,
+    pub key_track: f32,
+    pub velocity: f32,
+}
+
+impl Default for FilterSettings {
+    fn default() -> Self {
+        FilterSettings {
+            enabled: false,
+            mode: FilterMode::LowPass,
+            slope: FilterSlope::Db12,
+            cutoff: 8000.0,
+            resonance: 0.0,
+            env_amount: 0.0,
+            key_track: 0.0,
+            velocity: 0.0,
+        }
+    }
+}
+
+impl FilterSettings {
+    ///Lowest cutoff in Hz the filter runs at.
+    pub const MIN_CUTOFF: f32 = 20.0;
+    ///Note frequency at which key tracking leaves the cutoff untouched (C4).
+    const KEY_CENTER: f32 = 261.63;
+
+    ///Cutoff of a voice playing `note_freq` at `velocity`, with the filter envelope at `env`.
+    #[inline]
+    pub fn voice_cutoff(&self, cutoff: f32, note_freq: f32, velocity: f32, env: f32) -> f32 {
+        let octaves = self.env_amount * env
+            + self.velocity * velocity
+            + self.key_track * (note_freq.max(1.0) / Self::KEY_CENTER).log2();
+        cutoff * 2.0f32.powf(octaves)
+    }
+
+    ///Damping of the svf. Resonance 1.0 is close to, but not at self oscillation.
+    fn damping(&self) -> f32 {
+        2.0 - 1.96 * self.resonance.clamp(0.0, 1.0)
+    }
+
+    ///Magnitude response of the filter at `freq` for the given `cutoff` (analog prototype).
+    pub fn magnitude(&self, cutoff: f32, freq: f32) -> f32 {
+        let w = freq / cutoff.max(Svf::MIN_CUTOFF);
+        let k = self.damping();
+        let w2 = w * w;
+        let denom = ((1.0 - w2) * (1.0 - w2) + (k * w) * (k * w)).sqrt();
+        let num = match self.mode {
+            FilterMode::LowPass => 1.0,
+            FilterMode::HighPass => w2,
+            FilterMode::BandPass => k * w,
+            FilterMode::Notch => (1.0 - w2).abs(),
+        };
+        (num / denom).powi(self.slope.stages() as i32)
+    }
+}
+
+///Coefficients of a single svf stage.
+#[derive(Clone, Copy)]
+struct SvfCoeffs {
+    k: f32,
+    a1: f32,
+    a2: f32,
+    a3: f32,
+}
+
+impl SvfCoeffs {
+    fn new(cutoff: f32, k: f32, sample_rate: f32) -> Self {
+        let cutoff = cutoff.clamp(Svf::MIN_CUTOFF, sample_rate * Svf::MAX_CUTOFF_RATIO);
+        let g = (std::f32::consts::PI * cutoff / sample_rate).tan();
+        let a1 = 1.0 / (1.0 + g * (g + k));
+        let a2 = g * a1;
+        let a3 = g * a2;
+        SvfCoeffs { k, a1, a2, a3 }
+    }
+}
+
+///Trapezoidal state variable filter (after Andrew Simper's "SvfLinearTrapOptimised2").
+#[derive(Clone, Copy, Default)]
+struct Svf {
+    ic1eq: f32,
+    ic2eq: f32,
+}
+
+impl Svf {
+    const MIN_CUTOFF: f32 = FilterSettings::MIN_CUTOFF;
+    ///Highest cutoff relative to the sample rate, keeps the `tan` well away from its pole.
+    const MAX_CUTOFF_RATIO: f32 = 0.49;
+
+    #[inline(always)]
+    fn process(&mut self, c: &SvfCoeffs, mode: FilterMode, v0: f32) -> f32 {
+        let v3 = v0 - self.ic2eq;
+        let v1 = c.a1 * self.ic1eq + c.a2 * v3;
+        let v2 = self.ic2eq + c.a2 * self.ic1eq + c.a3 * v3;
+        self.ic1eq = 2.0 * v1 - self.ic1eq;
+        self.ic2eq = 2.0 * v2 - self.ic2eq;
+
+        match mode {
+            FilterMode::LowPass => v2,
+            FilterMode::HighPass => v0 - c.k * v1 - v2,
+            //normalized to unity gain at the peak
+            FilterMode::BandPass => c.k * v1,
+            FilterMode::Notch => v0 - c.k * v1,
+        }
+    }
+}
+
+///Filter state of a single voice. Two svf stages are cascaded for the 24 dB slope.
+#[derive(Clone, Copy, Default)]
+pub struct VoiceFilter {
+    stages: [Svf; 2],
+}
+
+impl VoiceFilter {
+    pub fn reset(&mut self) {
+        *self = VoiceFilter::default();
+    }
+
+    #[inline]
+    pub fn process(
+        &mut self,
+        settings: &FilterSettings,
+        cutoff: f32,
+        sample_rate: f32,
+        x: f32,
+    ) -> f32 {
+        let coeffs = SvfCoeffs::new(cutoff, settings.damping(), sample_rate);
+        let mut y = x;
+        for stage in &mut self.stages[0..settings.slope.stages()] {
+            y = stage.process(&coeffs, settings.mode, y);
+        }
+        y
+    }
+}
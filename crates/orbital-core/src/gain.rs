@@ -0,0 +1,81 @@
+use serde::{Deserialize, Serialize};
+
+use crate::osc::sigmoid;
+
+///Composition curve the summed voices are shaped with.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(feature = "nih-plug", derive(nih_plug::prelude::Enum))]
+pub enum GainType {
+    #[default]
+    Sigmoid,
+    Linear,
+    Tanh,
+    ///Cubic soft clipper, `x - x³/3` within -1..1
+    Cubic,
+    ///Asymmetric tanh with a biased operating point. Introduces even harmonics (and DC).
+    Tube,
+    ///Triangle wavefolder that mirrors everything above ±1 back into range.
+    Foldback,
+    ///Quantizes to `BITCRUSH_BITS` bits.
+    BitCrush,
+}
+
+impl GainType {
+    pub const ALL: [GainType; 7] = [
+        GainType::Sigmoid,
+        GainType::Linear,
+        GainType::Tanh,
+        GainType::Cubic,
+        GainType::Tube,
+        GainType::Foldback,
+        GainType::BitCrush,
+    ];
+    ///Bias of the tube curve's operating point.
+    const TUBE_BIAS: f32 = 0.3;
+    ///Resolution of the bit crusher.
+    const BITCRUSH_BITS: i32 = 6;
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            GainType::Sigmoid => "Sigmoid",
+            GainType::Linear => "Linear",
+            GainType::Tanh => "Tanh",
+            GainType::Cubic => "Cubic",
+            GainType::Tube => "Tube",
+            GainType::Foldback => "Foldback",
+            GainType::BitCrush => "BitCrush",
+        }
+    }
+
+    #[inline(always)]
+    pub fn map(&self, value: f32) -> f32 {
+        match self {
+            GainType::Sigmoid => sigmoid(value),
+            GainType::Linear => value.clamp(-1.0, 1.0),
+            GainType::Tanh => value.tanh(),
+            GainType::Cubic => {
+                let x = value.clamp(-1.0, 1.0);
+                //scaled so that the curve reaches ±1 at the clipping point
+                1.5 * (x - x * x * x / 3.0)
+            }
+            GainType::Tube => (value + Self::TUBE_BIAS).tanh() - Self::TUBE_BIAS.tanh(),
+            GainType::Foldback => 1.0 - ((value + 1.0).rem_euclid(4.0) - 2.0).abs(),
+            GainType::BitCrush => {
+                let levels = (1 << (Self::BITCRUSH_BITS - 1)) as f32;
+                (value.clamp(-1.0, 1.0) * levels).round() / levels
+            }
+        }
+    }
+
+    fn index(&self) -> usize {
+        Self::ALL.iter().position(|g| g == self).unwrap_or(0)
+    }
+
+    pub fn next(&mut self) {
+        *self = Self::ALL[(self.index() + 1) % Self::ALL.len()];
+    }
+
+    pub fn prev(&mut self) {
+        *self = Self::ALL[(self.index() + Self::ALL.len() - 1) % Self::ALL.len()];
+    }
+}
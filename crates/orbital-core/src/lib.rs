@@ -0,0 +1,28 @@
+//!Host independent synth engine of Orbital. Everything needed to render audio, without any plugin or
+//! GUI dependencies. See [Engine] for the entry point.
+#![cfg_attr(feature = "simd", feature(portable_simd))]
+
+use std::f32::consts::PI;
+
+pub use engine::{Engine, Event};
+
+mod engine;
+pub mod envelope;
+pub mod filter;
+pub mod gain;
+pub mod mod_matrix;
+pub mod osc;
+pub mod osc_array;
+pub mod oversampling;
+pub mod schema;
+pub mod state;
+pub mod tuning;
+
+pub type Time = f64;
+///Position in samples since the engine was started. Events are timed in samples, so long sessions don't lose
+/// precision.
+pub type SampleTime = u64;
+
+pub const TWOPI: f32 = 2.0 * PI;
+///Default reference pitch (A4). Absolute modulation uses the configured reference pitch instead.
+pub const ABS_BASE_FREQ: f32 = 440.0;
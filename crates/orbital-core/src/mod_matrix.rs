@@ -0,0 +1,301 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    envelope::EnvelopeParams,
+    osc::{modulator::ParentIndex, OscillatorBank, VoiceModulation},
+    Time, TWOPI,
+};
+
+///All sources a routing can read from. Per-note sources are sampled for each voice, the
+/// controllers and LFOs are shared by all voices.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ModSource {
+    ///Note-on velocity (0..1)
+    Velocity,
+    ///Played key, mapped from midi note 0..127 to 0..1
+    Key,
+    ///Midi CC 1 (0..1)
+    ModWheel,
+    ///Poly- or channel pressure, whichever is higher (0..1)
+    Aftertouch,
+    Lfo1,
+    Lfo2,
+    ///The voice's amplitude envelope
+    Envelope,
+    ///Secondary envelope that is only used for modulation
+    ModEnvelope,
+    ///Random value (-1..1) that is rolled on each note-on
+    Random,
+}
+
+impl ModSource {
+    pub const COUNT: usize = 9;
+    pub const ALL: [ModSource; Self::COUNT] = [
+        ModSource::Velocity,
+        ModSource::Key,
+        ModSource::ModWheel,
+        ModSource::Aftertouch,
+        ModSource::Lfo1,
+        ModSource::Lfo2,
+        ModSource::Envelope,
+        ModSource::ModEnvelope,
+        ModSource::Random,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ModSource::Velocity => "Velocity",
+            ModSource::Key => "Key",
+            ModSource::ModWheel => "Mod Wheel",
+            ModSource::Aftertouch => "Aftertouch",
+            ModSource::Lfo1 => "LFO 1",
+            ModSource::Lfo2 => "LFO 2",
+            ModSource::Envelope => "Envelope",
+            ModSource::ModEnvelope => "Mod Envelope",
+            ModSource::Random => "Random",
+        }
+    }
+}
+
+///All modulation targets. Orbitals are addressed by their oscillator slot, so a routing stays valid
+/// while the orbit tree is edited. Routings to a slot that is currently not in use have no effect.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum ModDestination {
+    ///Volume of a primary, or range of a modulator orbital
+    Radius(ParentIndex),
+    ///Speed index (octaving) of an orbital
+    Speed(ParentIndex),
+    Attack,
+    Decay,
+    Sustain,
+    Release,
+    ///Voice output gain
+    Gain,
+}
+
+impl ModDestination {
+    ///Returns all destinations that are not bound to an orbital.
+    pub const GLOBAL: [ModDestination; 5] = [
+        ModDestination::Attack,
+        ModDestination::Decay,
+        ModDestination::Sustain,
+        ModDestination::Release,
+        ModDestination::Gain,
+    ];
+
+    pub fn name(&self) -> String {
+        match self {
+            ModDestination::Radius(ParentIndex::Primary(s)) => format!("Primary {} Volume", s),
+            ModDestination::Radius(ParentIndex::Modulator(s)) => format!("Modulator {} Range", s),
+            ModDestination::Speed(ParentIndex::Primary(s)) => format!("Primary {} Speed", s),
+            ModDestination::Speed(ParentIndex::Modulator(s)) => format!("Modulator {} Speed", s),
+            ModDestination::Attack => "Attack".to_owned(),
+            ModDestination::Decay => "Decay".to_owned(),
+            ModDestination::Sustain => "Sustain".to_owned(),
+            ModDestination::Release => "Release".to_owned(),
+            ModDestination::Gain => "Gain".to_owned(),
+        }
+    }
+}
+
+///Single source -> destination connection.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct Routing {
+    pub source: ModSource,
+    pub destination: ModDestination,
+    ///Bipolar amount (-1..1) of the source that is added to the destination.
+    pub amount: f32,
+}
+
+impl Default for Routing {
+    fn default() -> Self {
+        Routing {
+            source: ModSource::Velocity,
+            destination: ModDestination::Gain,
+            amount: 0.0,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LfoShape {
+    Sine,
+    Triangle,
+    Saw,
+    Square,
+}
+
+impl LfoShape {
+    pub const ALL: [LfoShape; 4] = [
+        LfoShape::Sine,
+        LfoShape::Triangle,
+        LfoShape::Saw,
+        LfoShape::Square,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            LfoShape::Sine => "Sine",
+            LfoShape::Triangle => "Triangle",
+            LfoShape::Saw => "Saw",
+            LfoShape::Square => "Square",
+        }
+    }
+}
+
+///Free running, bipolar low frequency oscillator shared by all voices.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct Lfo {
+    ///Rate in Hz
+    pub rate: f32,
+    pub shape: LfoShape,
+    ///Current phase (0..1)
+    #[serde(skip)]
+    phase: f32,
+}
+
+impl Default for Lfo {
+    fn default() -> Self {
+        Lfo {
+            rate: 1.0,
+            shape: LfoShape::Sine,
+            phase: 0.0,
+        }
+    }
+}
+
+impl Lfo {
+    pub const MAX_RATE: f32 = 20.0;
+
+    pub fn advance(&mut self, delta: Time) {
+        self.phase = (self.phase + (self.rate as Time * delta) as f32).fract();
+    }
+
+    pub fn value(&self) -> f32 {
+        match self.shape {
+            LfoShape::Sine => (self.phase * TWOPI).sin(),
+            LfoShape::Triangle => 1.0 - 4.0 * (self.phase - 0.5).abs(),
+            LfoShape::Saw => self.phase * 2.0 - 1.0,
+            LfoShape::Square => {
+                if self.phase < 0.5 {
+                    1.0
+                } else {
+                    -1.0
+                }
+            }
+        }
+    }
+}
+
+///Offsets that are applied to a single voice for one block.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ModTargets {
+    pub osc: VoiceModulation,
+    pub attack: Time,
+    pub decay: Time,
+    pub sustain: f32,
+    pub release: Time,
+}
+
+impl ModTargets {
+    ///Applies the envelope offsets to `base`.
+    pub fn modulate_envelope(&self, base: &EnvelopeParams) -> EnvelopeParams {
+        EnvelopeParams {
+            delay: base.delay,
+            attack: (base.attack + self.attack).max(ModMatrix::MIN_ENV_TIME),
+            hold: base.hold,
+            decay: (base.decay + self.decay).max(0.0),
+            sustain_level: (base.sustain_level + self.sustain).clamp(0.0, 1.0),
+            release: (base.release + self.release).max(ModMatrix::MIN_ENV_TIME),
+        }
+    }
+}
+
+///Modulation matrix. Connects the [ModSource]s to [ModDestination]s. The matrix is evaluated once
+/// per block for each voice. The resulting targets are smoothed per sample by the
+/// [OscillatorBank](crate::osc::OscillatorBank).
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct ModMatrix {
+    pub routings: Vec<Routing>,
+    pub lfos: [Lfo; 2],
+    pub mod_envelope: EnvelopeParams,
+}
+
+impl ModMatrix {
+    ///Maximum number of routings.
+    pub const MAX_ROUTINGS: usize = 32;
+    ///Octaves a speed destination is shifted at full amount.
+    pub const SPEED_RANGE: f32 = 4.0;
+    ///Seconds an envelope time is shifted at full amount.
+    pub const TIME_RANGE: Time = 1.0;
+    ///Smallest envelope time we allow. Otherwise we get clicking.
+    const MIN_ENV_TIME: Time = 0.0001;
+
+    ///Takes over the settings of `other`, but keeps the current LFO phases.
+    pub fn update_from(&mut self, other: ModMatrix) {
+        let phases = self.lfos.map(|l| l.phase);
+        *self = other;
+        for (lfo, phase) in self.lfos.iter_mut().zip(phases) {
+            lfo.phase = phase;
+        }
+    }
+
+    ///Advances the LFOs by `delta` seconds.
+    pub fn advance(&mut self, delta: Time) {
+        for lfo in &mut self.lfos {
+            lfo.advance(delta);
+        }
+    }
+
+    pub fn lfo_value(&self, lfo: usize) -> f32 {
+        self.lfos[lfo].value()
+    }
+
+    ///True if any routing targets a modulator's speed or range.
+    pub fn modulates_modulators(&self) -> bool {
+        self.routings.iter().any(|r| {
+            matches!(
+                r.destination,
+                ModDestination::Radius(ParentIndex::Modulator(_))
+                    | ModDestination::Speed(ParentIndex::Modulator(_))
+            )
+        })
+    }
+
+    ///Accumulates all routings based on the given source values, which are indexed by [ModSource] order.
+    pub fn evaluate(&self, sources: &[f32; ModSource::COUNT]) -> ModTargets {
+        let mut targets = ModTargets::default();
+        for routing in &self.routings {
+            let value = sources[routing.source as usize] * routing.amount;
+            match routing.destination {
+                ModDestination::Radius(ParentIndex::Primary(s)) => {
+                    if s < OscillatorBank::PRIMARY_OSC_COUNT {
+                        targets.osc.primary_volume[s] += value;
+                    }
+                }
+                ModDestination::Radius(ParentIndex::Modulator(s)) => {
+                    if s < OscillatorBank::MOD_OSC_COUNT {
+                        targets.osc.modulator_range[s] += value;
+                    }
+                }
+                ModDestination::Speed(ParentIndex::Primary(s)) => {
+                    if s < OscillatorBank::PRIMARY_OSC_COUNT {
+                        targets.osc.primary_speed[s] += value * Self::SPEED_RANGE;
+                    }
+                }
+                ModDestination::Speed(ParentIndex::Modulator(s)) => {
+                    if s < OscillatorBank::MOD_OSC_COUNT {
+                        targets.osc.modulator_speed[s] += value * Self::SPEED_RANGE;
+                    }
+                }
+                ModDestination::Attack => targets.attack += value as Time * Self::TIME_RANGE,
+                ModDestination::Decay => targets.decay += value as Time * Self::TIME_RANGE,
+                ModDestination::Sustain => targets.sustain += value,
+                ModDestination::Release => targets.release += value as Time * Self::TIME_RANGE,
+                ModDestination::Gain => targets.osc.gain += value,
+            }
+        }
+
+        targets
+    }
+}
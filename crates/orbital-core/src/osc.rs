@@ -0,0 +1,681 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    envelope::{lerp, Envelope},
+    filter::{FilterSettings, VoiceFilter},
+    osc::modulator::ParentIndex,
+    osc_array::OscVoiceState,
+    oversampling::{Oversampler, OversamplingFactor},
+    state::{ModulatorState, OrbitalProperty, PrimaryState, SolarState},
+    SampleTime, Time, ABS_BASE_FREQ,
+};
+
+use self::{
+    kernel::{Kernel, SharedModulators, VoiceModulators, VoiceRender},
+    lanes::{LaneConfig, LaneRamp, Ramp, VoiceLanes, MODULATOR_SLOTS, PRIMARY_SLOTS},
+    modulator::ModulatorOsc,
+    primary::PrimaryOsc,
+};
+
+pub mod kernel;
+mod lanes;
+pub mod modulator;
+pub mod primary;
+
+#[inline(always)]
+pub fn sigmoid(x: f32) -> f32 {
+    x / (1.0 + x * x).sqrt()
+}
+
+#[allow(dead_code)]
+pub fn mel_to_freq(mel: f32) -> f32 {
+    700.0 * (10.0f32.powf((mel + 20.0) / 2595.0) - 1.0)
+}
+
+#[allow(dead_code)]
+pub fn freq_to_mel(freq: f32) -> f32 {
+    2595.0 * (1.0 + (freq / 700.0)).log10()
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+#[cfg_attr(feature = "nih-plug", derive(nih_plug::prelude::Enum))]
+pub enum ModulationType {
+    Absolute,
+    #[default]
+    Relative,
+}
+
+impl ModulationType {
+    pub fn next(&self) -> Self {
+        match self {
+            ModulationType::Absolute => Self::Relative,
+            ModulationType::Relative => Self::Absolute,
+        }
+    }
+}
+
+/// Single oscillator state. Used to sync graphics and audio engine. The phases live in the bank's per-voice
+/// [VoiceLanes](lanes::VoiceLanes).
+#[derive(Clone, Copy)]
+pub struct Oscillator<S> {
+    //Oscillator state type
+    osc: S,
+    ///Phase offset (0..2π)
+    offset: f32,
+}
+
+impl<S: Default> Default for Oscillator<S> {
+    fn default() -> Self {
+        Oscillator {
+            osc: S::default(),
+            offset: 0.0,
+        }
+    }
+}
+
+///Per-voice offsets that are added to the bank's oscillator state. Written by the
+/// [ModMatrix](crate::mod_matrix::ModMatrix).
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+pub struct VoiceModulation {
+    pub primary_volume: [f32; OscillatorBank::PRIMARY_OSC_COUNT],
+    ///In octaves
+    pub primary_speed: [f32; OscillatorBank::PRIMARY_OSC_COUNT],
+    pub modulator_range: [f32; OscillatorBank::MOD_OSC_COUNT],
+    ///In octaves
+    pub modulator_speed: [f32; OscillatorBank::MOD_OSC_COUNT],
+    ///Offset to the voice's gain. 0.0 leaves the gain untouched.
+    pub gain: f32,
+}
+
+impl VoiceModulation {
+    fn zip_with(&mut self, other: &Self, f: impl Fn(&mut f32, f32)) {
+        for (a, b) in self.primary_volume.iter_mut().zip(other.primary_volume) {
+            f(a, b);
+        }
+        for (a, b) in self.primary_speed.iter_mut().zip(other.primary_speed) {
+            f(a, b);
+        }
+        for (a, b) in self.modulator_range.iter_mut().zip(other.modulator_range) {
+            f(a, b);
+        }
+        for (a, b) in self.modulator_speed.iter_mut().zip(other.modulator_speed) {
+            f(a, b);
+        }
+        f(&mut self.gain, other.gain);
+    }
+}
+
+///Linearly ramps a voice's modulation from its current value to a new target over one block.
+#[derive(Clone, Copy, Default)]
+struct SmoothedModulation {
+    current: VoiceModulation,
+    step: VoiceModulation,
+    ///Samples left until `current` reaches the target
+    remaining: usize,
+}
+
+impl SmoothedModulation {
+    fn set_target(&mut self, target: &VoiceModulation, samples: usize) {
+        if samples == 0 {
+            self.current = *target;
+            self.remaining = 0;
+            return;
+        }
+
+        self.step = *target;
+        self.step.zip_with(&self.current, |step, current| {
+            *step = (*step - current) / samples as f32
+        });
+        self.remaining = samples;
+    }
+
+    ///Advances the ramp by `samples` at once.
+    fn skip(&mut self, samples: usize) {
+        let n = samples.min(self.remaining);
+        if n > 0 {
+            self.current.zip_with(&self.step, |c, s| *c += s * n as f32);
+            self.remaining -= n;
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct OscillatorBank {
+    ///Stores *all* primary oscillators. The children are declared in form of indices within the osc structs.
+    primary_osc: [Oscillator<PrimaryOsc>; Self::PRIMARY_BANK_SIZE],
+    modulator_osc: [Oscillator<ModulatorOsc>; Self::MODULATOR_BANK_SIZE],
+    pub mod_ty: ModulationType,
+    pub reset_phase: bool,
+    ///Smoothed modulation matrix results per voice.
+    modulation: [SmoothedModulation; Self::VOICE_COUNT],
+    ///Oscillator configuration in simd lanes, rebuilt from the oscillators if `lanes_valid` is false.
+    lanes: LaneConfig,
+    lanes_valid: bool,
+    ///Phases and modulation state of each voice.
+    voice_lanes: [VoiceLanes; Self::VOICE_COUNT],
+    ///Modulators shared by all voices in absolute modulation mode, where they do not depend on the voice.
+    shared_lanes: VoiceLanes,
+    ///Values of the shared modulators for each step of the current block.
+    shared_values: Box<[[f32; MODULATOR_SLOTS]]>,
+    ///Envelope of the voice that is rendered, for each step of the current block.
+    envelope: Box<[f32]>,
+    ///If set, the shared modulators keep running while no voice plays and are never reset by a note.
+    pub free_running: bool,
+    ///Set if the modulation matrix routes to a modulator's speed or range. The voices then step their own
+    /// modulators in absolute modulation mode as well, since the modulators depend on the voice.
+    pub voice_modulators: bool,
+    ///Implementation the voices are rendered with. Detected from the cpu's features by default.
+    pub kernel: Kernel,
+    ///Per-voice filter after the primary accumulation.
+    pub filter: FilterSettings,
+    filters: [VoiceFilter; Self::VOICE_COUNT],
+    ///Base cutoff, ramped towards `filter.cutoff` over each block.
+    cutoff: f32,
+    ///Decimates the oversampled voice sum back to the host's sample rate.
+    decimator: Oversampler,
+    ///Base frequency of modulators in absolute modulation mode. Follows the reference pitch.
+    pub abs_base_freq: f32,
+}
+
+impl Default for OscillatorBank {
+    fn default() -> Self {
+        //pre allocating oscillator banks. But vec allows us to outgrow if neede
+        OscillatorBank {
+            primary_osc: [Oscillator::default(); Self::PRIMARY_BANK_SIZE],
+            modulator_osc: [Oscillator::default(); Self::MODULATOR_BANK_SIZE],
+            mod_ty: ModulationType::default(),
+            reset_phase: false,
+            modulation: [SmoothedModulation::default(); Self::VOICE_COUNT],
+            lanes: LaneConfig::default(),
+            lanes_valid: false,
+            voice_lanes: [VoiceLanes::default(); Self::VOICE_COUNT],
+            shared_lanes: VoiceLanes::default(),
+            shared_values: Self::default_shared_values(),
+            envelope: vec![0.0; Self::BLOCK_SIZE * Oversampler::MAX_FACTOR].into_boxed_slice(),
+            free_running: false,
+            voice_modulators: false,
+            kernel: Kernel::detect(),
+            filter: FilterSettings::default(),
+            filters: [VoiceFilter::default(); Self::VOICE_COUNT],
+            cutoff: 0.0,
+            decimator: Oversampler::default(),
+            abs_base_freq: Self::default_abs_base_freq(),
+        }
+    }
+}
+
+impl OscillatorBank {
+    ///Number of maximal active voices.
+    pub const VOICE_COUNT: usize = 10;
+    ///Number of primary oscillators per voice.
+    pub const PRIMARY_OSC_COUNT: usize = 8;
+    ///Number of modulator oscillators per voice.
+    pub const MOD_OSC_COUNT: usize = 16;
+
+    pub const PRIMARY_BANK_SIZE: usize = Self::VOICE_COUNT * Self::PRIMARY_OSC_COUNT;
+    pub const MODULATOR_BANK_SIZE: usize = Self::VOICE_COUNT * Self::MOD_OSC_COUNT;
+    ///Frequency relative to the sample rate at which oscillators start to fade out.
+    const BAND_LIMIT_START: f32 = 0.4;
+    ///Number of samples that are rendered per voice at once.
+    const BLOCK_SIZE: usize = 64;
+
+    fn default_abs_base_freq() -> f32 {
+        ABS_BASE_FREQ
+    }
+
+    fn default_shared_values() -> Box<[[f32; MODULATOR_SLOTS]]> {
+        vec![[0.0; MODULATOR_SLOTS]; Self::BLOCK_SIZE * Oversampler::MAX_FACTOR].into_boxed_slice()
+    }
+
+    pub fn on_state_change(&mut self, new: &SolarState) {
+        //nih_log!("State change");
+
+        //turn off all to not keep anything "on" by misstake.
+        self.lanes_valid = false;
+        for o in &mut self.primary_osc {
+            o.osc.is_on = false;
+        }
+
+        for o in &mut self.modulator_osc {
+            o.osc.is_on = false;
+        }
+
+        //reconifg all oscs
+        // TODO: do diff and lerp between changes, reset on type change
+
+        for pstate in &new.primary_states {
+            let PrimaryState {
+                offset,
+                state,
+                slot,
+            } = *pstate;
+            //nih_log!("  [{}]: {:?}", slot, state);
+            self.on_primary_osc_line(slot, |osc| {
+                osc.offset = offset;
+                osc.osc = state;
+            })
+        }
+
+        for pstate in &new.modulator_states {
+            let ModulatorState {
+                offset,
+                state,
+                slot,
+            } = *pstate;
+
+            //nih_log!("  [{}]: {:?}", slot, state);
+            self.on_modulator_osc_line(slot, |osc| {
+                osc.offset = offset;
+                osc.osc = state;
+            })
+        }
+    }
+
+    ///Overwrites a single property of the oscillator in `slot` for all voices. Used to apply macros on top of
+    /// the last state change.
+    pub fn set_orbital_property(
+        &mut self,
+        slot: ParentIndex,
+        property: OrbitalProperty,
+        value: f32,
+    ) {
+        match slot {
+            ParentIndex::Primary(line) => self.on_primary_osc_line(line, |osc| match property {
+                OrbitalProperty::Radius => osc.osc.volume = value,
+                OrbitalProperty::Speed => osc.osc.speed_index = value,
+                OrbitalProperty::Offset => osc.offset = value,
+            }),
+            ParentIndex::Modulator(line) => {
+                self.on_modulator_osc_line(line, |osc| match property {
+                    OrbitalProperty::Radius => osc.osc.range = value,
+                    OrbitalProperty::Speed => osc.osc.speed_index = value,
+                    OrbitalProperty::Offset => osc.offset = value,
+                })
+            }
+        }
+    }
+
+    fn on_primary_osc_line(&mut self, line: usize, f: impl Fn(&mut Oscillator<PrimaryOsc>)) {
+        if line >= Self::PRIMARY_OSC_COUNT {
+            return;
+        }
+
+        self.lanes_valid = false;
+        for vidx in 0..Self::VOICE_COUNT {
+            f(&mut self.primary_osc[Self::primary_osc_index(vidx, line)]);
+        }
+    }
+
+    fn on_modulator_osc_line(&mut self, line: usize, f: impl Fn(&mut Oscillator<ModulatorOsc>)) {
+        if line >= Self::MOD_OSC_COUNT {
+            return;
+        }
+
+        self.lanes_valid = false;
+        for vidx in 0..Self::VOICE_COUNT {
+            f(&mut self.modulator_osc[Self::modulator_osc_index(vidx, line)]);
+        }
+    }
+
+    #[inline(always)]
+    fn primary_osc_index(voice: usize, osc: usize) -> usize {
+        voice * Self::PRIMARY_OSC_COUNT + osc
+    }
+
+    #[inline(always)]
+    fn modulator_osc_index(voice: usize, osc: usize) -> usize {
+        voice * Self::MOD_OSC_COUNT + osc
+    }
+    ///Sets the modulation `target` of a voice. The current modulation is ramped to the target within
+    /// the next `samples`.
+    pub fn set_modulation_target(
+        &mut self,
+        voice: usize,
+        target: &VoiceModulation,
+        samples: usize,
+    ) {
+        self.modulation[voice].set_target(target, samples);
+    }
+
+    ///Resets the voice's modulation to `target` without smoothing. Used on note-on, so a new note
+    /// does not glide from the last note's modulation.
+    pub fn reset_modulation(&mut self, voice: usize, target: &VoiceModulation) {
+        self.modulation[voice].set_target(target, 0);
+    }
+
+    ///Sets the oversampling factor of the bank. Returns true if it changed, in which case the latency
+    /// changed as well.
+    pub fn set_oversampling(&mut self, factor: OversamplingFactor) -> bool {
+        if self.decimator.factor() == factor {
+            return false;
+        }
+        self.decimator.set_factor(factor);
+        true
+    }
+
+    ///Latency of the decimation filter in samples.
+    pub fn latency(&self) -> u32 {
+        self.decimator.decimation_latency()
+    }
+
+    ///Clears the voice's filter state, so a new note does not start with the last note's tail.
+    pub fn reset_filter(&mut self, voice: usize) {
+        self.filters[voice].reset();
+    }
+
+    pub fn reset_voice(&mut self, voice_idx: usize) {
+        //nih_log!("Resetting {}", voice_idx);
+        self.voice_lanes[voice_idx] = VoiceLanes::default();
+    }
+
+    ///Resets the shared modulators, unless they are free running.
+    pub fn reset_shared_modulators(&mut self) {
+        if !self.free_running {
+            self.shared_lanes = VoiceLanes::default();
+        }
+    }
+
+    ///True if the voices read their modulation from the shared modulators. In absolute modulation mode the
+    /// modulators' frequencies do not depend on the voice, so they are stepped once for the whole bank. Unless
+    /// the voices modulate them, see `voice_modulators`.
+    fn shares_modulators(&self) -> bool {
+        self.mod_ty == ModulationType::Absolute && !self.voice_modulators
+    }
+
+    ///Rebuilds the simd lanes from the oscillators, if the configuration changed since the last rebuild.
+    fn update_lanes(&mut self) {
+        if self.lanes_valid {
+            return;
+        }
+        //All voices share the configuration, so the first voice's oscillators are representative.
+        let lanes = LaneConfig::build(
+            &self.primary_osc[0..Self::PRIMARY_OSC_COUNT],
+            &self.modulator_osc[0..Self::MOD_OSC_COUNT],
+        );
+        //Oscillators moved to other lanes, keep their phases
+        if lanes.packing_changed(&self.lanes) {
+            for voice in &mut self.voice_lanes {
+                voice.repack(&self.lanes, &lanes);
+            }
+            self.shared_lanes.repack(&self.lanes, &lanes);
+        }
+        self.lanes = lanes;
+        self.lanes_valid = true;
+    }
+
+    ///Frequencies and amplitudes of all active oscillators of a voice for the given modulation.
+    fn voice_targets(
+        &self,
+        base_frequency: f32,
+        modulator_base: f32,
+        modulation: &VoiceModulation,
+    ) -> VoiceTargets {
+        let cfg = &self.lanes;
+        let mut targets = VoiceTargets::default();
+        for idx in 0..cfg.primary_count {
+            let slot = cfg.primary_slot[idx];
+            targets.primary_freq[idx] = (base_frequency
+                * 2.0f32.powf(cfg.primary_speed[idx] + modulation.primary_speed[slot]))
+            .max(0.0);
+            targets.primary_volume[idx] =
+                (cfg.primary_volume[idx] + modulation.primary_volume[slot]).clamp(0.0, 1.0);
+        }
+        for idx in 0..cfg.modulator_count {
+            let slot = cfg.modulator_slot[idx];
+            targets.modulator_freq[idx] = (modulator_base
+                * 2.0f32.powf(cfg.modulator_speed[idx] + modulation.modulator_speed[slot]))
+            .max(0.0);
+            targets.modulator_range[idx] =
+                (cfg.modulator_range[idx] + modulation.modulator_range[slot]).clamp(0.0, 1.0);
+        }
+        targets.gain = (1.0 + modulation.gain).max(0.0);
+        targets
+    }
+
+    ///Renders `samples` of a voice and adds them, scaled by the voice's envelope, to `out`. `out` holds
+    /// `os_factor` sub-steps per sample.
+    ///
+    /// Frequencies, amplitudes and cutoff are evaluated at the start and end of the block and interpolated in
+    /// between. The envelope is evaluated at each sample, so notes start and end at their event's sample.
+    fn render_voice(
+        &mut self,
+        voice: usize,
+        state: &OscVoiceState,
+        block: VoiceBlock,
+        out: &mut [f32],
+    ) {
+        #[cfg(feature = "profile")]
+        puffin::profile_function!();
+
+        let VoiceBlock {
+            start,
+            samples,
+            delta_sec,
+            os_factor,
+            cutoff,
+        } = block;
+        let steps = samples * os_factor;
+        let end = start + samples as SampleTime;
+
+        //for absolute modulation we use the reference pitch for modulation offset, which is the same for all.
+        // At relative we use the voice's base frequency and modulate that relatively.
+        let modulator_base = match self.mod_ty {
+            ModulationType::Absolute => self.abs_base_freq,
+            ModulationType::Relative => state.freq,
+        };
+        let mod_start = self.modulation[voice].current;
+        self.modulation[voice].skip(samples);
+        let mod_end = self.modulation[voice].current;
+
+        //Voices that are silent for the whole block, or have nothing to play are skipped
+        let audible =
+            Self::sample_envelope(&state.env, start, os_factor, &mut self.envelope[0..steps]);
+        if !audible || self.lanes.primary_count == 0 {
+            return;
+        }
+
+        let from = self.voice_targets(state.freq, modulator_base, &mod_start);
+        let to = self.voice_targets(state.freq, modulator_base, &mod_end);
+
+        //the cutoff is ramped exponentially
+        let (voice_cutoff, cutoff_ratio) = if self.filter.enabled {
+            let cutoff_start = self.filter.voice_cutoff(
+                cutoff.0,
+                state.freq,
+                state.velocity,
+                state.filter_env.sample(start),
+            );
+            let cutoff_end = self.filter.voice_cutoff(
+                cutoff.1,
+                state.freq,
+                state.velocity,
+                state.filter_env.sample(end),
+            );
+            (
+                cutoff_start,
+                (cutoff_end / cutoff_start).powf(1.0 / steps as f32),
+            )
+        } else {
+            (cutoff.0, 1.0)
+        };
+
+        let (primaries, modulators) = (self.lanes.primary_count, self.lanes.modulator_count);
+        let mut own_ramps;
+        let modulators = if self.shares_modulators() {
+            VoiceModulators::Shared(&self.shared_values[0..steps])
+        } else {
+            own_ramps = (
+                LaneRamp::new(from.modulator_freq, to.modulator_freq, steps, modulators),
+                LaneRamp::new(from.modulator_range, to.modulator_range, steps, modulators),
+            );
+            VoiceModulators::Own {
+                freq: &mut own_ramps.0,
+                range: &mut own_ramps.1,
+            }
+        };
+        let mut render = VoiceRender {
+            cfg: &self.lanes,
+            lanes: &mut self.voice_lanes[voice],
+            filter: &mut self.filters[voice],
+            settings: &self.filter,
+            primary_freq: LaneRamp::new(from.primary_freq, to.primary_freq, steps, primaries),
+            primary_volume: LaneRamp::new(from.primary_volume, to.primary_volume, steps, primaries),
+            modulators,
+            gain: Ramp::new(from.gain, to.gain, steps),
+            envelope: &self.envelope[0..steps],
+            cutoff: voice_cutoff,
+            cutoff_ratio,
+            step_delta: delta_sec as f32 / os_factor as f32,
+        };
+        self.kernel.render(&mut render, &mut out[0..steps]);
+    }
+
+    ///Samples `env` for each step of a block starting at sample `start`. Sub-steps are interpolated between
+    /// the samples. Returns false if the envelope is silent for the whole block.
+    fn sample_envelope(
+        env: &Envelope,
+        start: SampleTime,
+        os_factor: usize,
+        out: &mut [f32],
+    ) -> bool {
+        let mut audible = false;
+        let mut from = env.sample(start);
+        for (idx, steps) in out.chunks_mut(os_factor).enumerate() {
+            let to = env.sample(start + idx as SampleTime + 1);
+            for (sub, value) in steps.iter_mut().enumerate() {
+                *value = lerp(from, to, sub as f32 / os_factor as f32);
+            }
+            audible |= from > 0.0 || to > 0.0;
+            from = to;
+        }
+        audible
+    }
+
+    ///Steps the shared modulators over a block, before the voices read their values.
+    fn render_shared_modulators(&mut self, block: VoiceBlock) {
+        let steps = block.samples * block.os_factor;
+        //Only used while no voice modulates the modulators' speed or range
+        let targets = self.voice_targets(0.0, self.abs_base_freq, &VoiceModulation::default());
+        let count = self.lanes.modulator_count;
+        let mut modulators = SharedModulators {
+            cfg: &self.lanes,
+            lanes: &mut self.shared_lanes,
+            freq: LaneRamp::new(targets.modulator_freq, targets.modulator_freq, steps, count),
+            range: LaneRamp::new(
+                targets.modulator_range,
+                targets.modulator_range,
+                steps,
+                count,
+            ),
+            step_delta: block.delta_sec as f32 / block.os_factor as f32,
+        };
+        self.kernel
+            .render_shared(&mut modulators, &mut self.shared_values[0..steps]);
+    }
+
+    ///Renders the voices into `out`, starting at sample `buffer_start`.
+    pub fn render(
+        &mut self,
+        voices: &[OscVoiceState; OscillatorBank::VOICE_COUNT],
+        out: &mut [f32],
+        sample_rate: f32,
+        buffer_start: SampleTime,
+    ) {
+        //PERFORMANCE: Measured by `benches/oscillator_bank.rs` for varying voice and orbital counts, buffer
+        // sizes and modulation types. Run `cargo bench -p orbital-core` before and after touching this path.
+
+        #[cfg(feature = "profile")]
+        {
+            let num_voices = voices
+                .iter()
+                .fold(0, |f, v| if !v.state.is_off() { f + 1 } else { f });
+
+            puffin::profile_function!(format!(
+                "OSC-Bank[{} @ {}] process Max: {:.2}ms",
+                num_voices,
+                out.len(),
+                (out.len() as f64 / sample_rate as f64) * 1000.0
+            ));
+        }
+
+        //NOTE: The voice composition curve is applied by the output stage
+        let delta_sec = (1.0 / sample_rate) as Time;
+        self.update_lanes();
+        //Free running modulators are stepped even if no voice plays
+        let step_shared = self.shares_modulators()
+            && (self.free_running || voices.iter().any(|v| !v.state.is_off()));
+
+        //ramp the cutoff exponentially, so automation does not zipper
+        if self.cutoff <= 0.0 {
+            self.cutoff = self.filter.cutoff;
+        }
+        let cutoff_ratio = (self.filter.cutoff / self.cutoff).powf(1.0 / out.len().max(1) as f32);
+
+        //Each output sample is rendered as `os_factor` sub-steps and decimated afterwards.
+        let os_factor = self.decimator.factor().factor();
+        let mut block = [0.0; Self::BLOCK_SIZE * Oversampler::MAX_FACTOR];
+
+        for (block_idx, out_block) in out.chunks_mut(Self::BLOCK_SIZE).enumerate() {
+            let block_len = out_block.len();
+            let cutoff_start = self.cutoff;
+            self.cutoff *= cutoff_ratio.powi(block_len as i32);
+            let voice_block = VoiceBlock {
+                start: buffer_start + (block_idx * Self::BLOCK_SIZE) as SampleTime,
+                samples: block_len,
+                delta_sec,
+                os_factor,
+                cutoff: (cutoff_start, self.cutoff),
+            };
+
+            if step_shared {
+                self.render_shared_modulators(voice_block);
+            }
+
+            let sub_samples = &mut block[0..block_len * os_factor];
+            sub_samples.fill(0.0);
+            for (vidx, voice) in voices.iter().enumerate() {
+                if voice.state.is_off() {
+                    continue;
+                }
+                self.render_voice(vidx, voice, voice_block, sub_samples);
+            }
+
+            for (sample, sub) in out_block.iter_mut().zip(sub_samples.chunks(os_factor)) {
+                *sample = self.decimator.decimate(sub);
+            }
+        }
+    }
+}
+
+///Frequencies and amplitudes of a voice's packed oscillators at one point in time.
+#[derive(Clone, Copy)]
+struct VoiceTargets {
+    primary_freq: [f32; PRIMARY_SLOTS],
+    primary_volume: [f32; PRIMARY_SLOTS],
+    modulator_freq: [f32; MODULATOR_SLOTS],
+    modulator_range: [f32; MODULATOR_SLOTS],
+    gain: f32,
+}
+
+impl Default for VoiceTargets {
+    fn default() -> Self {
+        VoiceTargets {
+            primary_freq: [0.0; PRIMARY_SLOTS],
+            primary_volume: [0.0; PRIMARY_SLOTS],
+            modulator_freq: [0.0; MODULATOR_SLOTS],
+            modulator_range: [0.0; MODULATOR_SLOTS],
+            gain: 1.0,
+        }
+    }
+}
+
+///Timing of a block that is rendered by [OscillatorBank::render_voice].
+#[derive(Clone, Copy)]
+struct VoiceBlock {
+    start: SampleTime,
+    samples: usize,
+    delta_sec: Time,
+    os_factor: usize,
+    ///Base cutoff at the start and end of the block
+    cutoff: (f32, f32),
+}
@@ -0,0 +1,776 @@
+#[cfg(feature = "simd")]
+use std::simd::{Simd, SimdElement};
+
+use crate::{
+    filter::{FilterSettings, VoiceFilter},
+    TWOPI,
+};
+
+use super::{
+    lanes::{
+        LaneConfig, LaneRamp, Phase, Ramp, VoiceLanes, MODULATOR_SLOTS, PHASE_SCALE, PRIMARY_SLOTS,
+    },
+    modulator::ParentIndex,
+    OscillatorBank,
+};
+
+///Implementation the oscillator bank is stepped with. All kernels produce the same output within float
+/// precision, the wider ones just process more oscillators per instruction.
+///
+/// The simd kernels need the `simd` feature, which requires a nightly toolchain.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kernel {
+    ///Plain float math, one oscillator at a time.
+    Scalar,
+    #[cfg(feature = "simd")]
+    F32x4,
+    ///Uses AVX2 if the cpu supports it.
+    #[cfg(feature = "simd")]
+    F32x8,
+    ///Uses AVX-512 if the cpu supports it.
+    #[cfg(feature = "simd")]
+    F32x16,
+}
+
+impl Default for Kernel {
+    fn default() -> Self {
+        Kernel::detect()
+    }
+}
+
+impl Kernel {
+    ///Picks the widest kernel the cpu has native support for.
+    #[cfg(feature = "simd")]
+    pub fn detect() -> Self {
+        #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+        {
+            if is_x86_feature_detected!("avx512f") {
+                return Kernel::F32x16;
+            }
+            if is_x86_feature_detected!("avx2") && is_x86_feature_detected!("fma") {
+                return Kernel::F32x8;
+            }
+        }
+        Kernel::F32x4
+    }
+
+    #[cfg(not(feature = "simd"))]
+    pub fn detect() -> Self {
+        Kernel::Scalar
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Kernel::Scalar => "Scalar",
+            #[cfg(feature = "simd")]
+            Kernel::F32x4 => "f32x4",
+            #[cfg(feature = "simd")]
+            Kernel::F32x8 => "f32x8",
+            #[cfg(feature = "simd")]
+            Kernel::F32x16 => "f32x16",
+        }
+    }
+
+    ///Renders `out.len()` steps of a voice, adding them to `out`.
+    pub fn render(self, voice: &mut VoiceRender, out: &mut [f32]) {
+        match self {
+            Kernel::Scalar => render_scalar(voice, out),
+            #[cfg(feature = "simd")]
+            Kernel::F32x4 => render_simd::<4>(voice, out),
+            #[cfg(feature = "simd")]
+            Kernel::F32x8 => {
+                #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+                if is_x86_feature_detected!("avx2") && is_x86_feature_detected!("fma") {
+                    //Safety: the features were just checked
+                    unsafe { render_avx2(voice, out) };
+                    return;
+                }
+                render_simd::<8>(voice, out)
+            }
+            #[cfg(feature = "simd")]
+            Kernel::F32x16 => {
+                #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+                if is_x86_feature_detected!("avx512f") {
+                    //Safety: the feature was just checked
+                    unsafe { render_avx512(voice, out) };
+                    return;
+                }
+                render_simd::<16>(voice, out)
+            }
+        }
+    }
+
+    ///Steps the shared modulators `out.len()` times, writing the modulators' values of each step to `out`.
+    pub fn render_shared(
+        self,
+        modulators: &mut SharedModulators,
+        out: &mut [[f32; MODULATOR_SLOTS]],
+    ) {
+        match self {
+            Kernel::Scalar => modulators.step(out, modulators_scalar),
+            #[cfg(feature = "simd")]
+            Kernel::F32x4 => modulators.step(out, modulators_simd::<4>),
+            #[cfg(feature = "simd")]
+            Kernel::F32x8 => {
+                #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+                if is_x86_feature_detected!("avx2") && is_x86_feature_detected!("fma") {
+                    //Safety: the features were just checked
+                    unsafe { shared_avx2(modulators, out) };
+                    return;
+                }
+                modulators.step(out, modulators_simd::<8>)
+            }
+            #[cfg(feature = "simd")]
+            Kernel::F32x16 => {
+                #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+                if is_x86_feature_detected!("avx512f") {
+                    //Safety: the feature was just checked
+                    unsafe { shared_avx512(modulators, out) };
+                    return;
+                }
+                modulators.step(out, modulators_simd::<16>)
+            }
+        }
+    }
+}
+
+///Where a voice's modulation comes from.
+pub enum VoiceModulators<'a> {
+    ///The voice steps its own modulators with these frequencies and ranges. Borrowed, so the enum stays
+    /// small for shared modulators.
+    Own {
+        freq: &'a mut LaneRamp<MODULATOR_SLOTS>,
+        range: &'a mut LaneRamp<MODULATOR_SLOTS>,
+    },
+    ///Values of the bank's shared modulators, one entry per step.
+    Shared(&'a [[f32; MODULATOR_SLOTS]]),
+}
+
+///State of one voice while rendering a block. The ramps are advanced once per step.
+pub struct VoiceRender<'a> {
+    pub cfg: &'a LaneConfig,
+    pub lanes: &'a mut VoiceLanes,
+    pub filter: &'a mut VoiceFilter,
+    pub settings: &'a FilterSettings,
+    pub primary_freq: LaneRamp<PRIMARY_SLOTS>,
+    pub primary_volume: LaneRamp<PRIMARY_SLOTS>,
+    pub modulators: VoiceModulators<'a>,
+    pub gain: Ramp,
+    ///Envelope of each step.
+    pub envelope: &'a [f32],
+    ///Cutoff of the voice's filter, multiplied by `cutoff_ratio` each step.
+    pub cutoff: f32,
+    pub cutoff_ratio: f32,
+    ///Time per step in seconds
+    pub step_delta: f32,
+}
+
+impl<'a> VoiceRender<'a> {
+    ///Modulator values of `step`. The voice's own modulators are stepped with `step_modulators`.
+    #[inline(always)]
+    fn modulator_values(
+        &mut self,
+        step: usize,
+        step_modulators: impl Fn(
+            &LaneConfig,
+            &mut VoiceLanes,
+            &[f32; MODULATOR_SLOTS],
+            &[f32; MODULATOR_SLOTS],
+            f32,
+        ) -> [f32; MODULATOR_SLOTS],
+    ) -> [f32; MODULATOR_SLOTS] {
+        match &mut self.modulators {
+            VoiceModulators::Own { freq, range } => {
+                let values = step_modulators(
+                    self.cfg,
+                    &mut *self.lanes,
+                    &freq.value,
+                    &range.value,
+                    self.step_delta,
+                );
+                freq.advance();
+                range.advance();
+                values
+            }
+            VoiceModulators::Shared(values) => values[step],
+        }
+    }
+
+    ///Applies gain, filter and envelope of `step` to the primaries' sum and advances all ramps.
+    #[inline(always)]
+    fn finish_step(&mut self, step: usize, accum: f32) -> f32 {
+        let mut sample = accum * self.gain.value;
+        if self.settings.enabled {
+            sample = self
+                .filter
+                .process(self.settings, self.cutoff, 1.0 / self.step_delta, sample);
+        }
+        sample *= self.envelope[step];
+
+        self.primary_freq.advance();
+        self.primary_volume.advance();
+        self.gain.advance();
+        self.cutoff *= self.cutoff_ratio;
+
+        sample
+    }
+}
+
+///Modulators that are shared by all voices. Only the modulator state of `lanes` is used.
+pub struct SharedModulators<'a> {
+    pub cfg: &'a LaneConfig,
+    pub lanes: &'a mut VoiceLanes,
+    pub freq: LaneRamp<MODULATOR_SLOTS>,
+    pub range: LaneRamp<MODULATOR_SLOTS>,
+    ///Time per step in seconds
+    pub step_delta: f32,
+}
+
+impl<'a> SharedModulators<'a> {
+    #[inline(always)]
+    fn step(
+        &mut self,
+        out: &mut [[f32; MODULATOR_SLOTS]],
+        step_modulators: impl Fn(
+            &LaneConfig,
+            &mut VoiceLanes,
+            &[f32; MODULATOR_SLOTS],
+            &[f32; MODULATOR_SLOTS],
+            f32,
+        ) -> [f32; MODULATOR_SLOTS],
+    ) {
+        //The primaries are modulated by each voice from the written values
+        let mut primary_mod = [0.0; PRIMARY_SLOTS];
+        for values in out.iter_mut() {
+            *values = step_modulators(
+                self.cfg,
+                &mut *self.lanes,
+                &self.freq.value,
+                &self.range.value,
+                self.step_delta,
+            );
+            scatter(
+                self.cfg,
+                values,
+                &mut self.lanes.modulator_mod,
+                &mut primary_mod,
+            );
+            self.freq.advance();
+            self.range.advance();
+        }
+    }
+}
+
+///Adds the modulation `values` of all modulators to their parents. `modulator_mod` is cleared first, since
+/// it carries over to the next step.
+#[inline(always)]
+fn scatter(
+    cfg: &LaneConfig,
+    values: &[f32; MODULATOR_SLOTS],
+    modulator_mod: &mut [f32; MODULATOR_SLOTS],
+    primary_mod: &mut [f32; PRIMARY_SLOTS],
+) {
+    *modulator_mod = [0.0; MODULATOR_SLOTS];
+    for (idx, parent) in &cfg.modulator_links[0..cfg.modulator_link_count] {
+        match parent {
+            ParentIndex::Primary(p) => primary_mod[*p] += values[*idx],
+            ParentIndex::Modulator(p) => modulator_mod[*p] += values[*idx],
+        }
+    }
+}
+
+///Gain that fades an oscillator out as its frequency approaches Nyquist.
+/// 1.0 below `BAND_LIMIT_START` times the sample rate, 0.0 at and above Nyquist.
+#[inline(always)]
+fn band_limit(freq: f32, multiplier: f32, d_sec: f32) -> f32 {
+    //cycles per sample, 0.5 is Nyquist
+    let ratio = freq * multiplier * d_sec;
+    ((0.5 - ratio) / (0.5 - OscillatorBank::BAND_LIMIT_START)).clamp(0.0, 1.0)
+}
+
+///Fixed-point increment of `cycles`. Whole cycles are dropped, since the phase wraps anyways.
+#[inline(always)]
+fn phase_increment(cycles: f32) -> Phase {
+    (cycles.fract() * PHASE_SCALE) as Phase
+}
+
+#[inline(always)]
+fn phase_step(freq: f32, multiplier: f32, phase: Phase, d_sec: f32) -> Phase {
+    phase.wrapping_add(phase_increment(d_sec * freq * multiplier))
+}
+
+#[inline(always)]
+fn radians(phase: Phase) -> f32 {
+    phase as f32 * (TWOPI / PHASE_SCALE)
+}
+
+///Phase steps the modulators based on the modulation their children wrote in the last step. Returns the
+/// modulation value of each modulator.
+#[allow(clippy::needless_range_loop)]
+#[inline(always)]
+fn modulators_scalar(
+    cfg: &LaneConfig,
+    lanes: &mut VoiceLanes,
+    freq: &[f32; MODULATOR_SLOTS],
+    range: &[f32; MODULATOR_SLOTS],
+    d_sec: f32,
+) -> [f32; MODULATOR_SLOTS] {
+    let mut values = [0.0; MODULATOR_SLOTS];
+    for idx in 0..cfg.modulator_count {
+        let multiplier = lanes.modulator_mod[idx] * cfg.modulator_mod.inv_children[idx]
+            + cfg.modulator_mod.identity[idx];
+        let phase = phase_step(freq[idx], multiplier, lanes.modulator_phase[idx], d_sec);
+        lanes.modulator_phase[idx] = phase;
+        let range = range[idx] * band_limit(freq[idx], multiplier, d_sec);
+        values[idx] = 1.0 + (radians(phase) + cfg.modulator_offset[idx]).cos() * range;
+    }
+    values
+}
+
+#[allow(clippy::needless_range_loop)]
+fn render_scalar(r: &mut VoiceRender, out: &mut [f32]) {
+    let cfg = r.cfg;
+    let d_sec = r.step_delta;
+    for (step, sub) in out.iter_mut().enumerate() {
+        let values = r.modulator_values(step, modulators_scalar);
+        let mut primary_mod = [0.0; PRIMARY_SLOTS];
+        scatter(cfg, &values, &mut r.lanes.modulator_mod, &mut primary_mod);
+
+        let mut accum = 0.0;
+        for idx in 0..cfg.primary_count {
+            let multiplier = primary_mod[idx] * cfg.primary_mod.inv_children[idx]
+                + cfg.primary_mod.identity[idx];
+            let freq = r.primary_freq.value[idx];
+            let phase = phase_step(freq, multiplier, r.lanes.primary_phase[idx], d_sec);
+            r.lanes.primary_phase[idx] = phase;
+            let volume = r.primary_volume.value[idx]
+                * band_limit(freq, multiplier, d_sec)
+                * cfg.primary_norm[idx];
+            accum += (radians(phase) + cfg.primary_offset[idx]).cos() * volume;
+        }
+
+        *sub += r.finish_step(step, accum);
+    }
+}
+
+#[cfg(feature = "simd")]
+#[inline(always)]
+fn load<T: SimdElement, const W: usize>(values: &[T], idx: usize) -> Simd<T, W> {
+    Simd::from_slice(&values[idx..idx + W])
+}
+
+#[cfg(feature = "simd")]
+#[inline(always)]
+fn simd_band_limit<const W: usize>(
+    freq: Simd<f32, W>,
+    multiplier: Simd<f32, W>,
+    d_sec: f32,
+) -> Simd<f32, W> {
+    let ratio = freq * multiplier * Simd::splat(d_sec);
+    let mut fade = (Simd::splat(0.5) - ratio) / Simd::splat(0.5 - OscillatorBank::BAND_LIMIT_START);
+    for i in 0..W {
+        fade[i] = fade[i].clamp(0.0, 1.0);
+    }
+    fade
+}
+
+#[cfg(feature = "simd")]
+#[inline(always)]
+fn simd_phase_step<const W: usize>(
+    freq: Simd<f32, W>,
+    multiplier: Simd<f32, W>,
+    phase: Simd<Phase, W>,
+    d_sec: f32,
+) -> Simd<Phase, W> {
+    let cycles = (Simd::splat(d_sec) * freq * multiplier).to_array();
+    let mut increment = [0; W];
+    for i in 0..W {
+        increment[i] = phase_increment(cycles[i]);
+    }
+    //integer simd addition wraps
+    phase + Simd::from_array(increment)
+}
+
+#[cfg(feature = "simd")]
+#[inline(always)]
+fn simd_radians<const W: usize>(phase: Simd<Phase, W>) -> Simd<f32, W> {
+    let phase = phase.to_array();
+    let mut rad = [0.0; W];
+    for i in 0..W {
+        rad[i] = radians(phase[i]);
+    }
+    Simd::from_array(rad)
+}
+
+#[cfg(feature = "simd")]
+///Same as [modulators_scalar], but `W` modulators at a time.
+#[inline(always)]
+fn modulators_simd<const W: usize>(
+    cfg: &LaneConfig,
+    lanes: &mut VoiceLanes,
+    freq: &[f32; MODULATOR_SLOTS],
+    range: &[f32; MODULATOR_SLOTS],
+    d_sec: f32,
+) -> [f32; MODULATOR_SLOTS] {
+    let one = Simd::<f32, W>::splat(1.0);
+    let mut values = [0.0; MODULATOR_SLOTS];
+    let mut idx = 0;
+    while idx < cfg.modulator_count {
+        let multiplier = load::<f32, W>(&lanes.modulator_mod, idx)
+            * load(&cfg.modulator_mod.inv_children, idx)
+            + load(&cfg.modulator_mod.identity, idx);
+        let freq = load(freq, idx);
+        let phase = simd_phase_step(freq, multiplier, load(&lanes.modulator_phase, idx), d_sec);
+        phase.copy_to_slice(&mut lanes.modulator_phase[idx..idx + W]);
+        let range = load(range, idx) * simd_band_limit(freq, multiplier, d_sec);
+        (one + sleef::f32x::cos_u10(simd_radians(phase) + load(&cfg.modulator_offset, idx))
+            * range)
+            .copy_to_slice(&mut values[idx..idx + W]);
+        idx += W;
+    }
+    values
+}
+
+#[cfg(feature = "simd")]
+///Same as [render_scalar], but `W` oscillators at a time.
+#[inline(always)]
+fn render_simd<const W: usize>(r: &mut VoiceRender, out: &mut [f32]) {
+    let cfg = r.cfg;
+    let d_sec = r.step_delta;
+    for (step, sub) in out.iter_mut().enumerate() {
+        let values = r.modulator_values(step, modulators_simd::<W>);
+        let mut primary_mod = [0.0; PRIMARY_SLOTS];
+        scatter(cfg, &values, &mut r.lanes.modulator_mod, &mut primary_mod);
+
+        let mut accum = 0.0;
+        let mut idx = 0;
+        while idx < cfg.primary_count {
+            let multiplier = load::<f32, W>(&primary_mod, idx)
+                * load(&cfg.primary_mod.inv_children, idx)
+                + load(&cfg.primary_mod.identity, idx);
+            let freq = load(&r.primary_freq.value, idx);
+            let phase = simd_phase_step(freq, multiplier, load(&r.lanes.primary_phase, idx), d_sec);
+            phase.copy_to_slice(&mut r.lanes.primary_phase[idx..idx + W]);
+            let volume = load(&r.primary_volume.value, idx)
+                * simd_band_limit(freq, multiplier, d_sec)
+                * load(&cfg.primary_norm, idx);
+            let samples =
+                sleef::f32x::cos_u10(simd_radians(phase) + load(&cfg.primary_offset, idx)) * volume;
+            accum += samples.to_array().iter().sum::<f32>();
+            idx += W;
+        }
+
+        *sub += r.finish_step(step, accum);
+    }
+}
+
+#[cfg(all(feature = "simd", any(target_arch = "x86", target_arch = "x86_64")))]
+#[target_feature(enable = "avx2,fma")]
+unsafe fn render_avx2(r: &mut VoiceRender, out: &mut [f32]) {
+    render_simd::<8>(r, out)
+}
+
+#[cfg(all(feature = "simd", any(target_arch = "x86", target_arch = "x86_64")))]
+#[target_feature(enable = "avx512f")]
+unsafe fn render_avx512(r: &mut VoiceRender, out: &mut [f32]) {
+    render_simd::<16>(r, out)
+}
+
+#[cfg(all(feature = "simd", any(target_arch = "x86", target_arch = "x86_64")))]
+#[target_feature(enable = "avx2,fma")]
+unsafe fn shared_avx2(m: &mut SharedModulators, out: &mut [[f32; MODULATOR_SLOTS]]) {
+    m.step(out, modulators_simd::<8>)
+}
+
+#[cfg(all(feature = "simd", any(target_arch = "x86", target_arch = "x86_64")))]
+#[target_feature(enable = "avx512f")]
+unsafe fn shared_avx512(m: &mut SharedModulators, out: &mut [[f32; MODULATOR_SLOTS]]) {
+    m.step(out, modulators_simd::<16>)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Kernel;
+    use crate::{
+        envelope::EnvelopeParams,
+        osc::{
+            modulator::{ModulatorOsc, ParentIndex},
+            primary::PrimaryOsc,
+            ModulationType, OscillatorBank,
+        },
+        osc_array::{OscVoiceState, VoiceState},
+        oversampling::OversamplingFactor,
+        state::{ModulatorState, PrimaryState, SolarState},
+        SampleTime,
+    };
+    use std::f64::consts::TAU;
+
+    const SAMPLE_RATE: f32 = 48000.0;
+    const TOLERANCE: f32 = 1e-3;
+    const SAMPLES: usize = 4000;
+
+    ///Five primaries, modulated by a mix of direct and chained modulators.
+    fn test_state() -> SolarState {
+        let primary_states = (0..5)
+            .map(|slot| PrimaryState {
+                offset: slot as f32 * 0.3,
+                state: PrimaryOsc {
+                    speed_index: slot as f32 * 0.5 - 1.0,
+                    volume: 0.8,
+                    is_on: true,
+                },
+                slot,
+            })
+            .collect();
+        let modulator_states = (0..11)
+            .map(|slot| ModulatorState {
+                offset: slot as f32 * 0.1,
+                state: ModulatorOsc {
+                    parent_osc_slot: if slot < 6 {
+                        ParentIndex::Primary(slot % 5)
+                    } else {
+                        ParentIndex::Modulator(slot - 6)
+                    },
+                    is_on: true,
+                    range: 0.3,
+                    speed_index: slot as f32 * 0.25 - 1.0,
+                },
+                slot,
+            })
+            .collect();
+        SolarState {
+            primary_states,
+            modulator_states,
+        }
+    }
+
+    ///Three voices, pressed at the first sample.
+    fn test_voices() -> [OscVoiceState; OscillatorBank::VOICE_COUNT] {
+        let mut voices = [OscVoiceState::default(); OscillatorBank::VOICE_COUNT];
+        for (idx, voice) in voices.iter_mut().take(3).enumerate() {
+            voice.state = VoiceState::On;
+            voice.freq = 220.0 * (idx + 1) as f32;
+            voice.velocity = 1.0;
+            voice.env.on_press(0, SAMPLE_RATE);
+            voice.filter_env.on_press(0, SAMPLE_RATE);
+        }
+        voices
+    }
+
+    fn render(kernel: Kernel, setup: &impl Fn(&mut OscillatorBank)) -> Vec<f32> {
+        let mut bank = OscillatorBank {
+            kernel,
+            ..Default::default()
+        };
+        setup(&mut bank);
+        bank.on_state_change(&test_state());
+        let voices = test_voices();
+
+        //Render in uneven buffers, so blocks are split at different positions
+        let mut out = vec![0.0; SAMPLES];
+        let mut time = 0;
+        for chunk in out.chunks_mut(333) {
+            bank.render(&voices, chunk, SAMPLE_RATE, time);
+            time += chunk.len() as SampleTime;
+        }
+        out
+    }
+
+    ///Gain of an oscillator at `cycles` per sample, see [band_limit](super::band_limit).
+    fn reference_band_limit(cycles: f64) -> f64 {
+        ((0.5 - cycles) / (0.5 - OscillatorBank::BAND_LIMIT_START as f64)).clamp(0.0, 1.0)
+    }
+
+    ///Frequency multiplier of `parent`: the mean of its modulators' `values`, 1.0 without modulators.
+    fn reference_multiplier(state: &SolarState, parent: ParentIndex, values: &[f64]) -> f64 {
+        let children: Vec<f64> = state
+            .modulator_states
+            .iter()
+            .zip(values)
+            .filter(|(m, _)| m.state.parent_osc_slot == parent)
+            .map(|(_, v)| *v)
+            .collect();
+        if children.is_empty() {
+            1.0
+        } else {
+            children.iter().sum::<f64>() / children.len() as f64
+        }
+    }
+
+    ///The oscillator model written down directly: one sample and one orbital after the other, with float
+    /// phases and without lanes or blocks. Unmodulated and without filter or oversampling.
+    fn reference(mod_ty: ModulationType) -> Vec<f32> {
+        let state = test_state();
+        let d_sec = 1.0 / SAMPLE_RATE as f64;
+        let mut out = vec![0.0; SAMPLES];
+        for voice in test_voices().iter().filter(|v| !v.state.is_off()) {
+            let voice_freq = voice.freq as f64;
+            let modulator_base = match mod_ty {
+                ModulationType::Absolute => OscillatorBank::default_abs_base_freq() as f64,
+                ModulationType::Relative => voice_freq,
+            };
+            let mut primary_phase = vec![0.0f64; state.primary_states.len()];
+            let mut modulator_phase = vec![0.0f64; state.modulator_states.len()];
+            //Before the first sample no modulator has written its value, so modulated modulators stand still
+            let mut values = vec![0.0f64; state.modulator_states.len()];
+
+            for (at, out) in out.iter_mut().enumerate() {
+                //Modulators are modulated by their children's values of the last sample
+                let last = values.clone();
+                for (idx, m) in state.modulator_states.iter().enumerate() {
+                    let multiplier =
+                        reference_multiplier(&state, ParentIndex::Modulator(m.slot), &last);
+                    let cycles = modulator_base
+                        * 2.0f64.powf(m.state.speed_index as f64)
+                        * multiplier
+                        * d_sec;
+                    modulator_phase[idx] = (modulator_phase[idx] + cycles).rem_euclid(1.0);
+                    let range = m.state.range as f64 * reference_band_limit(cycles);
+                    values[idx] =
+                        1.0 + (modulator_phase[idx] * TAU + m.offset as f64).cos() * range;
+                }
+
+                let mut sample = 0.0;
+                for (idx, p) in state.primary_states.iter().enumerate() {
+                    let multiplier =
+                        reference_multiplier(&state, ParentIndex::Primary(p.slot), &values);
+                    let cycles =
+                        voice_freq * 2.0f64.powf(p.state.speed_index as f64) * multiplier * d_sec;
+                    primary_phase[idx] = (primary_phase[idx] + cycles).rem_euclid(1.0);
+                    //Each group of four slots is normalized by the number of orbitals in it
+                    let group = state
+                        .primary_states
+                        .iter()
+                        .filter(|other| other.slot / 4 == p.slot / 4)
+                        .count();
+                    let volume =
+                        p.state.volume as f64 * reference_band_limit(cycles) / group as f64;
+                    sample += (primary_phase[idx] * TAU + p.offset as f64).cos() * volume;
+                }
+                *out += (sample * voice.env.sample(at as SampleTime) as f64) as f32;
+            }
+        }
+        out
+    }
+
+    fn assert_matches(name: &str, reference: &[f32], out: &[f32]) {
+        assert!(
+            reference.iter().any(|s| s.abs() > 0.01),
+            "reference is silent"
+        );
+        for (idx, (a, b)) in reference.iter().zip(out).enumerate() {
+            assert!(
+                (a - b).abs() < TOLERANCE,
+                "{} differs at sample {}: {} vs {}",
+                name,
+                idx,
+                a,
+                b
+            );
+        }
+    }
+
+    #[test]
+    fn scalar_matches_reference() {
+        let out = render(Kernel::Scalar, &|_| {});
+        assert_matches("Scalar", &reference(ModulationType::Relative), &out);
+    }
+
+    #[test]
+    fn scalar_matches_reference_shared_modulators() {
+        let out = render(Kernel::Scalar, &|bank| {
+            bank.mod_ty = ModulationType::Absolute
+        });
+        assert_matches("Scalar", &reference(ModulationType::Absolute), &out);
+    }
+
+    ///Kernels that are compared against the scalar one.
+    #[cfg(feature = "simd")]
+    fn simd_kernels() -> Vec<Kernel> {
+        vec![Kernel::F32x4, Kernel::F32x8, Kernel::F32x16]
+    }
+
+    ///Without the feature there is nothing to compare, which must not pass as a successful comparison. The
+    /// comparisons are ignored instead, and fail if they are run anyways.
+    #[cfg(not(feature = "simd"))]
+    fn simd_kernels() -> Vec<Kernel> {
+        panic!("The SIMD kernels need the `simd` feature: `cargo +nightly test --features simd`")
+    }
+
+    fn assert_kernels_match(setup: impl Fn(&mut OscillatorBank)) {
+        let reference = render(Kernel::Scalar, &setup);
+        for kernel in simd_kernels() {
+            assert_matches(kernel.name(), &reference, &render(kernel, &setup));
+        }
+    }
+
+    ///A note pressed and released within blocks starts and ends at its events' samples, not at the blocks'
+    /// borders.
+    #[test]
+    fn note_events_are_sample_exact() {
+        const PRESS: SampleTime = 1037;
+        const RELEASE: SampleTime = 2021;
+
+        let mut bank = OscillatorBank::default();
+        bank.on_state_change(&test_state());
+        let mut voices = [OscVoiceState::default(); OscillatorBank::VOICE_COUNT];
+        voices[0].state = VoiceState::On;
+        voices[0].freq = 220.0;
+        voices[0].velocity = 1.0;
+        voices[0].env.parameters = EnvelopeParams {
+            delay: 0.0,
+            attack: 0.0,
+            hold: 0.0,
+            decay: 0.0,
+            sustain_level: 1.0,
+            release: 0.0,
+        };
+        voices[0].env.on_press(PRESS, SAMPLE_RATE);
+        voices[0].env.on_release(RELEASE);
+
+        let mut out = vec![0.0; 3000];
+        bank.render(&voices, &mut out, SAMPLE_RATE, 0);
+
+        let (press, release) = (PRESS as usize, RELEASE as usize);
+        assert!(
+            out[..press].iter().all(|s| *s == 0.0),
+            "sound before the press"
+        );
+        assert!(out[press] != 0.0, "silent at the press");
+        assert!(out[release] != 0.0, "silent at the release");
+        assert!(
+            out[release + 1..].iter().all(|s| *s == 0.0),
+            "sound after the release"
+        );
+    }
+
+    #[test]
+    #[cfg_attr(
+        not(feature = "simd"),
+        ignore = "needs the nightly-only `simd` feature"
+    )]
+    fn kernels_match_scalar() {
+        assert_kernels_match(|_| {});
+    }
+
+    #[test]
+    #[cfg_attr(
+        not(feature = "simd"),
+        ignore = "needs the nightly-only `simd` feature"
+    )]
+    fn kernels_match_scalar_oversampled_and_filtered() {
+        assert_kernels_match(|bank| {
+            bank.set_oversampling(OversamplingFactor::X4);
+            bank.filter.enabled = true;
+        });
+    }
+
+    #[test]
+    #[cfg_attr(
+        not(feature = "simd"),
+        ignore = "needs the nightly-only `simd` feature"
+    )]
+    fn kernels_match_scalar_shared_modulators() {
+        assert_kernels_match(|bank| bank.mod_ty = ModulationType::Absolute);
+    }
+}
@@ -0,0 +1,288 @@
+use super::{
+    modulator::{ModulatorOsc, ParentIndex},
+    primary::PrimaryOsc,
+    Oscillator, OscillatorBank,
+};
+
+///Widest simd lane any kernel uses. The lane arrays are padded to a multiple of it.
+pub const MAX_LANE_WIDTH: usize = 16;
+
+const fn padded(count: usize) -> usize {
+    count.div_ceil(MAX_LANE_WIDTH) * MAX_LANE_WIDTH
+}
+
+///Fixed-point oscillator phase. The integer's full range is one cycle, so the phase wraps on overflow
+/// without losing precision, however long a note is held.
+pub type Phase = u32;
+///Phase units per cycle
+pub const PHASE_SCALE: f32 = 4294967296.0;
+
+pub const PRIMARY_SLOTS: usize = padded(OscillatorBank::PRIMARY_OSC_COUNT);
+pub const MODULATOR_SLOTS: usize = padded(OscillatorBank::MOD_OSC_COUNT);
+
+///Structure-of-arrays copy of the bank's oscillator configuration. All voices share the same configuration,
+/// so this is built once whenever the configuration changes, instead of gathering the lanes from the
+/// oscillator structs on every step.
+///
+/// Only oscillators that are on are stored, packed into the first `primary_count` and `modulator_count`
+/// entries. So the cost of a step scales with the number of orbitals in the solar system. The arrays are
+/// padded, so kernels of any width up to [MAX_LANE_WIDTH] can load whole lanes.
+#[derive(Clone, Copy)]
+pub struct LaneConfig {
+    ///Slot of each packed primary oscillator
+    pub primary_slot: [usize; PRIMARY_SLOTS],
+    pub primary_count: usize,
+    pub primary_speed: [f32; PRIMARY_SLOTS],
+    pub primary_volume: [f32; PRIMARY_SLOTS],
+    pub primary_offset: [f32; PRIMARY_SLOTS],
+    ///Weight of each oscillator in the voice's sum. Each group of four slots is normalized by the number of
+    /// active oscillators in it.
+    pub primary_norm: [f32; PRIMARY_SLOTS],
+    pub primary_mod: ChildModulation<PRIMARY_SLOTS>,
+
+    pub modulator_slot: [usize; MODULATOR_SLOTS],
+    pub modulator_count: usize,
+    pub modulator_speed: [f32; MODULATOR_SLOTS],
+    pub modulator_range: [f32; MODULATOR_SLOTS],
+    pub modulator_offset: [f32; MODULATOR_SLOTS],
+    pub modulator_mod: ChildModulation<MODULATOR_SLOTS>,
+    ///Packed modulator index and packed parent index of each modulator that modulates an active parent.
+    pub modulator_links: [(usize, ParentIndex); OscillatorBank::MOD_OSC_COUNT],
+    pub modulator_link_count: usize,
+}
+
+///Turns the summed modulation of an oscillator's children into its frequency multiplier.
+#[derive(Clone, Copy)]
+pub struct ChildModulation<const N: usize> {
+    ///1/n for oscillators with n modulating children, 0.0 otherwise.
+    pub inv_children: [f32; N],
+    ///1.0 for oscillators without children, so their multiplier stays at 1.0.
+    pub identity: [f32; N],
+}
+
+impl<const N: usize> ChildModulation<N> {
+    fn new(children: &[usize]) -> Self {
+        let mut inv_children = [0.0; N];
+        let mut identity = [1.0; N];
+        for (idx, count) in children.iter().enumerate() {
+            if *count > 0 {
+                inv_children[idx] = 1.0 / *count as f32;
+                identity[idx] = 0.0;
+            }
+        }
+        ChildModulation {
+            inv_children,
+            identity,
+        }
+    }
+}
+
+impl Default for LaneConfig {
+    fn default() -> Self {
+        LaneConfig {
+            primary_slot: [0; PRIMARY_SLOTS],
+            primary_count: 0,
+            primary_speed: [0.0; PRIMARY_SLOTS],
+            primary_volume: [0.0; PRIMARY_SLOTS],
+            primary_offset: [0.0; PRIMARY_SLOTS],
+            primary_norm: [0.0; PRIMARY_SLOTS],
+            primary_mod: ChildModulation::new(&[]),
+            modulator_slot: [0; MODULATOR_SLOTS],
+            modulator_count: 0,
+            modulator_speed: [0.0; MODULATOR_SLOTS],
+            modulator_range: [0.0; MODULATOR_SLOTS],
+            modulator_offset: [0.0; MODULATOR_SLOTS],
+            modulator_mod: ChildModulation::new(&[]),
+            modulator_links: [(0, ParentIndex::Primary(0)); OscillatorBank::MOD_OSC_COUNT],
+            modulator_link_count: 0,
+        }
+    }
+}
+
+impl LaneConfig {
+    ///Builds the configuration from a single voice's oscillators.
+    pub fn build(
+        primaries: &[Oscillator<PrimaryOsc>],
+        modulators: &[Oscillator<ModulatorOsc>],
+    ) -> Self {
+        let mut cfg = LaneConfig::default();
+        //Packed index of each slot, `None` if the slot is off
+        let mut primary_packed = [None; OscillatorBank::PRIMARY_OSC_COUNT];
+        let mut modulator_packed = [None; OscillatorBank::MOD_OSC_COUNT];
+
+        let mut group_count = [0.0f32; OscillatorBank::PRIMARY_OSC_COUNT / 4];
+        for (slot, osc) in primaries.iter().enumerate() {
+            if osc.osc.is_on {
+                group_count[slot / 4] += 1.0;
+            }
+        }
+        for (slot, osc) in primaries.iter().enumerate() {
+            if !osc.osc.is_on {
+                continue;
+            }
+            let idx = cfg.primary_count;
+            primary_packed[slot] = Some(idx);
+            cfg.primary_slot[idx] = slot;
+            cfg.primary_speed[idx] = osc.osc.speed_index;
+            cfg.primary_volume[idx] = osc.osc.volume;
+            cfg.primary_offset[idx] = osc.offset;
+            cfg.primary_norm[idx] = 1.0 / group_count[slot / 4];
+            cfg.primary_count += 1;
+        }
+
+        for (slot, osc) in modulators.iter().enumerate() {
+            if !osc.osc.is_on {
+                continue;
+            }
+            let idx = cfg.modulator_count;
+            modulator_packed[slot] = Some(idx);
+            cfg.modulator_slot[idx] = slot;
+            cfg.modulator_speed[idx] = osc.osc.speed_index;
+            cfg.modulator_range[idx] = osc.osc.range;
+            cfg.modulator_offset[idx] = osc.offset;
+            cfg.modulator_count += 1;
+        }
+
+        //Resolve the parents to packed indices. Modulators of parents that are off or outside of the
+        // bank are dropped.
+        let mut primary_children = [0usize; OscillatorBank::PRIMARY_OSC_COUNT];
+        let mut modulator_children = [0usize; OscillatorBank::MOD_OSC_COUNT];
+        for idx in 0..cfg.modulator_count {
+            let slot = cfg.modulator_slot[idx];
+            let parent = match modulators[slot].osc.parent_osc_slot {
+                ParentIndex::Primary(p) => primary_packed
+                    .get(p)
+                    .copied()
+                    .flatten()
+                    .map(ParentIndex::Primary),
+                ParentIndex::Modulator(p) => modulator_packed
+                    .get(p)
+                    .copied()
+                    .flatten()
+                    .map(ParentIndex::Modulator),
+            };
+            let parent = match parent {
+                Some(p) => p,
+                None => continue,
+            };
+            match parent {
+                ParentIndex::Primary(p) => primary_children[p] += 1,
+                ParentIndex::Modulator(p) => modulator_children[p] += 1,
+            }
+            cfg.modulator_links[cfg.modulator_link_count] = (idx, parent);
+            cfg.modulator_link_count += 1;
+        }
+        cfg.primary_mod = ChildModulation::new(&primary_children);
+        cfg.modulator_mod = ChildModulation::new(&modulator_children);
+
+        cfg
+    }
+
+    ///True if the packing differs from `other`, in which case the voice state has to be moved.
+    pub fn packing_changed(&self, other: &LaneConfig) -> bool {
+        self.primary_slot[0..self.primary_count] != other.primary_slot[0..other.primary_count]
+            || self.modulator_slot[0..self.modulator_count]
+                != other.modulator_slot[0..other.modulator_count]
+    }
+}
+
+///Oscillator state of a single voice. Indexed by packed index.
+#[derive(Clone, Copy)]
+pub struct VoiceLanes {
+    pub primary_phase: [Phase; PRIMARY_SLOTS],
+    pub modulator_phase: [Phase; MODULATOR_SLOTS],
+    ///Summed modulation of each modulator's children, written in the last step.
+    pub modulator_mod: [f32; MODULATOR_SLOTS],
+}
+
+impl VoiceLanes {
+    ///Moves the state from the packing of `from` to the packing of `to`. Oscillators that were
+    /// off before start at phase 0.
+    pub fn repack(&mut self, from: &LaneConfig, to: &LaneConfig) {
+        let mut primary_phase = [0; OscillatorBank::PRIMARY_OSC_COUNT];
+        let mut modulator_phase = [0; OscillatorBank::MOD_OSC_COUNT];
+        let mut modulator_mod = [0.0; OscillatorBank::MOD_OSC_COUNT];
+        for (idx, slot) in from.primary_slot[0..from.primary_count].iter().enumerate() {
+            primary_phase[*slot] = self.primary_phase[idx];
+        }
+        for (idx, slot) in from.modulator_slot[0..from.modulator_count]
+            .iter()
+            .enumerate()
+        {
+            modulator_phase[*slot] = self.modulator_phase[idx];
+            modulator_mod[*slot] = self.modulator_mod[idx];
+        }
+
+        *self = VoiceLanes::default();
+        for (idx, slot) in to.primary_slot[0..to.primary_count].iter().enumerate() {
+            self.primary_phase[idx] = primary_phase[*slot];
+        }
+        for (idx, slot) in to.modulator_slot[0..to.modulator_count].iter().enumerate() {
+            self.modulator_phase[idx] = modulator_phase[*slot];
+            self.modulator_mod[idx] = modulator_mod[*slot];
+        }
+    }
+}
+
+impl Default for VoiceLanes {
+    fn default() -> Self {
+        VoiceLanes {
+            primary_phase: [0; PRIMARY_SLOTS],
+            modulator_phase: [0; MODULATOR_SLOTS],
+            modulator_mod: [0.0; MODULATOR_SLOTS],
+        }
+    }
+}
+
+///Linear ramp of one value per oscillator, evaluated step by step over a block. Only the first `count`
+/// values are advanced.
+#[derive(Clone, Copy)]
+pub struct LaneRamp<const N: usize> {
+    pub value: [f32; N],
+    step: [f32; N],
+    count: usize,
+}
+
+impl<const N: usize> LaneRamp<N> {
+    ///Ramps from `start` to `end` in `steps`.
+    pub fn new(start: [f32; N], end: [f32; N], steps: usize, count: usize) -> Self {
+        let inv = 1.0 / steps.max(1) as f32;
+        let mut step = end;
+        for (s, start) in step.iter_mut().zip(start) {
+            *s = (*s - start) * inv;
+        }
+        LaneRamp {
+            value: start,
+            step,
+            count: count.min(N),
+        }
+    }
+
+    #[inline(always)]
+    pub fn advance(&mut self) {
+        for (v, s) in self.value[0..self.count].iter_mut().zip(self.step) {
+            *v += s;
+        }
+    }
+}
+
+///Linear ramp of a single value.
+#[derive(Clone, Copy)]
+pub struct Ramp {
+    pub value: f32,
+    step: f32,
+}
+
+impl Ramp {
+    pub fn new(start: f32, end: f32, steps: usize) -> Self {
+        Ramp {
+            value: start,
+            step: (end - start) / steps.max(1) as f32,
+        }
+    }
+
+    #[inline(always)]
+    pub fn advance(&mut self) {
+        self.value += self.step;
+    }
+}
@@ -12,9 +12,11 @@ pub struct PrimaryOsc {
 }
 
 impl PrimaryOsc {
+    ///Returns the oscillators frequency for the given `base_frequency`, after shifting the speed index by
+    /// `speed_offset` octaves.
     #[inline(always)]
-    pub fn freq(&self, base_frequency: f32) -> f32 {
-        base_frequency * 2.0f32.powf(self.speed_index)
+    pub fn freq_shifted(&self, base_frequency: f32, speed_offset: f32) -> f32 {
+        base_frequency * 2.0f32.powf(self.speed_index + speed_offset)
     }
 }
 
@@ -0,0 +1,294 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    envelope::{Envelope, EnvelopeParams},
+    filter::FilterSettings,
+    mod_matrix::{ModMatrix, ModSource},
+    osc::OscillatorBank,
+    tuning::Tuning,
+    SampleTime, Time, ABS_BASE_FREQ,
+};
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+pub enum VoiceState {
+    Off,
+    On,
+    Released,
+}
+
+impl VoiceState {
+    pub fn is_off(&self) -> bool {
+        matches!(self, VoiceState::Off)
+    }
+    pub fn is_released(&self) -> bool {
+        matches!(self, VoiceState::Released)
+    }
+
+    pub fn is_active(&self) -> bool {
+        !matches!(self, VoiceState::Off)
+    }
+}
+
+///Single banks state.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+pub struct OscVoiceState {
+    //local voice's envelope state.
+    pub env: Envelope,
+    pub state: VoiceState,
+    pub note: u8,
+    pub freq: f32,
+    ///Secondary envelope, used as modulation source.
+    #[serde(default)]
+    pub mod_env: Envelope,
+    #[serde(default)]
+    pub velocity: f32,
+    ///Polyphonic aftertouch of this voice's note.
+    #[serde(default)]
+    pub pressure: f32,
+    ///Random value rolled on note-on.
+    #[serde(default)]
+    pub random: f32,
+    ///Envelope of the voice's filter cutoff.
+    #[serde(default)]
+    pub filter_env: Envelope,
+}
+
+impl Default for OscVoiceState {
+    fn default() -> Self {
+        OscVoiceState {
+            env: Envelope::default(),
+            state: VoiceState::Off,
+            note: 0,
+            freq: 0.0,
+            mod_env: Envelope::default(),
+            velocity: 0.0,
+            pressure: 0.0,
+            random: 0.0,
+            filter_env: Envelope::default(),
+        }
+    }
+}
+
+///Oscillator bank array. Basically, if you imagine a grid of oscillators, each bank is a
+/// column (per voice).
+///
+/// This is more or less *the synth*. It is not persisted, but rebuilt from the persisted solar system (see
+/// [schema](crate::schema)) and the host parameters.
+#[derive(Clone)]
+pub struct OscArray {
+    //all os
+    pub bank: OscillatorBank,
+    ///State of each voice.
+    voices: [OscVoiceState; OscillatorBank::VOICE_COUNT],
+    ///Modulation matrix. Persisted by the plugin params and set on init.
+    pub matrix: ModMatrix,
+    ///Envelope parameters as set by the host, before modulation.
+    envelope: EnvelopeParams,
+    ///Last known mod wheel value (0..1)
+    pub mod_wheel: f32,
+    ///Last known channel pressure (0..1)
+    pub channel_pressure: f32,
+    rng_state: u32,
+    ///Note to frequency mapping. Persisted by the plugin params and set on init.
+    tuning: Tuning,
+    ///Frequency of A4 in Hz.
+    reference_pitch: f32,
+}
+
+impl Default for OscArray {
+    fn default() -> Self {
+        OscArray {
+            bank: OscillatorBank::default(),
+            voices: [OscVoiceState::default(); OscillatorBank::VOICE_COUNT],
+            matrix: ModMatrix::default(),
+            envelope: EnvelopeParams::default(),
+            mod_wheel: 0.0,
+            channel_pressure: 0.0,
+            rng_state: Self::default_seed(),
+            tuning: Tuning::default(),
+            reference_pitch: Self::default_reference_pitch(),
+        }
+    }
+}
+
+impl OscArray {
+    fn default_seed() -> u32 {
+        0x9E37_79B9
+    }
+
+    fn default_reference_pitch() -> f32 {
+        ABS_BASE_FREQ
+    }
+
+    ///Xorshift based random value in -1..1
+    fn next_random(&mut self) -> f32 {
+        let mut x = self.rng_state;
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.rng_state = x;
+        (x as f32 / u32::MAX as f32) * 2.0 - 1.0
+    }
+
+    pub fn note_on(&mut self, note: u8, velocity: f32, at: SampleTime, sample_rate: f32) {
+        //Notes that are not mapped by the keyboard mapping are ignored.
+        let freq = match self.tuning.note_to_freq(note, self.reference_pitch) {
+            Some(f) => f,
+            None => return,
+        };
+        let random = self.next_random();
+        //search for an inactive voice and init.
+        //The shared modulators only restart if no other voice is using them.
+        let alone = self.voices.iter().all(|v| v.state.is_off());
+        for vidx in 0..self.voices.len() {
+            let v = &mut self.voices[vidx];
+            if v.state.is_off() {
+                v.state = VoiceState::On;
+                v.note = note;
+                v.freq = freq;
+                v.velocity = velocity;
+                v.pressure = 0.0;
+                v.random = random;
+                v.env.on_press(at, sample_rate);
+                v.mod_env.on_press(at, sample_rate);
+                v.filter_env.on_press(at, sample_rate);
+
+                if self.bank.reset_phase {
+                    self.bank.reset_voice(vidx);
+                    if alone {
+                        self.bank.reset_shared_modulators();
+                    }
+                }
+                self.bank.reset_filter(vidx);
+
+                //Jump to the new note's modulation, otherwise we'd glide from the last note's state.
+                let targets = self.matrix.evaluate(&self.source_values(vidx, at));
+                self.bank.reset_modulation(vidx, &targets.osc);
+
+                return;
+            }
+        }
+    }
+
+    pub fn note_off(&mut self, note: u8, at: SampleTime) {
+        for v in &mut self.voices {
+            if v.note == note && !v.state.is_off() {
+                v.env.on_release(at);
+                v.mod_env.on_release(at);
+                v.filter_env.on_release(at);
+                v.state = VoiceState::Released;
+            }
+        }
+    }
+
+    ///Sets the polyphonic aftertouch of all voices playing `note`.
+    pub fn note_pressure(&mut self, note: u8, pressure: f32) {
+        for v in &mut self.voices {
+            if v.note == note && !v.state.is_off() {
+                v.pressure = pressure;
+            }
+        }
+    }
+
+    pub fn set_envelopes(&mut self, new: EnvelopeParams) {
+        self.envelope = new;
+        for v in &mut self.voices {
+            v.env.parameters = new;
+        }
+    }
+
+    pub fn set_filter(&mut self, settings: FilterSettings, envelope: EnvelopeParams) {
+        self.bank.filter = settings;
+        for v in &mut self.voices {
+            v.filter_env.parameters = envelope;
+        }
+    }
+
+    pub fn set_tuning(&mut self, tuning: Tuning) {
+        self.tuning = tuning;
+        self.retune();
+    }
+
+    ///Sets the frequency of A4. Absolute modulation follows the reference pitch as well.
+    pub fn set_reference_pitch(&mut self, pitch: f32) {
+        if pitch == self.reference_pitch {
+            return;
+        }
+        self.reference_pitch = pitch;
+        self.bank.abs_base_freq = pitch;
+        self.retune();
+    }
+
+    ///Recalculates the frequency of all playing voices.
+    fn retune(&mut self) {
+        for v in &mut self.voices {
+            if v.state.is_off() {
+                continue;
+            }
+            if let Some(freq) = self.tuning.note_to_freq(v.note, self.reference_pitch) {
+                v.freq = freq;
+            }
+        }
+    }
+
+    ///Collects all modulation source values of the voice at sample `at`.
+    fn source_values(&self, voice: usize, at: SampleTime) -> [f32; ModSource::COUNT] {
+        let v = &self.voices[voice];
+        let mut values = [0.0; ModSource::COUNT];
+        values[ModSource::Velocity as usize] = v.velocity;
+        values[ModSource::Key as usize] = v.note as f32 / 127.0;
+        values[ModSource::ModWheel as usize] = self.mod_wheel;
+        values[ModSource::Aftertouch as usize] = v.pressure.max(self.channel_pressure);
+        values[ModSource::Lfo1 as usize] = self.matrix.lfo_value(0);
+        values[ModSource::Lfo2 as usize] = self.matrix.lfo_value(1);
+        values[ModSource::Envelope as usize] = v.env.sample(at);
+        values[ModSource::ModEnvelope as usize] = v.mod_env.sample(at);
+        values[ModSource::Random as usize] = v.random;
+        values
+    }
+
+    ///Evaluates the modulation matrix for all active voices. The bank will reach the new targets after `samples`.
+    fn update_modulation(&mut self, samples: usize, sample_rate: f32, block_start: SampleTime) {
+        #[cfg(feature = "profile")]
+        puffin::profile_function!();
+
+        let mod_env_params = self.matrix.mod_envelope;
+        self.bank.voice_modulators = self.matrix.modulates_modulators();
+        for vidx in 0..self.voices.len() {
+            self.voices[vidx].mod_env.parameters = mod_env_params;
+            if self.voices[vidx].state.is_off() {
+                continue;
+            }
+            let targets = self.matrix.evaluate(&self.source_values(vidx, block_start));
+            self.voices[vidx].env.parameters = targets.modulate_envelope(&self.envelope);
+            self.bank.set_modulation_target(vidx, &targets.osc, samples);
+        }
+
+        self.matrix.advance(samples as Time / sample_rate as Time);
+    }
+
+    ///Turns off finished voices and updates the modulation for the next `samples`.
+    fn begin_block(&mut self, samples: usize, sample_rate: f32, block_start: SampleTime) {
+        //check each voice once if we can turn it off
+        for v in &mut self.voices {
+            #[cfg(feature = "profile")]
+            puffin::profile_scope!("Voice key-filter update");
+            if v.env.after_sampling(block_start) {
+                v.state = VoiceState::Off;
+                v.env.reset();
+                v.mod_env.reset();
+                v.filter_env.reset();
+                v.freq = 0.0;
+                v.note = 0;
+            }
+        }
+        self.update_modulation(samples, sample_rate, block_start);
+    }
+
+    ///Renders the voice sum into `out`, starting at sample `block_start`.
+    pub fn render(&mut self, out: &mut [f32], sample_rate: f32, block_start: SampleTime) {
+        self.begin_block(out.len(), sample_rate, block_start);
+        self.bank
+            .render(&self.voices, out, sample_rate, block_start);
+    }
+}
@@ -0,0 +1,255 @@
+use serde::{Deserialize, Serialize};
+
+use crate::TWOPI;
+
+///Number of non-zero coefficients per side of the half-band filter. The full filter has
+/// `4 * TAPS - 1` taps.
+const TAPS: usize = 8;
+
+lazy_static::lazy_static! {
+    ///Odd (non-zero) coefficients of a blackman windowed half-band low-pass. The even ones are zero,
+    /// except for the center tap, which is 0.5.
+    static ref HALFBAND: [f32; 2 * TAPS] = {
+        let len = (4 * TAPS - 1) as f32;
+        let mut coeffs = [0.0; 2 * TAPS];
+        for (j, c) in coeffs.iter_mut().enumerate() {
+            //offset to the center tap
+            let m = 2.0 * j as f32 - (2 * TAPS - 1) as f32;
+            let sinc = (std::f32::consts::FRAC_PI_2 * m).sin() / (std::f32::consts::PI * m);
+            //position within the window (0..1)
+            let w = (m + (len - 1.0) / 2.0) / (len - 1.0);
+            let window = 0.42 - 0.5 * (TWOPI * w).cos() + 0.08 * (2.0 * TWOPI * w).cos();
+            *c = sinc * window;
+        }
+        //Normalize for unity gain at DC
+        let sum: f32 = coeffs.iter().sum();
+        for c in &mut coeffs {
+            *c *= 0.5 / sum;
+        }
+        coeffs
+    };
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "nih-plug", derive(nih_plug::prelude::Enum))]
+pub enum OversamplingFactor {
+    #[default]
+    #[cfg_attr(feature = "nih-plug", name = "1x")]
+    None,
+    #[cfg_attr(feature = "nih-plug", name = "2x")]
+    X2,
+    #[cfg_attr(feature = "nih-plug", name = "4x")]
+    X4,
+    #[cfg_attr(feature = "nih-plug", name = "8x")]
+    X8,
+}
+
+impl OversamplingFactor {
+    pub const ALL: [OversamplingFactor; 4] = [
+        OversamplingFactor::None,
+        OversamplingFactor::X2,
+        OversamplingFactor::X4,
+        OversamplingFactor::X8,
+    ];
+
+    ///Number of cascaded 2x stages.
+    pub fn stages(&self) -> usize {
+        match self {
+            OversamplingFactor::None => 0,
+            OversamplingFactor::X2 => 1,
+            OversamplingFactor::X4 => 2,
+            OversamplingFactor::X8 => 3,
+        }
+    }
+
+    pub fn factor(&self) -> usize {
+        1 << self.stages()
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            OversamplingFactor::None => "1x",
+            OversamplingFactor::X2 => "2x",
+            OversamplingFactor::X4 => "4x",
+            OversamplingFactor::X8 => "8x",
+        }
+    }
+}
+
+///Polyphase 2x half-band interpolator.
+#[derive(Clone, Copy)]
+struct HalfbandUp {
+    coeffs: [f32; 2 * TAPS],
+    history: [f32; 2 * TAPS],
+    pos: usize,
+}
+
+impl HalfbandUp {
+    fn new() -> Self {
+        HalfbandUp {
+            coeffs: *HALFBAND,
+            history: [0.0; 2 * TAPS],
+            pos: 0,
+        }
+    }
+
+    ///Returns two output samples for each input sample.
+    #[inline]
+    fn process(&mut self, x: f32) -> [f32; 2] {
+        self.history[self.pos] = x;
+        self.pos = (self.pos + 1) % self.history.len();
+
+        //The even branch is the (delayed) center tap, the odd branch the convolution with all non-zero taps.
+        let even = self.history[(self.pos + TAPS - 1) % self.history.len()];
+        let mut odd = 0.0;
+        for (j, c) in self.coeffs.iter().enumerate() {
+            //newest sample last
+            odd += c * self.history[(self.pos + j) % self.history.len()];
+        }
+        //Gain of 2 compensates the zero stuffing
+        [even, odd * 2.0]
+    }
+}
+
+///Polyphase 2x half-band decimator.
+#[derive(Clone, Copy)]
+struct HalfbandDown {
+    coeffs: [f32; 2 * TAPS],
+    odd_history: [f32; 2 * TAPS],
+    even_history: [f32; TAPS],
+    pos: usize,
+    even_pos: usize,
+}
+
+impl HalfbandDown {
+    fn new() -> Self {
+        HalfbandDown {
+            coeffs: *HALFBAND,
+            odd_history: [0.0; 2 * TAPS],
+            even_history: [0.0; TAPS],
+            pos: 0,
+            even_pos: 0,
+        }
+    }
+
+    ///Returns one sample for two input samples.
+    #[inline]
+    fn process(&mut self, x: [f32; 2]) -> f32 {
+        self.odd_history[self.pos] = x[1];
+        self.pos = (self.pos + 1) % self.odd_history.len();
+
+        //Delay the even branch to line up with the center of the odd branch
+        let center = std::mem::replace(&mut self.even_history[self.even_pos], x[0]);
+        self.even_pos = (self.even_pos + 1) % self.even_history.len();
+
+        let mut acc = 0.5 * center;
+        for (j, c) in self.coeffs.iter().enumerate() {
+            acc += c * self.odd_history[(self.pos + j) % self.odd_history.len()];
+        }
+        acc
+    }
+}
+
+///Cascade of half-band filters to run some processing at up to 8x the sample rate.
+#[derive(Clone, Copy)]
+pub struct Oversampler {
+    factor: OversamplingFactor,
+    up: [HalfbandUp; 3],
+    down: [HalfbandDown; 3],
+}
+
+impl Default for Oversampler {
+    fn default() -> Self {
+        Oversampler::new(OversamplingFactor::None)
+    }
+}
+
+impl Oversampler {
+    ///Largest supported oversampling factor
+    pub const MAX_FACTOR: usize = 8;
+
+    pub fn new(factor: OversamplingFactor) -> Self {
+        Oversampler {
+            factor,
+            up: [HalfbandUp::new(); 3],
+            down: [HalfbandDown::new(); 3],
+        }
+    }
+
+    pub fn factor(&self) -> OversamplingFactor {
+        self.factor
+    }
+
+    ///Changes the factor. Resets the filters if it changed.
+    pub fn set_factor(&mut self, factor: OversamplingFactor) {
+        if factor != self.factor {
+            *self = Oversampler::new(factor);
+        }
+    }
+
+    ///Latency at the base sample rate, in samples, for up- and downsampling.
+    pub fn latency(&self) -> u32 {
+        (2.0 * self.stage_latency()).round() as u32
+    }
+
+    ///Latency of the decimation only, in samples at the base sample rate.
+    pub fn decimation_latency(&self) -> u32 {
+        self.stage_latency().round() as u32
+    }
+
+    ///Group delay of one filter per stage, in samples at the base sample rate.
+    fn stage_latency(&self) -> f32 {
+        //The half-band filter is linear phase, so it delays by half its length at its higher rate.
+        (0..self.factor.stages())
+            .map(|stage| (2 * TAPS - 1) as f32 / (2 << stage) as f32)
+            .sum()
+    }
+
+    ///Upsamples `x`, applies `f` to each upsampled sample, and decimates back.
+    #[inline]
+    pub fn process(&mut self, x: f32, mut f: impl FnMut(f32) -> f32) -> f32 {
+        let stages = self.factor.stages();
+        if stages == 0 {
+            return f(x);
+        }
+
+        let mut buffer = [0.0; Self::MAX_FACTOR];
+        let mut scratch = [0.0; Self::MAX_FACTOR];
+        buffer[0] = x;
+        let mut len = 1;
+        for stage in 0..stages {
+            for i in 0..len {
+                let [a, b] = self.up[stage].process(buffer[i]);
+                scratch[2 * i] = a;
+                scratch[2 * i + 1] = b;
+            }
+            len *= 2;
+            buffer[0..len].copy_from_slice(&scratch[0..len]);
+        }
+
+        for s in &mut buffer[0..len] {
+            *s = f(*s);
+        }
+
+        self.decimate(&buffer[0..len])
+    }
+
+    ///Decimates `factor()` samples at the oversampled rate into one sample at the base rate.
+    #[inline]
+    pub fn decimate(&mut self, input: &[f32]) -> f32 {
+        let stages = self.factor.stages();
+        debug_assert!(input.len() == self.factor.factor());
+
+        let mut buffer = [0.0; Self::MAX_FACTOR];
+        let mut len = input.len();
+        buffer[0..len].copy_from_slice(input);
+        for stage in (0..stages).rev() {
+            for i in 0..(len / 2) {
+                buffer[i] = self.down[stage].process([buffer[2 * i], buffer[2 * i + 1]]);
+            }
+            len /= 2;
+        }
+
+        buffer[0]
+    }
+}
@@ -0,0 +1,422 @@
+//!Versioned schema of the persisted solar system. The plugin stores its solar system in this form instead of
+//! serializing the GUI and synth structs, so those can change without breaking saved projects and presets.
+//!
+//! Versions:
+//! - 0: Written by 0.2 and earlier. The GUI's solar system serialized as is, without a version field. Orbit
+//!   radii are in pixels and the slot allocation is stored next to the orbitals.
+//! - 1: [PersistedSolarSystem]. Planets with their moons, the radius is the slot's volume or range (0..1).
+//!
+//! When changing the schema, add a new version, keep the old one's structs and extend [migrate] by a
+//! migration from the previous version.
+//!
+//! A loaded solar system is not trusted. [PersistedSolarSystem::validate] repairs slots and values, and
+//! rejects graphs that can't be a solar system.
+
+use std::fmt::Display;
+
+use serde::{de::Error as _, Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::Value;
+
+use crate::{
+    osc::{modulator::ParentIndex, OscillatorBank},
+    TWOPI,
+};
+
+///Version [PersistedSolarSystem] is written with.
+pub const SCHEMA_VERSION: u32 = 1;
+///Valid speed indices, the range of the slots' speed parameters.
+pub const SPEED_RANGE: (f32, f32) = (-20.0, 20.0);
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SchemaError {
+    ///The state does not match the schema of its version.
+    Parse(String),
+    ///The state was written by a newer version of the plugin.
+    UnsupportedVersion(u32),
+    ///The state describes a graph that can't be a solar system.
+    InvalidGraph(String),
+}
+
+impl Display for SchemaError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SchemaError::Parse(e) => write!(f, "Invalid state: {}", e),
+            SchemaError::UnsupportedVersion(v) => {
+                write!(f, "State version {} is newer than this plugin", v)
+            }
+            SchemaError::InvalidGraph(e) => write!(f, "Invalid solar system: {}", e),
+        }
+    }
+}
+
+///Fix applied by [PersistedSolarSystem::validate].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Repair {
+    ///The slot was out of range, or already used by another orbital. The orbital was moved to a free slot.
+    MovedSlot { from: ParentIndex, to: ParentIndex },
+    ///The slot was invalid and no slot was free, the orbital was removed together with its moons.
+    Removed(ParentIndex),
+    ///A value was not finite, or out of its range, and was reset or clamped.
+    Value {
+        orbital: ParentIndex,
+        property: &'static str,
+    },
+}
+
+impl Display for Repair {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Repair::MovedSlot { from, to } => write!(f, "Moved {:?} to {:?}", from, to),
+            Repair::Removed(index) => write!(f, "Removed {:?}, no slot is free", index),
+            Repair::Value { orbital, property } => {
+                write!(f, "Reset invalid {} of {:?}", property, orbital)
+            }
+        }
+    }
+}
+
+///Slots claimed while validating.
+struct Slots {
+    primaries: [bool; OscillatorBank::PRIMARY_OSC_COUNT],
+    modulators: [bool; OscillatorBank::MOD_OSC_COUNT],
+}
+
+impl Slots {
+    fn get(&mut self, is_planet: bool) -> &mut [bool] {
+        if is_planet {
+            &mut self.primaries
+        } else {
+            &mut self.modulators
+        }
+    }
+
+    ///Claims `slot`. Returns false if it is out of range or already claimed.
+    fn claim(&mut self, is_planet: bool, slot: usize) -> bool {
+        match self.get(is_planet).get_mut(slot) {
+            Some(used) if !*used => {
+                *used = true;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn claim_free(&mut self, is_planet: bool) -> Option<usize> {
+        let slots = self.get(is_planet);
+        let slot = slots.iter().position(|used| !*used)?;
+        slots[slot] = true;
+        Some(slot)
+    }
+
+    fn release(&mut self, is_planet: bool, slot: usize) {
+        if let Some(used) = self.get(is_planet).get_mut(slot) {
+            *used = false;
+        }
+    }
+}
+
+fn index(is_planet: bool, slot: usize) -> ParentIndex {
+    if is_planet {
+        ParentIndex::Primary(slot)
+    } else {
+        ParentIndex::Modulator(slot)
+    }
+}
+
+///Planet or moon of the persisted solar system.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PersistedOrbital {
+    ///Primary slot for planets, modulator slot for moons.
+    pub slot: usize,
+    ///Volume of a planet, or range of a moon (0..1)
+    pub radius: f32,
+    ///Speed index in octaves
+    pub speed: f32,
+    ///Phase offset in radiant
+    pub offset: f32,
+    #[serde(default)]
+    pub muted: bool,
+    ///Moons orbiting this orbital.
+    #[serde(default)]
+    pub moons: Vec<PersistedOrbital>,
+}
+
+impl PersistedOrbital {
+    ///Levels of orbitals, including self.
+    fn depth(&self) -> usize {
+        1 + self.moons.iter().map(|m| m.depth()).max().unwrap_or(0)
+    }
+
+    ///Number of orbitals, including self.
+    fn count(&self) -> usize {
+        1 + self.moons.iter().map(|m| m.count()).sum::<usize>()
+    }
+
+    ///Resets non finite values, and clamps them into their range.
+    fn repair_values(&mut self, is_planet: bool, repairs: &mut Vec<Repair>) {
+        let orbital = index(is_planet, self.slot);
+        let mut repair = |value: &mut f32, property: &'static str, valid: fn(f32) -> f32| {
+            let repaired = if value.is_finite() {
+                valid(*value)
+            } else {
+                0.0
+            };
+            if repaired != *value {
+                *value = repaired;
+                repairs.push(Repair::Value { orbital, property });
+            }
+        };
+        repair(&mut self.radius, "radius", |r| r.clamp(0.0, 1.0));
+        repair(&mut self.speed, "speed", |s| {
+            s.clamp(SPEED_RANGE.0, SPEED_RANGE.1)
+        });
+        repair(&mut self.offset, "offset", |o| {
+            if (0.0..=TWOPI).contains(&o) {
+                o
+            } else {
+                o.rem_euclid(TWOPI)
+            }
+        });
+    }
+}
+
+///Claims the slots of all orbitals in tree order. `keep` is set for each orbital that could claim its slot.
+fn claim_slots(
+    orbitals: &[PersistedOrbital],
+    is_planet: bool,
+    slots: &mut Slots,
+    keep: &mut Vec<bool>,
+) {
+    for orbital in orbitals {
+        keep.push(slots.claim(is_planet, orbital.slot));
+        claim_slots(&orbital.moons, false, slots, keep);
+    }
+}
+
+///Releases the kept slots of `orbital` and its moons, which are removed.
+fn release_slots(
+    orbital: &PersistedOrbital,
+    is_planet: bool,
+    slots: &mut Slots,
+    keep: &mut impl Iterator<Item = bool>,
+) {
+    if keep.next().unwrap_or(false) {
+        slots.release(is_planet, orbital.slot);
+    }
+    for moon in &orbital.moons {
+        release_slots(moon, false, slots, keep);
+    }
+}
+
+///Moves orbitals that could not keep their slot to a free one, or removes them if there is none.
+fn repair_slots(
+    orbitals: &mut Vec<PersistedOrbital>,
+    is_planet: bool,
+    slots: &mut Slots,
+    keep: &mut impl Iterator<Item = bool>,
+    repairs: &mut Vec<Repair>,
+) {
+    let mut idx = 0;
+    while idx < orbitals.len() {
+        let orbital = &mut orbitals[idx];
+        if !keep.next().unwrap_or(false) {
+            let from = index(is_planet, orbital.slot);
+            if let Some(slot) = slots.claim_free(is_planet) {
+                orbital.slot = slot;
+                repairs.push(Repair::MovedSlot {
+                    from,
+                    to: index(is_planet, slot),
+                });
+            } else {
+                for moon in &orbital.moons {
+                    release_slots(moon, false, slots, keep);
+                }
+                orbitals.remove(idx);
+                repairs.push(Repair::Removed(from));
+                continue;
+            }
+        }
+        orbital.repair_values(is_planet, repairs);
+        repair_slots(&mut orbital.moons, false, slots, keep, repairs);
+        idx += 1;
+    }
+}
+
+///Solar system as it is persisted. Serializes in the current [SCHEMA_VERSION], deserializes any known version.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct PersistedSolarSystem {
+    pub planets: Vec<PersistedOrbital>,
+}
+
+impl PersistedSolarSystem {
+    pub fn from_json(src: &str) -> Result<Self, SchemaError> {
+        let value = serde_json::from_str(src).map_err(|e| SchemaError::Parse(e.to_string()))?;
+        migrate(value)
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("Solar system is always serializable")
+    }
+
+    ///Checks a loaded solar system. Orbitals with an out of range or duplicated slot are moved to a free slot,
+    /// or removed if there is none. Values that are not finite or out of range are reset or clamped. Returns
+    /// what was repaired.
+    ///
+    /// Chains of moons longer than there are modulator slots are rejected, since they can't be repaired
+    /// without changing the graph.
+    pub fn validate(&mut self) -> Result<Vec<Repair>, SchemaError> {
+        let depth = self.planets.iter().map(|p| p.depth()).max().unwrap_or(0);
+        if depth > OscillatorBank::MOD_OSC_COUNT + 1 {
+            return Err(SchemaError::InvalidGraph(format!(
+                "Moons are nested {} levels deep, but there are only {} modulator slots",
+                depth - 1,
+                OscillatorBank::MOD_OSC_COUNT
+            )));
+        }
+
+        let mut slots = Slots {
+            primaries: [false; OscillatorBank::PRIMARY_OSC_COUNT],
+            modulators: [false; OscillatorBank::MOD_OSC_COUNT],
+        };
+        //Orbitals keep valid slots first, so an invalid one that comes earlier can't take them.
+        let mut keep = Vec::with_capacity(self.planets.iter().map(|p| p.count()).sum());
+        claim_slots(&self.planets, true, &mut slots, &mut keep);
+
+        let mut repairs = Vec::new();
+        repair_slots(
+            &mut self.planets,
+            true,
+            &mut slots,
+            &mut keep.into_iter(),
+            &mut repairs,
+        );
+        Ok(repairs)
+    }
+}
+
+impl Serialize for PersistedSolarSystem {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        v1::SolarSystem {
+            version: SCHEMA_VERSION,
+            planets: self.planets.clone(),
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for PersistedSolarSystem {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        migrate(Value::deserialize(deserializer)?).map_err(D::Error::custom)
+    }
+}
+
+///Reads a solar system of any known version and migrates it to the current one.
+pub fn migrate(value: Value) -> Result<PersistedSolarSystem, SchemaError> {
+    let version = match value.get("version") {
+        None => 0,
+        Some(v) => v
+            .as_u64()
+            .and_then(|v| u32::try_from(v).ok())
+            .ok_or_else(|| SchemaError::Parse(format!("Invalid version {}", v)))?,
+    };
+
+    let parse = |e: serde_json::Error| SchemaError::Parse(e.to_string());
+    match version {
+        0 => serde_json::from_value(value)
+            .map_err(parse)
+            .and_then(v0::migrate),
+        1 => serde_json::from_value(value)
+            .map(|v1: v1::SolarSystem| PersistedSolarSystem {
+                planets: v1.planets,
+            })
+            .map_err(parse),
+        v => Err(SchemaError::UnsupportedVersion(v)),
+    }
+}
+
+mod v1 {
+    use serde::{Deserialize, Serialize};
+
+    use super::PersistedOrbital;
+
+    #[derive(Serialize, Deserialize)]
+    pub struct SolarSystem {
+        pub version: u32,
+        pub planets: Vec<PersistedOrbital>,
+    }
+}
+
+///The 0.2 GUI structs, reduced to the fields the migration needs.
+mod v0 {
+    use serde::Deserialize;
+
+    use super::{PersistedOrbital, PersistedSolarSystem, SchemaError};
+
+    ///Orbit radii of the 0.2 GUI in pixels. Used to map the radius to the slot's volume or range.
+    const MIN_ORBIT: f32 = 25.0;
+    const MAX_ORBIT_PRIMARY: f32 = 300.0;
+    const MAX_ORBIT_SECONDARY: f32 = 100.0;
+
+    #[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+    pub enum ObjTy {
+        Sun,
+        Planet,
+        Moon,
+        Astroid,
+    }
+
+    #[derive(Deserialize)]
+    pub struct Orbital {
+        pub radius: f32,
+        pub offset: f32,
+        pub speed_index: f32,
+        pub obj: ObjTy,
+        pub osc_slot: usize,
+        ///Not written by 0.2, but by development builds before the schema was introduced.
+        #[serde(default)]
+        pub muted: bool,
+        pub children: Vec<Orbital>,
+    }
+
+    #[derive(Deserialize)]
+    pub struct SolarSystem {
+        pub orbitals: Vec<Orbital>,
+    }
+
+    ///Migrates `orbital`, which is expected to be of type `obj`, like the 0.2 GUI would have created it.
+    fn migrate_orbital(orbital: Orbital, obj: ObjTy) -> Result<PersistedOrbital, SchemaError> {
+        if orbital.obj != obj {
+            return Err(SchemaError::InvalidGraph(format!(
+                "{:?} in slot {} should be a {:?}",
+                orbital.obj, orbital.osc_slot, obj
+            )));
+        }
+        let (max_orbit, child) = match obj {
+            ObjTy::Planet => (MAX_ORBIT_PRIMARY, ObjTy::Moon),
+            _ => (MAX_ORBIT_SECONDARY, ObjTy::Astroid),
+        };
+        Ok(PersistedOrbital {
+            slot: orbital.osc_slot,
+            radius: ((orbital.radius - MIN_ORBIT) / (max_orbit - MIN_ORBIT)).clamp(0.0, 1.0),
+            speed: orbital.speed_index,
+            offset: orbital.offset,
+            muted: orbital.muted,
+            moons: orbital
+                .children
+                .into_iter()
+                .map(|c| migrate_orbital(c, child))
+                .collect::<Result<_, _>>()?,
+        })
+    }
+
+    ///Fails if an orbital's type doesn't match its place in the tree, like a planet orbiting a moon.
+    pub fn migrate(system: SolarSystem) -> Result<PersistedSolarSystem, SchemaError> {
+        Ok(PersistedSolarSystem {
+            planets: system
+                .orbitals
+                .into_iter()
+                .map(|o| migrate_orbital(o, ObjTy::Planet))
+                .collect::<Result<_, _>>()?,
+        })
+    }
+}
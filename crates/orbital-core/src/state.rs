@@ -0,0 +1,72 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    osc::{modulator::ModulatorOsc, primary::PrimaryOsc, OscillatorBank},
+    TWOPI,
+};
+
+///Oscillators of the solar system, as applied by [OscillatorBank::on_state_change].
+#[derive(Clone)]
+pub struct SolarState {
+    pub primary_states: Vec<PrimaryState>,
+    pub modulator_states: Vec<ModulatorState>,
+}
+
+impl Default for SolarState {
+    fn default() -> Self {
+        SolarState {
+            primary_states: Vec::with_capacity(OscillatorBank::PRIMARY_OSC_COUNT),
+            modulator_states: Vec::with_capacity(OscillatorBank::MOD_OSC_COUNT),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct PrimaryState {
+    pub offset: f32,
+    pub state: PrimaryOsc,
+    pub slot: usize,
+}
+
+#[derive(Clone)]
+pub struct ModulatorState {
+    pub offset: f32,
+    pub state: ModulatorOsc,
+    pub slot: usize,
+}
+
+///Properties of an orbital a macro can be assigned to.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OrbitalProperty {
+    ///Volume of a primary, or range of a modulator (0..1)
+    Radius,
+    ///Speed index in octaves
+    Speed,
+    ///Phase offset in radiant
+    Offset,
+}
+
+impl OrbitalProperty {
+    pub const ALL: [OrbitalProperty; 3] = [
+        OrbitalProperty::Radius,
+        OrbitalProperty::Speed,
+        OrbitalProperty::Offset,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            OrbitalProperty::Radius => "Radius",
+            OrbitalProperty::Speed => "Speed",
+            OrbitalProperty::Offset => "Offset",
+        }
+    }
+
+    ///Range of valid values for this property.
+    pub fn range(&self) -> (f32, f32) {
+        match self {
+            OrbitalProperty::Radius => (0.0, 1.0),
+            OrbitalProperty::Speed => (-20.0, 20.0),
+            OrbitalProperty::Offset => (0.0, TWOPI),
+        }
+    }
+}
@@ -0,0 +1,411 @@
+use std::{fmt::Display, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+use crate::ABS_BASE_FREQ;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TuningError {
+    Io(String),
+    ///The file ended before all declared entries were read.
+    UnexpectedEnd,
+    ///A line could not be parsed. Contains the offending line.
+    InvalidLine(String),
+    EmptyScale,
+}
+
+impl Display for TuningError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TuningError::Io(e) => write!(f, "Could not read file: {}", e),
+            TuningError::UnexpectedEnd => write!(f, "File ended unexpectedly"),
+            TuningError::InvalidLine(l) => write!(f, "Invalid line: \"{}\"", l),
+            TuningError::EmptyScale => write!(f, "Scale has no degrees"),
+        }
+    }
+}
+
+///Non-comment lines of a Scala file. Comments start with `!`.
+fn scala_lines(src: &str) -> impl Iterator<Item = &str> {
+    src.lines()
+        .map(|l| l.trim_end_matches('\r'))
+        .filter(|l| !l.starts_with('!'))
+}
+
+fn read_file(path: &Path) -> Result<String, TuningError> {
+    std::fs::read_to_string(path).map_err(|e| TuningError::Io(e.to_string()))
+}
+
+fn parse_int(line: &str) -> Result<i32, TuningError> {
+    line.split_whitespace()
+        .next()
+        .and_then(|t| t.parse().ok())
+        .ok_or_else(|| TuningError::InvalidLine(line.to_string()))
+}
+
+///Scala scale (.scl). Pitches are stored in cents, the last one is the period (usually the octave).
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Scale {
+    pub description: String,
+    pub pitches: Vec<f64>,
+}
+
+impl Scale {
+    pub fn load(path: &Path) -> Result<Self, TuningError> {
+        Self::parse(&read_file(path)?)
+    }
+
+    pub fn parse(src: &str) -> Result<Self, TuningError> {
+        let mut lines = scala_lines(src);
+        let description = lines
+            .next()
+            .ok_or(TuningError::UnexpectedEnd)?
+            .trim()
+            .to_string();
+        let count = parse_int(lines.next().ok_or(TuningError::UnexpectedEnd)?)?;
+        if count <= 0 {
+            return Err(TuningError::EmptyScale);
+        }
+
+        //Not preallocated from `count`, a broken file may declare any number of degrees
+        let mut pitches = Vec::new();
+        for _ in 0..count {
+            let line = lines.next().ok_or(TuningError::UnexpectedEnd)?;
+            pitches.push(Self::parse_pitch(line)?);
+        }
+
+        Ok(Scale {
+            description,
+            pitches,
+        })
+    }
+
+    ///Parses a pitch line. Values containing a `.` are cents, everything else is a ratio (`3/2`) or an integer.
+    fn parse_pitch(line: &str) -> Result<f64, TuningError> {
+        let invalid = || TuningError::InvalidLine(line.to_string());
+        let token = line.split_whitespace().next().ok_or_else(invalid)?;
+        if token.contains('.') {
+            return token.parse().map_err(|_| invalid());
+        }
+
+        let (num, den) = match token.split_once('/') {
+            Some((n, d)) => (n, d),
+            None => (token, "1"),
+        };
+        let num: f64 = num.parse().map_err(|_| invalid())?;
+        let den: f64 = den.parse().map_err(|_| invalid())?;
+        if num <= 0.0 || den <= 0.0 {
+            return Err(invalid());
+        }
+        Ok(1200.0 * (num / den).log2())
+    }
+
+    fn len(&self) -> i32 {
+        self.pitches.len() as i32
+    }
+
+    ///Pitch of `degree` in cents, relative to degree 0. Degrees outside the scale repeat with the period.
+    fn cents(&self, degree: i32) -> f64 {
+        let period = self.pitches[self.pitches.len() - 1];
+        let octave = degree.div_euclid(self.len());
+        let step = degree.rem_euclid(self.len());
+        let within = if step == 0 {
+            0.0
+        } else {
+            self.pitches[step as usize - 1]
+        };
+        octave as f64 * period + within
+    }
+}
+
+///Scala keyboard mapping (.kbm).
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct KeyboardMapping {
+    pub first_note: i32,
+    pub last_note: i32,
+    ///Note that is mapped to scale degree 0
+    pub middle_note: i32,
+    pub reference_note: i32,
+    pub reference_freq: f64,
+    ///Scale degree that is used as the mapping's period
+    pub octave_degree: i32,
+    ///Scale degree of each key in the pattern. `None` for unmapped keys. An empty pattern maps linearly.
+    pub mapping: Vec<Option<i32>>,
+}
+
+impl KeyboardMapping {
+    pub fn load(path: &Path) -> Result<Self, TuningError> {
+        Self::parse(&read_file(path)?)
+    }
+
+    pub fn parse(src: &str) -> Result<Self, TuningError> {
+        let mut lines = scala_lines(src).filter(|l| !l.trim().is_empty());
+        let mut next = || lines.next().ok_or(TuningError::UnexpectedEnd);
+
+        let size = parse_int(next()?)?;
+        let first_note = parse_int(next()?)?;
+        let last_note = parse_int(next()?)?;
+        let middle_note = parse_int(next()?)?;
+        let reference_note = parse_int(next()?)?;
+        let freq_line = next()?;
+        let reference_freq = freq_line
+            .split_whitespace()
+            .next()
+            .and_then(|t| t.parse::<f64>().ok())
+            .filter(|f| *f > 0.0)
+            .ok_or_else(|| TuningError::InvalidLine(freq_line.to_string()))?;
+        let octave_degree = parse_int(next()?)?;
+
+        let mut mapping = Vec::new();
+        for _ in 0..size.max(0) {
+            let line = next()?;
+            if line.trim_start().starts_with('x') {
+                mapping.push(None);
+            } else {
+                mapping.push(Some(parse_int(line)?));
+            }
+        }
+
+        Ok(KeyboardMapping {
+            first_note,
+            last_note,
+            middle_note,
+            reference_note,
+            reference_freq,
+            octave_degree,
+            mapping,
+        })
+    }
+
+    ///Default mapping of a scale: linear, degree 0 on middle C, A4 at 440Hz.
+    fn linear(scale: &Scale) -> Self {
+        KeyboardMapping {
+            first_note: 0,
+            last_note: 127,
+            middle_note: 60,
+            reference_note: 69,
+            reference_freq: ABS_BASE_FREQ as f64,
+            octave_degree: scale.len(),
+            mapping: Vec::new(),
+        }
+    }
+
+    ///Scale degree of `note`, or `None` if it is not mapped.
+    fn degree(&self, note: i32) -> Option<i32> {
+        let offset = note.checked_sub(self.middle_note)?;
+        if self.mapping.is_empty() {
+            return Some(offset);
+        }
+        let size = self.mapping.len() as i32;
+        let period = offset.div_euclid(size);
+        self.mapping[offset.rem_euclid(size) as usize]
+            .and_then(|d| period.checked_mul(self.octave_degree)?.checked_add(d))
+    }
+}
+
+///Maps note numbers to frequencies. Without a scale this is 12-TET.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct Tuning {
+    pub scale: Option<Scale>,
+    ///Keyboard mapping of the scale. Only used if a scale is set.
+    pub mapping: Option<KeyboardMapping>,
+}
+
+impl Tuning {
+    ///Frequency of `note`, or `None` if the note is not mapped. `reference_pitch` is the frequency of A4 and
+    /// transposes all tunings relative to 440Hz.
+    pub fn note_to_freq(&self, note: u8, reference_pitch: f32) -> Option<f32> {
+        let transpose = reference_pitch as f64 / ABS_BASE_FREQ as f64;
+        let scale = match &self.scale {
+            Some(s) if !s.pitches.is_empty() => s,
+            _ => {
+                return Some(
+                    (reference_pitch as f64 * 2.0f64.powf((note as f64 - 69.0) / 12.0)) as f32,
+                )
+            }
+        };
+
+        let linear;
+        let mapping = match &self.mapping {
+            Some(m) => m,
+            None => {
+                linear = KeyboardMapping::linear(scale);
+                &linear
+            }
+        };
+
+        let note = note as i32;
+        if note < mapping.first_note || note > mapping.last_note {
+            return None;
+        }
+        let degree = mapping.degree(note)?;
+        //If the reference note itself is unmapped, fall back to its linear degree.
+        let reference_degree = mapping
+            .degree(mapping.reference_note)
+            .unwrap_or(mapping.reference_note.saturating_sub(mapping.middle_note));
+        let cents = scale.cents(degree) - scale.cents(reference_degree);
+        Some((mapping.reference_freq * transpose * 2.0f64.powf(cents / 1200.0)) as f32)
+    }
+
+    ///Short human readable description.
+    pub fn name(&self) -> String {
+        match &self.scale {
+            Some(s) if !s.description.is_empty() => s.description.clone(),
+            Some(s) => format!("{} note scale", s.pitches.len()),
+            None => "12-TET".to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{KeyboardMapping, Scale, Tuning, TuningError};
+
+    fn assert_close(a: f64, b: f64) {
+        assert!((a - b).abs() < 1e-3, "{} != {}", a, b);
+    }
+
+    fn tuning(scl: &str, kbm: Option<&str>) -> Tuning {
+        Tuning {
+            scale: Some(Scale::parse(scl).unwrap()),
+            mapping: kbm.map(|kbm| KeyboardMapping::parse(kbm).unwrap()),
+        }
+    }
+
+    fn freq(tuning: &Tuning, note: u8) -> f64 {
+        tuning.note_to_freq(note, 440.0).unwrap() as f64
+    }
+
+    const MAJOR: &str =
+        "! major.scl\n!\nJust major scale\n 7\n!\n9/8\n5/4\n4/3\n3/2\n5/3\n15/8\n2/1\n";
+
+    #[test]
+    fn comments_and_description() {
+        let scale = Scale::parse(MAJOR).unwrap();
+        assert_eq!(scale.description, "Just major scale");
+        assert_eq!(scale.pitches.len(), 7);
+
+        //The description may be empty, the line is still required
+        let scale = Scale::parse("!\r\n\r\n1\r\n2/1\r\n").unwrap();
+        assert_eq!(scale.description, "");
+        assert_eq!(scale.pitches, vec![1200.0]);
+    }
+
+    #[test]
+    fn cents_and_ratios() {
+        let scale = Scale::parse("\n5\n701.955 fifth\n3/2\n-100.0\n3\n2.\n").unwrap();
+        assert_close(scale.pitches[0], 701.955);
+        assert_close(scale.pitches[1], 701.955);
+        assert_close(scale.pitches[2], -100.0);
+        //An integer is a ratio, with a `.` it is cents
+        assert_close(scale.pitches[3], 1901.955);
+        assert_close(scale.pitches[4], 2.0);
+    }
+
+    ///The last degree is the period, the scale repeats with it.
+    #[test]
+    fn octave_degree() {
+        //Linear, A4 is degree 9, the major third above the octave
+        let major = tuning(MAJOR, None);
+        let c4 = 440.0 / 2.0 / (5.0 / 4.0);
+        assert_close(freq(&major, 60), c4);
+        assert_close(freq(&major, 66), c4 * 15.0 / 8.0);
+        assert_close(freq(&major, 67), 2.0 * c4);
+        assert_close(freq(&major, 53), c4 / 2.0);
+
+        //Bohlen-Pierce, repeats at the tritave
+        let bp = tuning("\n2\n9/7\n3/1\n", None);
+        assert_close(freq(&bp, 69), 440.0);
+        assert_close(freq(&bp, 71), 3.0 * 440.0);
+        assert_close(freq(&bp, 70), 3.0 * 440.0 / (9.0 / 7.0));
+        assert_close(freq(&bp, 72), 9.0 * 440.0 / (9.0 / 7.0));
+    }
+
+    ///Reference note and frequency, and the unmapped keys of a mapping.
+    #[test]
+    fn keyboard_mapping() {
+        //White keys of the major scale, reference C4 at 256Hz
+        let kbm = "! white.kbm\n12\n0\n127\n60\n60\n256.0\n7\n0\nx\n1\nx\n2\n3\nx\n4\nx\n5\nx\n6\n";
+        let mapping = KeyboardMapping::parse(kbm).unwrap();
+        assert_eq!(mapping.mapping.len(), 12);
+        assert_eq!(mapping.mapping[1], None);
+        assert_eq!(mapping.mapping[2], Some(1));
+
+        let white = tuning(MAJOR, Some(kbm));
+        assert_close(freq(&white, 60), 256.0);
+        assert_close(freq(&white, 62), 256.0 * 9.0 / 8.0);
+        assert_close(freq(&white, 67), 256.0 * 3.0 / 2.0);
+        assert_close(freq(&white, 72), 512.0);
+        assert_close(freq(&white, 59), 256.0 * 15.0 / 16.0);
+        assert_eq!(white.note_to_freq(61, 440.0), None);
+        assert_eq!(white.note_to_freq(70, 440.0), None);
+        //Transposed with the reference pitch
+        assert_close(white.note_to_freq(60, 880.0).unwrap() as f64, 512.0);
+
+        //Outside the keyboard range
+        let range = "0\n48\n72\n60\n69\n440\n7\n";
+        let range = tuning(MAJOR, Some(range));
+        assert_eq!(range.note_to_freq(47, 440.0), None);
+        assert_eq!(range.note_to_freq(73, 440.0), None);
+        assert_close(freq(&range, 69), 440.0);
+    }
+
+    #[test]
+    fn malformed() {
+        use TuningError::*;
+
+        assert_eq!(Scale::parse(""), Err(UnexpectedEnd));
+        assert_eq!(Scale::parse("desc\n"), Err(UnexpectedEnd));
+        assert_eq!(Scale::parse("desc\n0\n"), Err(EmptyScale));
+        assert_eq!(Scale::parse("desc\n-3\n"), Err(EmptyScale));
+        assert_eq!(
+            Scale::parse("desc\nseven\n"),
+            Err(InvalidLine("seven".to_string()))
+        );
+        assert_eq!(Scale::parse("desc\n2000000000\n2/1\n"), Err(UnexpectedEnd));
+        for pitch in [
+            "", "abc", "1.2.3", "3/", "/2", "0/1", "3/0", "-3/2", "1/2/3",
+        ] {
+            assert_eq!(
+                Scale::parse(&format!("desc\n1\n{}\n", pitch)),
+                Err(InvalidLine(pitch.to_string())),
+                "{}",
+                pitch
+            );
+        }
+
+        assert_eq!(KeyboardMapping::parse(""), Err(UnexpectedEnd));
+        assert_eq!(
+            KeyboardMapping::parse("0\n0\n127\n60\n69\n-440\n12\n"),
+            Err(InvalidLine("-440".to_string()))
+        );
+        assert_eq!(
+            KeyboardMapping::parse("2000000000\n0\n127\n60\n69\n440\n12\n0\n"),
+            Err(UnexpectedEnd)
+        );
+        assert_eq!(
+            KeyboardMapping::parse("1\n0\n127\n60\n69\n440\n12\ny\n"),
+            Err(InvalidLine("y".to_string()))
+        );
+    }
+
+    ///Parsable, but nonsensical mappings must not panic.
+    #[test]
+    fn extreme_mapping() {
+        let extremes = [i32::MIN, -1, 0, 1, i32::MAX];
+        for &middle_note in &extremes {
+            for &reference_note in &extremes {
+                for &octave_degree in &extremes {
+                    let kbm = format!(
+                        "2\n0\n127\n{}\n{}\n440\n{}\n{}\nx\n",
+                        middle_note, reference_note, octave_degree, octave_degree
+                    );
+                    let tuning = tuning(MAJOR, Some(&kbm));
+                    for note in 0..=127 {
+                        tuning.note_to_freq(note, 440.0);
+                    }
+                }
+            }
+        }
+    }
+}
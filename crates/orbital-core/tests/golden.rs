@@ -0,0 +1,357 @@
+//!Golden-audio regression tests. Reference patches are rendered into memory and compared against the renders
+//! checked in at `tests/golden`, with tolerances on the loudness over time and on the spectrum. So refactors of
+//! the DSP (new kernels, oversampling, ...) can't change the sound unnoticed.
+//!
+//! Patches are played through [Engine], which the plugin and `orbital-render` drive the synth with. So the
+//! timing of events within a block is covered as well, not only the oscillators.
+//!
+//! After an intended change of the sound, rewrite the references with `ORBITAL_BLESS=1 cargo test --test golden`.
+
+use std::{f32::consts::PI, fs, path::PathBuf};
+
+use orbital_core::{
+    envelope::EnvelopeParams,
+    gain::GainType,
+    mod_matrix::{ModDestination, ModSource, Routing},
+    osc::{
+        modulator::{ModulatorOsc, ParentIndex},
+        primary::PrimaryOsc,
+        ModulationType,
+    },
+    osc_array::OscArray,
+    state::{ModulatorState, PrimaryState, SolarState},
+    Engine, Event,
+};
+
+const SAMPLE_RATE: f32 = 32000.0;
+///Length of a render in samples
+const LENGTH: usize = 8192;
+///Sample at which the notes are released
+const RELEASE_AT: usize = 6144;
+const BLOCK_SIZE: usize = 512;
+
+///Length of the windows the RMS is compared over
+const RMS_WINDOW: usize = 512;
+///Allowed relative RMS difference per window
+const RMS_TOLERANCE: f32 = 0.02;
+///Windows quieter than this are not compared relatively
+const RMS_FLOOR: f32 = 1e-3;
+
+const FFT_SIZE: usize = 1024;
+///Allowed difference per spectral band in dB
+const SPECTRAL_TOLERANCE: f32 = 1.5;
+///Bands this far below the loudest one are ignored
+const SPECTRAL_RANGE: f32 = 60.0;
+
+///Planets around the sun as `(speed_index, volume)`, and moons as `(parent, speed_index, range)`.
+struct Patch {
+    primaries: &'static [(f32, f32)],
+    modulators: &'static [(ParentIndex, f32, f32)],
+    mod_ty: ModulationType,
+    notes: &'static [u8],
+    ///Offset of each note into the first block. The notes are released at the same offset into the block at
+    /// [RELEASE_AT]. Notes without an offset start at 0.
+    offsets: &'static [usize],
+    routings: &'static [Routing],
+}
+
+impl Patch {
+    fn state(&self) -> SolarState {
+        let mut state = SolarState::default();
+        for (slot, (speed_index, volume)) in self.primaries.iter().enumerate() {
+            state.primary_states.push(PrimaryState {
+                offset: 0.0,
+                state: PrimaryOsc {
+                    speed_index: *speed_index,
+                    volume: *volume,
+                    is_on: true,
+                },
+                slot,
+            });
+        }
+        for (slot, (parent, speed_index, range)) in self.modulators.iter().enumerate() {
+            state.modulator_states.push(ModulatorState {
+                offset: 0.0,
+                state: ModulatorOsc {
+                    parent_osc_slot: *parent,
+                    is_on: true,
+                    range: *range,
+                    speed_index: *speed_index,
+                },
+                slot,
+            });
+        }
+        state
+    }
+
+    fn offset(&self, note: usize) -> usize {
+        self.offsets.get(note).copied().unwrap_or(0)
+    }
+
+    ///Plays all notes within the first block and releases them within the block at [RELEASE_AT].
+    fn render(&self) -> Vec<f32> {
+        let mut engine = Engine::new(OscArray::default(), SAMPLE_RATE);
+        engine.synth.set_envelopes(EnvelopeParams {
+            delay: 0.0,
+            attack: 0.01,
+            hold: 0.0,
+            decay: 0.05,
+            sustain_level: 0.7,
+            release: 0.05,
+        });
+        engine.synth.bank.mod_ty = self.mod_ty.clone();
+        engine.synth.bank.on_state_change(&self.state());
+        engine.synth.matrix.routings = self.routings.to_vec();
+
+        for (idx, note) in self.notes.iter().enumerate() {
+            engine.handle(
+                Event::NoteOn {
+                    note: *note,
+                    velocity: 0.8,
+                },
+                self.offset(idx),
+            );
+        }
+        let mut out = vec![0.0; LENGTH];
+        let (held, released) = out.split_at_mut(RELEASE_AT);
+        for block in held.chunks_mut(BLOCK_SIZE) {
+            engine.render_mono(block);
+        }
+        for (idx, note) in self.notes.iter().enumerate() {
+            engine.handle(Event::NoteOff { note: *note }, self.offset(idx));
+        }
+        for block in released.chunks_mut(BLOCK_SIZE) {
+            engine.render_mono(block);
+        }
+        out
+    }
+}
+
+const SINGLE_PLANET: Patch = Patch {
+    primaries: &[(0.0, 0.8)],
+    modulators: &[],
+    mod_ty: ModulationType::Relative,
+    notes: &[57],
+    offsets: &[],
+    routings: &[],
+};
+
+const PLANET_AND_MOON: Patch = Patch {
+    primaries: &[(0.0, 0.8)],
+    modulators: &[(ParentIndex::Primary(0), 1.0, 0.4)],
+    mod_ty: ModulationType::Relative,
+    notes: &[57],
+    offsets: &[],
+    routings: &[],
+};
+
+const PLANET_AND_MOON_ABSOLUTE: Patch = Patch {
+    mod_ty: ModulationType::Absolute,
+    ..PLANET_AND_MOON
+};
+
+///Two planets, one with a moon that is modulated by a second moon, played as a chord.
+const CHORD: Patch = Patch {
+    primaries: &[(0.0, 0.6), (-1.0, 0.4)],
+    modulators: &[
+        (ParentIndex::Primary(0), 2.0, 0.3),
+        (ParentIndex::Modulator(0), -0.5, 0.5),
+    ],
+    mod_ty: ModulationType::Relative,
+    notes: &[48, 55, 60, 64],
+    offsets: &[],
+    routings: &[],
+};
+
+///The chord, with notes that start and end within blocks.
+const STAGGERED_CHORD: Patch = Patch {
+    offsets: &[0, 37, 101, 263],
+    ..CHORD
+};
+
+fn reference_path(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("golden")
+        .join(format!("{}.f32", name))
+}
+
+///References are stored as raw little endian f32 samples.
+fn load_reference(name: &str) -> Vec<f32> {
+    let path = reference_path(name);
+    let bytes = fs::read(&path).unwrap_or_else(|e| {
+        panic!(
+            "Could not read {:?} ({}). Create it with ORBITAL_BLESS=1",
+            path, e
+        )
+    });
+    bytes
+        .chunks_exact(4)
+        .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .collect()
+}
+
+fn store_reference(name: &str, samples: &[f32]) {
+    let bytes: Vec<u8> = samples.iter().flat_map(|s| s.to_le_bytes()).collect();
+    fs::write(reference_path(name), bytes).expect("Could not write reference");
+}
+
+fn rms(samples: &[f32]) -> f32 {
+    (samples.iter().map(|s| s * s).sum::<f32>() / samples.len().max(1) as f32).sqrt()
+}
+
+///Energy in dB of a Hann windowed frame, summed into bands that get wider towards the top, so each band
+/// covers roughly the same musical range.
+fn band_energies(frame: &[f32]) -> Vec<f32> {
+    let windowed: Vec<f32> = frame
+        .iter()
+        .enumerate()
+        .map(|(i, s)| s * (0.5 - 0.5 * (2.0 * PI * i as f32 / FFT_SIZE as f32).cos()))
+        .collect();
+
+    let mut bands = Vec::new();
+    let (mut start, mut width) = (1, 1);
+    while start < FFT_SIZE / 2 {
+        let end = (start + width).min(FFT_SIZE / 2);
+        let energy: f32 = (start..end)
+            .map(|bin| {
+                //Plain DFT, fast enough for a few frames
+                let (mut re, mut im) = (0.0f32, 0.0f32);
+                for (i, s) in windowed.iter().enumerate() {
+                    let angle = 2.0 * PI * ((bin * i) % FFT_SIZE) as f32 / FFT_SIZE as f32;
+                    re += s * angle.cos();
+                    im -= s * angle.sin();
+                }
+                re * re + im * im
+            })
+            .sum();
+        bands.push(10.0 * (energy + 1e-12).log10());
+        start = end;
+        width = (width * 5 / 4).max(width + 1);
+    }
+    bands
+}
+
+fn assert_matches_reference(name: &str, out: &[f32]) {
+    assert!(
+        out.iter().all(|s| s.is_finite()),
+        "{}: render is not finite",
+        name
+    );
+    assert!(rms(out) > RMS_FLOOR, "{}: render is silent", name);
+
+    if std::env::var_os("ORBITAL_BLESS").is_some() {
+        store_reference(name, out);
+        return;
+    }
+
+    let reference = load_reference(name);
+    assert_eq!(reference.len(), out.len(), "{}: length differs", name);
+
+    for (idx, (a, b)) in reference
+        .chunks(RMS_WINDOW)
+        .zip(out.chunks(RMS_WINDOW))
+        .enumerate()
+    {
+        let (expected, actual) = (rms(a), rms(b));
+        assert!(
+            (expected - actual).abs() <= RMS_TOLERANCE * expected.max(RMS_FLOOR),
+            "{}: RMS of window {} is {}, expected {}",
+            name,
+            idx,
+            actual,
+            expected
+        );
+    }
+
+    //Compare the spectrum while the notes are held, and during the release
+    for start in [FFT_SIZE, RELEASE_AT - FFT_SIZE, RELEASE_AT] {
+        let expected = band_energies(&reference[start..start + FFT_SIZE]);
+        let actual = band_energies(&out[start..start + FFT_SIZE]);
+        let loudest = expected.iter().cloned().fold(f32::MIN, f32::max);
+        for (band, (e, a)) in expected.iter().zip(&actual).enumerate() {
+            if *e < loudest - SPECTRAL_RANGE {
+                continue;
+            }
+            assert!(
+                (e - a).abs() <= SPECTRAL_TOLERANCE,
+                "{}: band {} of the frame at {} is {:.2} dB, expected {:.2} dB",
+                name,
+                band,
+                start,
+                a,
+                e
+            );
+        }
+    }
+}
+
+#[test]
+fn single_planet() {
+    assert_matches_reference("single_planet", &SINGLE_PLANET.render());
+}
+
+#[test]
+fn planet_and_moon_relative() {
+    assert_matches_reference("planet_and_moon_relative", &PLANET_AND_MOON.render());
+}
+
+#[test]
+fn planet_and_moon_absolute() {
+    assert_matches_reference(
+        "planet_and_moon_absolute",
+        &PLANET_AND_MOON_ABSOLUTE.render(),
+    );
+}
+
+#[test]
+fn chord() {
+    assert_matches_reference("chord", &CHORD.render());
+}
+
+#[test]
+fn staggered_chord() {
+    assert_matches_reference("staggered_chord", &STAGGERED_CHORD.render());
+}
+
+///Routings to a moon are heard in absolute modulation mode as well, where the moons are otherwise shared by all
+/// voices. Velocity raises the moon's range by the same amount as the static range of the reference.
+#[test]
+fn absolute_modulator_routing() {
+    let routed = Patch {
+        routings: &[Routing {
+            source: ModSource::Velocity,
+            destination: ModDestination::Radius(ParentIndex::Modulator(0)),
+            amount: 0.5,
+        }],
+        ..PLANET_AND_MOON_ABSOLUTE
+    }
+    .render();
+    let wider = Patch {
+        modulators: &[(ParentIndex::Primary(0), 1.0, 0.8)],
+        ..PLANET_AND_MOON_ABSOLUTE
+    }
+    .render();
+    let plain = PLANET_AND_MOON_ABSOLUTE.render();
+
+    let difference = |a: &[f32], b: &[f32]| {
+        a.iter()
+            .zip(b)
+            .map(|(a, b)| (a - b).abs())
+            .fold(0.0f32, f32::max)
+    };
+    assert!(difference(&routed, &plain) > 0.01);
+    assert!(difference(&routed, &wider) < 1e-3);
+}
+
+///The composition curve is applied after the synth, like the plugin's output stage does. The chord is driven
+/// into the curve, so the saturating ones actually shape it.
+#[test]
+fn gain_types() {
+    const DRIVE: f32 = 3.0;
+    let chord = CHORD.render();
+    for gain_ty in GainType::ALL {
+        let shaped: Vec<f32> = chord.iter().map(|s| gain_ty.map(s * DRIVE)).collect();
+        assert_matches_reference(&format!("gain_{}", gain_ty.name().to_lowercase()), &shaped);
+    }
+}
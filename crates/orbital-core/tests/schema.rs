@@ -0,0 +1,250 @@
+//!Loads solar systems persisted by every released version. The repository's history starts at 0.2, so its
+//! layout is the oldest one covered. Add a blob to `tests/state` whenever the schema changes.
+
+use std::{fs, path::PathBuf};
+
+use orbital_core::{
+    osc::{modulator::ParentIndex, OscillatorBank},
+    schema::{PersistedOrbital, PersistedSolarSystem, Repair, SchemaError, SCHEMA_VERSION},
+};
+
+fn state_dir() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("state")
+}
+
+fn load(name: &str) -> PersistedSolarSystem {
+    let src = fs::read_to_string(state_dir().join(name)).expect("Could not read state");
+    PersistedSolarSystem::from_json(&src).unwrap_or_else(|e| panic!("{}: {}", name, e))
+}
+
+fn orbital(slot: usize, moons: Vec<PersistedOrbital>) -> PersistedOrbital {
+    PersistedOrbital {
+        slot,
+        radius: 0.5,
+        speed: 0.0,
+        offset: 0.0,
+        muted: false,
+        moons,
+    }
+}
+
+fn assert_close(a: f32, b: f32) {
+    assert!((a - b).abs() < 1e-4, "{} != {}", a, b);
+}
+
+///Structure of the solar system both test blobs store: two planets, the first one with a moon, that has a
+/// moon itself.
+fn assert_structure(system: &PersistedSolarSystem) {
+    assert_eq!(system.planets.len(), 2);
+    let (first, second) = (&system.planets[0], &system.planets[1]);
+    assert_eq!((first.slot, second.slot), (0, 1));
+    assert_close(first.speed, 0.0);
+    assert_close(first.offset, 1.2);
+    assert_close(second.radius, 1.0);
+    assert_close(second.speed, -1.0);
+    assert!(second.moons.is_empty());
+
+    assert_eq!(first.moons.len(), 1);
+    let moon = &first.moons[0];
+    assert_eq!(moon.slot, 0);
+    assert_close(moon.speed, 1.0);
+    assert_eq!(moon.moons.len(), 1);
+    assert_eq!(moon.moons[0].slot, 1);
+    assert_close(moon.moons[0].radius, 0.2);
+    assert_close(moon.moons[0].speed, -2.0);
+}
+
+#[test]
+fn every_blob_loads() {
+    for entry in fs::read_dir(state_dir()).expect("Could not list states") {
+        let path = entry.unwrap().path();
+        let src = fs::read_to_string(&path).unwrap();
+        if let Err(e) = PersistedSolarSystem::from_json(&src) {
+            panic!("{:?}: {}", path, e);
+        }
+    }
+}
+
+///0.2 stored the GUI's orbit radii in pixels, they are mapped to the slot's volume or range.
+#[test]
+fn version_0_2() {
+    let system = load("0.2-solar-system.json");
+    assert_structure(&system);
+    assert_close(system.planets[0].radius, (100.0 - 25.0) / 275.0);
+    assert_close(system.planets[0].moons[0].radius, (50.0 - 25.0) / 75.0);
+    assert!(!system.planets[0].moons[0].muted);
+}
+
+#[test]
+fn version_1() {
+    let system = load("v1-solar-system.json");
+    assert_structure(&system);
+    assert_close(system.planets[0].radius, 0.25);
+    assert!(system.planets[0].moons[0].muted);
+}
+
+#[test]
+fn roundtrip_writes_current_version() {
+    let system = load("0.2-solar-system.json");
+    let json = system.to_json();
+    let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+    assert_eq!(value["version"], SCHEMA_VERSION);
+    assert_eq!(PersistedSolarSystem::from_json(&json), Ok(system));
+}
+
+#[test]
+fn newer_version_is_rejected() {
+    let src = format!(r#"{{"version": {}, "planets": []}}"#, SCHEMA_VERSION + 1);
+    assert_eq!(
+        PersistedSolarSystem::from_json(&src),
+        Err(SchemaError::UnsupportedVersion(SCHEMA_VERSION + 1))
+    );
+}
+
+#[test]
+fn missing_fields_are_reported() {
+    let system = PersistedSolarSystem {
+        planets: vec![PersistedOrbital {
+            slot: 0,
+            radius: 0.5,
+            speed: 0.0,
+            offset: 0.0,
+            muted: false,
+            moons: Vec::new(),
+        }],
+    };
+    assert_eq!(
+        PersistedSolarSystem::from_json(&system.to_json()),
+        Ok(system)
+    );
+    assert!(matches!(
+        PersistedSolarSystem::from_json(r#"{"version": 1, "planets": [{"slot": 0}]}"#),
+        Err(SchemaError::Parse(_))
+    ));
+}
+
+#[test]
+fn valid_system_is_not_repaired() {
+    let mut system = load("v1-solar-system.json");
+    let loaded = system.clone();
+    assert_eq!(system.validate(), Ok(Vec::new()));
+    assert_eq!(system, loaded);
+}
+
+///Duplicated slots are moved to a free slot. The first orbital keeps its slot.
+#[test]
+fn duplicated_slot_is_moved() {
+    let mut system = PersistedSolarSystem {
+        planets: vec![
+            orbital(0, vec![orbital(0, Vec::new())]),
+            orbital(0, vec![orbital(0, Vec::new())]),
+        ],
+    };
+    assert_eq!(
+        system.validate(),
+        Ok(vec![
+            Repair::MovedSlot {
+                from: ParentIndex::Primary(0),
+                to: ParentIndex::Primary(1)
+            },
+            Repair::MovedSlot {
+                from: ParentIndex::Modulator(0),
+                to: ParentIndex::Modulator(1)
+            },
+        ])
+    );
+    assert_eq!((system.planets[0].slot, system.planets[1].slot), (0, 1));
+    assert_eq!(system.planets[1].moons[0].slot, 1);
+}
+
+///An out of range slot doesn't take the slot of an orbital that comes later.
+#[test]
+fn out_of_range_slot_is_moved() {
+    let mut system = PersistedSolarSystem {
+        planets: vec![
+            orbital(OscillatorBank::PRIMARY_OSC_COUNT, Vec::new()),
+            orbital(0, Vec::new()),
+        ],
+    };
+    assert_eq!(
+        system.validate(),
+        Ok(vec![Repair::MovedSlot {
+            from: ParentIndex::Primary(OscillatorBank::PRIMARY_OSC_COUNT),
+            to: ParentIndex::Primary(1)
+        }])
+    );
+    assert_eq!((system.planets[0].slot, system.planets[1].slot), (1, 0));
+}
+
+///Without a free slot the orbital is removed together with its moons.
+#[test]
+fn orbital_is_removed_if_full() {
+    let mut planets: Vec<_> = (0..OscillatorBank::PRIMARY_OSC_COUNT)
+        .map(|slot| orbital(slot, Vec::new()))
+        .collect();
+    planets.push(orbital(3, vec![orbital(0, Vec::new())]));
+    let mut system = PersistedSolarSystem { planets };
+
+    assert_eq!(
+        system.validate(),
+        Ok(vec![Repair::Removed(ParentIndex::Primary(3))])
+    );
+    assert_eq!(system.planets.len(), OscillatorBank::PRIMARY_OSC_COUNT);
+    assert!(system.planets.iter().all(|p| p.moons.is_empty()));
+}
+
+#[test]
+fn invalid_values_are_repaired() {
+    let mut planet = orbital(0, Vec::new());
+    planet.radius = f32::NAN;
+    planet.speed = f32::INFINITY;
+    planet.offset = -1.0;
+    let mut moon = orbital(0, Vec::new());
+    moon.radius = 3.0;
+    moon.speed = -100.0;
+    planet.moons.push(moon);
+    let mut system = PersistedSolarSystem {
+        planets: vec![planet],
+    };
+
+    let repairs = system.validate().unwrap();
+    assert_eq!(repairs.len(), 5);
+    let (planet, moon) = (&system.planets[0], &system.planets[0].moons[0]);
+    assert_eq!((planet.radius, planet.speed), (0.0, 0.0));
+    assert_close(planet.offset, std::f32::consts::TAU - 1.0);
+    assert_eq!((moon.radius, moon.speed), (1.0, -20.0));
+    assert_eq!(system.validate(), Ok(Vec::new()));
+}
+
+///A chain of moons needs one modulator slot per moon, a longer one can't be repaired.
+#[test]
+fn too_deep_chain_is_rejected() {
+    let chain = |len: usize| {
+        let mut moon = orbital(0, Vec::new());
+        for slot in 1..len {
+            moon = orbital(slot, vec![moon]);
+        }
+        PersistedSolarSystem {
+            planets: vec![orbital(0, vec![moon])],
+        }
+    };
+    assert!(chain(OscillatorBank::MOD_OSC_COUNT).validate().is_ok());
+    assert!(matches!(
+        chain(OscillatorBank::MOD_OSC_COUNT + 1).validate(),
+        Err(SchemaError::InvalidGraph(_))
+    ));
+}
+
+///0.2 states store the type of each orbital, which has to match its place in the tree.
+#[test]
+fn version_0_2_invalid_type_is_rejected() {
+    let src = fs::read_to_string(state_dir().join("0.2-solar-system.json")).unwrap();
+    let mut value: serde_json::Value = serde_json::from_str(&src).unwrap();
+    value["orbitals"][0]["children"][0]["obj"] = "Planet".into();
+    assert!(matches!(
+        orbital_core::schema::migrate(value),
+        Err(SchemaError::InvalidGraph(_))
+    ));
+}
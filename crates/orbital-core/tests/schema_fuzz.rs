@@ -0,0 +1,238 @@
+//!Feeds arbitrary states to the schema, like a corrupted project or a hand edited preset would. Loading may
+//! fail, but must not panic, and a validated solar system must be usable by the synth.
+
+use std::{fs, path::PathBuf};
+
+use orbital_core::{
+    osc::OscillatorBank,
+    schema::{PersistedOrbital, PersistedSolarSystem, SPEED_RANGE},
+    TWOPI,
+};
+use serde_json::{json, Map, Value};
+
+const ITERATIONS: usize = 2000;
+
+///Xorshift, so failures are reproducible without depending on a random crate.
+struct Rng(u32);
+
+impl Rng {
+    fn next(&mut self) -> u32 {
+        let mut x = self.0;
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.0 = x;
+        x
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        self.next() as usize % n
+    }
+
+    fn chance(&mut self, percent: u32) -> bool {
+        self.next() % 100 < percent
+    }
+
+    ///Slot, mostly in range. Rarely of the wrong type.
+    fn slot(&mut self) -> Value {
+        if self.chance(1) {
+            return self.value();
+        }
+        match self.below(5) {
+            0 => json!(self.next()),
+            1 => json!(self.below(OscillatorBank::PRIMARY_OSC_COUNT)),
+            _ => json!(self.below(OscillatorBank::MOD_OSC_COUNT)),
+        }
+    }
+
+    ///Float, some huge or out of range. Rarely of the wrong type.
+    fn float(&mut self) -> Value {
+        if self.chance(1) {
+            return self.value();
+        }
+        match self.below(8) {
+            0 => json!(f32::MAX),
+            1 => json!(-1e30),
+            2 => json!(1e300),
+            3 | 4 => json!((self.next() as f32 / u32::MAX as f32 - 0.5) * 100.0),
+            _ => json!(self.next() as f32 / u32::MAX as f32),
+        }
+    }
+
+    ///Inserts a value under `key`, unless the field is randomly left out.
+    fn field(
+        &mut self,
+        map: &mut Map<String, Value>,
+        key: &str,
+        value: impl FnOnce(&mut Self) -> Value,
+    ) {
+        if !self.chance(1) {
+            let value = value(self);
+            map.insert(key.to_owned(), value);
+        }
+    }
+
+    ///Value of any type.
+    fn value(&mut self) -> Value {
+        match self.below(6) {
+            0 => Value::Null,
+            1 => json!(self.chance(50)),
+            2 => json!(-(self.below(100) as i64)),
+            3 => json!("Planet"),
+            4 => json!([]),
+            _ => json!({}),
+        }
+    }
+}
+
+///Moons of an orbital at `depth`. Below the planets' moons, the tree continues as a chain, so it stays small
+/// but sometimes gets deeper than there are modulator slots.
+fn children(rng: &mut Rng, depth: usize, orbital: fn(&mut Rng, usize) -> Value) -> Value {
+    let count = match depth {
+        0 | 1 => rng.below(4),
+        _ => rng.chance(90) as usize,
+    };
+    Value::Array((0..count).map(|_| orbital(rng, depth + 1)).collect())
+}
+
+fn orbital_v0(rng: &mut Rng, depth: usize) -> Value {
+    let mut map = Map::new();
+    rng.field(&mut map, "radius", Rng::float);
+    rng.field(&mut map, "offset", Rng::float);
+    rng.field(&mut map, "speed_index", Rng::float);
+    //Mostly the type the 0.2 GUI would have created
+    let obj = match (rng.chance(5), depth) {
+        (true, _) => ["Sun", "Planet", "Moon", "Astroid", "Comet"][rng.below(5)],
+        (false, 0) => "Planet",
+        (false, 1) => "Moon",
+        (false, _) => "Astroid",
+    };
+    rng.field(&mut map, "obj", |_| json!(obj));
+    rng.field(&mut map, "osc_slot", Rng::slot);
+    rng.field(&mut map, "children", |rng| children(rng, depth, orbital_v0));
+    Value::Object(map)
+}
+
+fn orbital_v1(rng: &mut Rng, depth: usize) -> Value {
+    let mut map = Map::new();
+    rng.field(&mut map, "slot", Rng::slot);
+    rng.field(&mut map, "radius", Rng::float);
+    rng.field(&mut map, "speed", Rng::float);
+    rng.field(&mut map, "offset", Rng::float);
+    if rng.chance(50) {
+        let muted = rng.chance(50);
+        map.insert("muted".to_owned(), json!(muted));
+    }
+    if rng.chance(80) {
+        let moons = children(rng, depth, orbital_v1);
+        map.insert("moons".to_owned(), moons);
+    }
+    Value::Object(map)
+}
+
+fn system(rng: &mut Rng) -> Value {
+    let version = match rng.below(5) {
+        0 => None,
+        1 => Some(json!(0)),
+        2 => Some(rng.value()),
+        3 => Some(json!(rng.next())),
+        _ => Some(json!(1)),
+    };
+    let (key, orbital): (_, fn(&mut Rng, usize) -> Value) = match version {
+        None => ("orbitals", orbital_v0),
+        Some(ref v) if v == 0 => ("orbitals", orbital_v0),
+        _ => ("planets", orbital_v1),
+    };
+    let mut map = Map::new();
+    if let Some(version) = version {
+        map.insert("version".to_owned(), version);
+    }
+    rng.field(&mut map, key, |rng| {
+        Value::Array((0..rng.below(12)).map(|_| orbital(rng, 0)).collect())
+    });
+    Value::Object(map)
+}
+
+fn assert_valid_orbital(orbital: &PersistedOrbital, slots: &mut [bool]) {
+    assert!(
+        slots.get(orbital.slot) == Some(&false),
+        "Slot {} invalid",
+        orbital.slot
+    );
+    slots[orbital.slot] = true;
+    assert!((0.0..=1.0).contains(&orbital.radius), "{:?}", orbital);
+    assert!(
+        (SPEED_RANGE.0..=SPEED_RANGE.1).contains(&orbital.speed),
+        "{:?}",
+        orbital
+    );
+    assert!((0.0..=TWOPI).contains(&orbital.offset), "{:?}", orbital);
+}
+
+fn assert_valid_moons(moons: &[PersistedOrbital], slots: &mut [bool]) {
+    for moon in moons {
+        assert_valid_orbital(moon, slots);
+        assert_valid_moons(&moon.moons, slots);
+    }
+}
+
+///Loads and validates `src`. If both succeed, the solar system must be valid and stay unchanged when it is
+/// validated again.
+fn check(src: &str) {
+    let mut system = match PersistedSolarSystem::from_json(src) {
+        Ok(system) => system,
+        Err(_) => return,
+    };
+    if system.validate().is_err() {
+        return;
+    }
+
+    let mut primaries = [false; OscillatorBank::PRIMARY_OSC_COUNT];
+    let mut modulators = [false; OscillatorBank::MOD_OSC_COUNT];
+    for planet in &system.planets {
+        assert_valid_orbital(planet, &mut primaries);
+        assert_valid_moons(&planet.moons, &mut modulators);
+    }
+
+    let validated = system.clone();
+    assert_eq!(system.validate(), Ok(Vec::new()));
+    assert_eq!(system, validated);
+    assert_eq!(
+        PersistedSolarSystem::from_json(&system.to_json()),
+        Ok(system)
+    );
+}
+
+#[test]
+fn random_states() {
+    let mut rng = Rng(0x2545_f491);
+    for _ in 0..ITERATIONS {
+        check(&system(&mut rng).to_string());
+    }
+}
+
+///Flips, inserts and removes bytes of the stored states.
+#[test]
+fn mutated_states() {
+    let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("state");
+    let mut rng = Rng(0x9e37_79b9);
+    for entry in fs::read_dir(dir).expect("Could not list states") {
+        let src = fs::read(entry.unwrap().path()).unwrap();
+        for _ in 0..ITERATIONS {
+            let mut bytes = src.clone();
+            for _ in 0..=rng.below(4) {
+                let at = rng.below(bytes.len());
+                match rng.below(3) {
+                    0 => bytes[at] = rng.next() as u8,
+                    1 => bytes.insert(at, b"0123456789-.,:[]{}\"e"[rng.below(20)]),
+                    _ => {
+                        bytes.remove(at);
+                    }
+                }
+            }
+            check(&String::from_utf8_lossy(&bytes));
+        }
+    }
+}
@@ -0,0 +1,130 @@
+use std::{path::PathBuf, process::ExitCode};
+
+use hound::{SampleFormat, WavSpec, WavWriter};
+use orbital::offline::{load_params, midi_events, OfflineRenderer};
+
+const USAGE: &str = "Usage: orbital-render <state.json> <input.mid> <output.wav> [--sample-rate <Hz>] [--bits <16|24|32>] [--tail <seconds>]
+
+Renders a standard midi file through a saved plugin state, with the same signal chain as the plugin: synth,
+output stage and effects. 32 bit output is written as float.";
+
+///Same channel layout as the plugin
+const CHANNELS: u16 = 2;
+
+struct Args {
+    state: PathBuf,
+    midi: PathBuf,
+    output: PathBuf,
+    sample_rate: u32,
+    bits: u16,
+    ///Seconds rendered after the last event
+    tail: f64,
+}
+
+fn parse_args() -> Result<Args, String> {
+    let mut positional = Vec::new();
+    let mut sample_rate = 48000;
+    let mut bits = 24;
+    let mut tail = 2.0;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| {
+            args.next()
+                .ok_or_else(|| format!("Missing value for {}", name))
+        };
+        match arg.as_str() {
+            "--sample-rate" => {
+                sample_rate = value(&arg)?
+                    .parse()
+                    .map_err(|_| "Invalid sample rate".to_string())?
+            }
+            "--bits" => {
+                bits = value(&arg)?
+                    .parse()
+                    .map_err(|_| "Invalid bit depth".to_string())?
+            }
+            "--tail" => {
+                tail = value(&arg)?
+                    .parse()
+                    .map_err(|_| "Invalid tail length".to_string())?
+            }
+            "-h" | "--help" => return Err(USAGE.to_string()),
+            _ => positional.push(PathBuf::from(&arg)),
+        }
+    }
+
+    if ![16, 24, 32].contains(&bits) {
+        return Err(format!("Unsupported bit depth {}", bits));
+    }
+    if sample_rate == 0 {
+        return Err("Sample rate must be positive".to_string());
+    }
+    let [state, midi, output]: [PathBuf; 3] =
+        positional.try_into().map_err(|_| USAGE.to_string())?;
+
+    Ok(Args {
+        state,
+        midi,
+        output,
+        sample_rate,
+        bits,
+        tail: tail.max(0.0),
+    })
+}
+
+fn write_wav(args: &Args, [left, right]: &[Vec<f32>; 2]) -> Result<(), hound::Error> {
+    let spec = WavSpec {
+        channels: CHANNELS,
+        sample_rate: args.sample_rate,
+        bits_per_sample: args.bits,
+        sample_format: if args.bits == 32 {
+            SampleFormat::Float
+        } else {
+            SampleFormat::Int
+        },
+    };
+    let mut writer = WavWriter::create(&args.output, spec)?;
+    let max = ((1i32 << (args.bits - 1)) - 1) as f32;
+    for (l, r) in left.iter().zip(right) {
+        for sample in [l, r] {
+            if args.bits == 32 {
+                writer.write_sample(*sample)?;
+            } else {
+                writer.write_sample((sample.clamp(-1.0, 1.0) * max).round() as i32)?;
+            }
+        }
+    }
+    writer.finalize()
+}
+
+fn run() -> Result<(), String> {
+    let args = parse_args()?;
+    let params = load_params(&args.state).map_err(|e| e.to_string())?;
+    let midi = std::fs::read(&args.midi)
+        .map_err(|e| format!("Could not read {}: {}", args.midi.display(), e))?;
+    let events = midi_events(&midi).map_err(|e| e.to_string())?;
+
+    let mut renderer = OfflineRenderer::new(params, args.sample_rate as f32);
+    let channels = renderer.render_events(&events, args.tail);
+    write_wav(&args, &channels)
+        .map_err(|e| format!("Could not write {}: {}", args.output.display(), e))?;
+
+    println!(
+        "Rendered {} events, {:.2}s to {}",
+        events.len(),
+        channels[0].len() as f64 / args.sample_rate as f64,
+        args.output.display()
+    );
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    match run() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -1,53 +1,75 @@
-use nih_plug::prelude::Enum;
-use serde::{Deserialize, Serialize};
+use orbital_core::{
+    mod_matrix::ModMatrix,
+    osc::{
+        modulator::{ModulatorOsc, ParentIndex},
+        primary::PrimaryOsc,
+        OscillatorBank,
+    },
+    state::{ModulatorState, PrimaryState, SolarState},
+    tuning::Tuning,
+};
 
-use crate::osc::{modulator::ModulatorOsc, primary::PrimaryOsc, sigmoid, ModulationType};
+use crate::{fx::FxOrder, macros::MacroAssignment, slots::SlotValues};
 
-#[derive(Clone)]
-pub struct SolarState {
-    pub primary_states: Vec<PrimaryState>,
-    pub modulator_states: Vec<ModulatorState>,
-}
-
-#[derive(Clone)]
-pub struct PrimaryState {
-    pub offset: f32,
-    pub state: PrimaryOsc,
-    pub slot: usize,
-}
-
-#[derive(Clone)]
-pub struct ModulatorState {
-    pub offset: f32,
-    pub state: ModulatorOsc,
-    pub slot: usize,
-}
-
-#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Enum)]
-pub enum GainType {
-    Sigmoid,
-    Linear,
+///Structure of the solar system, without any of the orbital's values. Those are
+/// host parameters, see [slots](crate::slots).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SolarTopology {
+    ///True for each primary slot that has a planet
+    pub primaries: [bool; OscillatorBank::PRIMARY_OSC_COUNT],
+    ///Parent of each modulator slot, `None` if the slot is unused
+    pub modulators: [Option<ParentIndex>; OscillatorBank::MOD_OSC_COUNT],
 }
 
-impl Default for GainType {
+impl Default for SolarTopology {
     fn default() -> Self {
-        GainType::Sigmoid
+        SolarTopology {
+            primaries: [false; OscillatorBank::PRIMARY_OSC_COUNT],
+            modulators: [None; OscillatorBank::MOD_OSC_COUNT],
+        }
     }
 }
 
-impl GainType {
-    #[inline(always)]
-    pub fn map(&self, value: f32) -> f32 {
-        match self {
-            GainType::Sigmoid => sigmoid(value),
-            GainType::Linear => value.clamp(-1.0, 1.0),
+impl SolarTopology {
+    ///Rebuilds `state` from this topology and the slot values. Does not allocate if `state` was created
+    /// via `default()`.
+    pub fn fill_state(
+        &self,
+        state: &mut SolarState,
+        primaries: &[SlotValues],
+        modulators: &[SlotValues],
+    ) {
+        state.primary_states.clear();
+        state.modulator_states.clear();
+
+        for (slot, (used, values)) in self.primaries.iter().zip(primaries).enumerate() {
+            if !*used {
+                continue;
+            }
+            state.primary_states.push(PrimaryState {
+                offset: values.offset,
+                slot,
+                state: PrimaryOsc {
+                    speed_index: values.speed,
+                    volume: values.radius,
+                    is_on: values.on,
+                },
+            });
         }
-    }
 
-    pub fn next(&mut self) {
-        match self {
-            GainType::Linear => *self = GainType::Sigmoid,
-            GainType::Sigmoid => *self = GainType::Linear,
+        for (slot, (parent, values)) in self.modulators.iter().zip(modulators).enumerate() {
+            if let Some(parent) = parent {
+                state.modulator_states.push(ModulatorState {
+                    offset: values.offset,
+                    slot,
+                    state: ModulatorOsc {
+                        parent_osc_slot: *parent,
+                        speed_index: values.speed,
+                        range: values.radius,
+                        is_on: values.on,
+                    },
+                });
+            }
         }
     }
 }
@@ -55,7 +77,9 @@ impl GainType {
 ///Communication messages from the renderer to the oscillator bank.
 #[derive(Clone)]
 pub enum ComMsg {
-    StateChange(SolarState),
-    ModRelationChanged(ModulationType),
-    GainChange(GainType),
+    TopologyChange(SolarTopology),
+    ModMatrixChange(ModMatrix),
+    MacroChange(Vec<MacroAssignment>),
+    FxOrderChange(FxOrder),
+    TuningChange(Tuning),
 }
@@ -0,0 +1,139 @@
+use std::sync::Arc;
+
+use nih_plug::prelude::{BoolParam, EnumParam, FloatParam, FloatRange, Params};
+use orbital_core::{
+    envelope::EnvelopeParams,
+    filter::{FilterMode, FilterSettings, FilterSlope},
+};
+
+///Host parameters of the per-voice filter.
+#[derive(Params)]
+pub struct FilterParams {
+    #[id = "flt_on"]
+    pub on: BoolParam,
+    #[id = "flt_mode"]
+    pub mode: EnumParam<FilterMode>,
+    #[id = "flt_slope"]
+    pub slope: EnumParam<FilterSlope>,
+    #[id = "flt_cutoff"]
+    pub cutoff: FloatParam,
+    #[id = "flt_res"]
+    pub resonance: FloatParam,
+    ///Cutoff offset in octaves at full filter envelope.
+    #[id = "flt_env_amt"]
+    pub env_amount: FloatParam,
+    ///Amount the cutoff follows the played note (1.0 = one octave per octave).
+    #[id = "flt_key"]
+    pub key_track: FloatParam,
+    ///Cutoff offset in octaves at full velocity.
+    #[id = "flt_vel"]
+    pub velocity: FloatParam,
+
+    #[id = "flt_attack"]
+    pub attack: FloatParam,
+    #[id = "flt_decay"]
+    pub decay: FloatParam,
+    #[id = "flt_sustain"]
+    pub sustain: FloatParam,
+    #[id = "flt_release"]
+    pub release: FloatParam,
+}
+
+impl Default for FilterParams {
+    fn default() -> Self {
+        FilterParams {
+            on: BoolParam::new("Filter", false),
+            mode: EnumParam::new("Filter Mode", FilterMode::LowPass),
+            slope: EnumParam::new("Filter Slope", FilterSlope::Db12),
+            cutoff: FloatParam::new(
+                "Cutoff",
+                8000.0,
+                FloatRange::Skewed {
+                    min: FilterSettings::MIN_CUTOFF,
+                    max: 20000.0,
+                    factor: FloatRange::skew_factor(-2.0),
+                },
+            )
+            .with_unit(" Hz")
+            .with_value_to_string(Arc::new(|v| format!("{:.0}", v))),
+            resonance: FloatParam::new("Resonance", 0.0, FloatRange::Linear { min: 0.0, max: 1.0 })
+                .with_value_to_string(Arc::new(|v| format!("{:.2}", v))),
+            env_amount: FloatParam::new(
+                "Filter Env Amount",
+                0.0,
+                FloatRange::Linear {
+                    min: -8.0,
+                    max: 8.0,
+                },
+            )
+            .with_unit(" oct")
+            .with_value_to_string(Arc::new(|v| format!("{:.1}", v))),
+            key_track: FloatParam::new(
+                "Key Tracking",
+                0.0,
+                FloatRange::Linear { min: 0.0, max: 1.0 },
+            )
+            .with_value_to_string(Arc::new(|v| format!("{:.2}", v))),
+            velocity: FloatParam::new(
+                "Filter Velocity",
+                0.0,
+                FloatRange::Linear { min: 0.0, max: 4.0 },
+            )
+            .with_unit(" oct")
+            .with_value_to_string(Arc::new(|v| format!("{:.1}", v))),
+            attack: FloatParam::new(
+                "Filter Attack",
+                0.01,
+                FloatRange::Linear {
+                    min: 0.0001,
+                    max: 1.0,
+                },
+            )
+            .with_value_to_string(Arc::new(|v| format!("{:.2}", v))),
+            decay: FloatParam::new(
+                "Filter Decay",
+                0.3,
+                FloatRange::Linear { min: 0.0, max: 1.0 },
+            )
+            .with_value_to_string(Arc::new(|v| format!("{:.2}", v))),
+            sustain: FloatParam::new(
+                "Filter Sustain",
+                0.0,
+                FloatRange::Linear { min: 0.0, max: 1.0 },
+            )
+            .with_value_to_string(Arc::new(|v| format!("{:.2}", v))),
+            release: FloatParam::new(
+                "Filter Release",
+                0.1,
+                FloatRange::Linear { min: 0.0, max: 1.0 },
+            )
+            .with_value_to_string(Arc::new(|v| format!("{:.2}", v))),
+        }
+    }
+}
+
+impl FilterParams {
+    pub fn settings(&self) -> FilterSettings {
+        FilterSettings {
+            enabled: self.on.value(),
+            mode: self.mode.value(),
+            slope: self.slope.value(),
+            cutoff: self.cutoff.value(),
+            resonance: self.resonance.value(),
+            env_amount: self.env_amount.value(),
+            key_track: self.key_track.value(),
+            velocity: self.velocity.value(),
+        }
+    }
+
+    pub fn envelope(&self) -> EnvelopeParams {
+        EnvelopeParams {
+            delay: 0.0,
+            attack: self.attack.value() as f64,
+            hold: 0.0,
+            decay: self.decay.value() as f64,
+            sustain_level: self.sustain.value(),
+            release: self.release.value() as f64,
+        }
+    }
+}
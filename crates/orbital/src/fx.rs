@@ -0,0 +1,160 @@
+use nih_plug::prelude::Params;
+use serde::{Deserialize, Serialize};
+
+use self::{
+    chorus::{Chorus, ChorusParams},
+    delay::{Delay, DelayParams},
+    reverb::{Reverb, ReverbParams},
+};
+
+pub mod chorus;
+pub mod delay;
+pub mod reverb;
+
+///Stereo frame the effects operate on.
+pub type Frame = [f32; 2];
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FxKind {
+    Chorus,
+    Delay,
+    Reverb,
+}
+
+impl FxKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            FxKind::Chorus => "Chorus",
+            FxKind::Delay => "Delay",
+            FxKind::Reverb => "Reverb",
+        }
+    }
+}
+
+///Processing order of the effect chain. Always contains each effect exactly once.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FxOrder(pub [FxKind; 3]);
+
+impl Default for FxOrder {
+    fn default() -> Self {
+        FxOrder([FxKind::Chorus, FxKind::Delay, FxKind::Reverb])
+    }
+}
+
+impl FxOrder {
+    ///Swaps the effects at `a` and `b`. Out of range indices are ignored.
+    pub fn swap(&mut self, a: usize, b: usize) {
+        if a < self.0.len() && b < self.0.len() {
+            self.0.swap(a, b);
+        }
+    }
+}
+
+///Crossfades between the `dry` and `wet` frame.
+pub fn mix(dry: Frame, wet: Frame, mix: f32) -> Frame {
+    [
+        dry[0] * (1.0 - mix) + wet[0] * mix,
+        dry[1] * (1.0 - mix) + wet[1] * mix,
+    ]
+}
+
+///Host parameters of all effects.
+#[derive(Params, Default)]
+pub struct FxParams {
+    #[nested(group = "Chorus")]
+    pub chorus: ChorusParams,
+    #[nested(group = "Delay")]
+    pub delay: DelayParams,
+    #[nested(group = "Reverb")]
+    pub reverb: ReverbParams,
+}
+
+///Simple delay line with linear interpolated reads.
+pub struct DelayLine {
+    buffer: Vec<f32>,
+    pos: usize,
+}
+
+impl DelayLine {
+    pub fn new(len: usize) -> Self {
+        DelayLine {
+            buffer: vec![0.0; len.max(2)],
+            pos: 0,
+        }
+    }
+
+    pub fn reset(&mut self) {
+        for s in &mut self.buffer {
+            *s = 0.0;
+        }
+    }
+
+    #[inline(always)]
+    pub fn write(&mut self, x: f32) {
+        self.buffer[self.pos] = x;
+        self.pos = (self.pos + 1) % self.buffer.len();
+    }
+
+    ///Reads the sample written `delay` samples ago. `delay` is clamped to the line's length.
+    #[inline(always)]
+    pub fn read(&self, delay: f32) -> f32 {
+        let len = self.buffer.len();
+        let delay = delay.clamp(1.0, (len - 1) as f32);
+        let int = delay.floor() as usize;
+        let frac = delay - int as f32;
+        let a = self.buffer[(self.pos + len - int) % len];
+        let b = self.buffer[(self.pos + len - int - 1) % len];
+        a + (b - a) * frac
+    }
+}
+
+///Post-synth effects, processed in the user defined [FxOrder].
+pub struct FxChain {
+    pub order: FxOrder,
+    chorus: Chorus,
+    delay: Delay,
+    reverb: Reverb,
+}
+
+impl Default for FxChain {
+    fn default() -> Self {
+        Self::new(44100.0)
+    }
+}
+
+impl FxChain {
+    pub fn new(sample_rate: f32) -> Self {
+        FxChain {
+            order: FxOrder::default(),
+            chorus: Chorus::new(sample_rate),
+            delay: Delay::new(sample_rate),
+            reverb: Reverb::new(sample_rate),
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.chorus.reset();
+        self.delay.reset();
+        self.reverb.reset();
+    }
+
+    ///Processes both channels in place. `tempo` is the host's tempo in bpm, used by the synced delay.
+    pub fn process(&mut self, left: &mut [f32], right: &mut [f32], params: &FxParams, tempo: f64) {
+        #[cfg(feature = "profile")]
+        puffin::profile_function!();
+
+        self.delay.set_tempo(&params.delay, tempo);
+
+        for (l, r) in left.iter_mut().zip(right.iter_mut()) {
+            let mut frame = [*l, *r];
+            for kind in self.order.0 {
+                frame = match kind {
+                    FxKind::Chorus => self.chorus.process(frame, &params.chorus),
+                    FxKind::Delay => self.delay.process(frame, &params.delay),
+                    FxKind::Reverb => self.reverb.process(frame, &params.reverb),
+                };
+            }
+            [*l, *r] = frame;
+        }
+    }
+}
@@ -0,0 +1,112 @@
+use std::sync::Arc;
+
+use nih_plug::prelude::{BoolParam, FloatParam, FloatRange, Params, SmoothingStyle};
+
+use orbital_core::TWOPI;
+
+use super::{mix, DelayLine, Frame};
+
+#[derive(Params)]
+pub struct ChorusParams {
+    #[id = "cho_bypass"]
+    pub bypass: BoolParam,
+    #[id = "cho_mix"]
+    pub mix: FloatParam,
+    ///Lfo rate in Hz
+    #[id = "cho_rate"]
+    pub rate: FloatParam,
+    ///Modulation depth in ms
+    #[id = "cho_depth"]
+    pub depth: FloatParam,
+}
+
+impl Default for ChorusParams {
+    fn default() -> Self {
+        ChorusParams {
+            bypass: BoolParam::new("Chorus Bypass", true),
+            mix: FloatParam::new("Chorus Mix", 0.5, FloatRange::Linear { min: 0.0, max: 1.0 })
+                .with_smoother(SmoothingStyle::Linear(50.0))
+                .with_value_to_string(Arc::new(|v| format!("{:.2}", v))),
+            rate: FloatParam::new(
+                "Chorus Rate",
+                0.5,
+                FloatRange::Skewed {
+                    min: 0.05,
+                    max: 5.0,
+                    factor: FloatRange::skew_factor(-1.0),
+                },
+            )
+            .with_unit(" Hz")
+            .with_value_to_string(Arc::new(|v| format!("{:.2}", v))),
+            depth: FloatParam::new(
+                "Chorus Depth",
+                3.0,
+                FloatRange::Linear {
+                    min: 0.0,
+                    max: Chorus::MAX_DEPTH,
+                },
+            )
+            .with_smoother(SmoothingStyle::Linear(50.0))
+            .with_unit(" ms")
+            .with_value_to_string(Arc::new(|v| format!("{:.1}", v))),
+        }
+    }
+}
+
+///Three voice ensemble chorus. Each channel reads three taps from its delay line, modulated by
+/// phase shifted sines. The right channel's taps are shifted by a quarter period against the left ones.
+pub struct Chorus {
+    lines: [DelayLine; 2],
+    phase: f32,
+    sample_rate: f32,
+}
+
+impl Chorus {
+    ///Center delay of the taps in ms
+    const BASE_DELAY: f32 = 15.0;
+    ///Maximum modulation depth in ms
+    const MAX_DEPTH: f32 = 10.0;
+    const VOICES: usize = 3;
+
+    pub fn new(sample_rate: f32) -> Self {
+        let len = ((Self::BASE_DELAY + Self::MAX_DEPTH + 1.0) / 1000.0 * sample_rate) as usize;
+        Chorus {
+            lines: [DelayLine::new(len), DelayLine::new(len)],
+            phase: 0.0,
+            sample_rate,
+        }
+    }
+
+    pub fn reset(&mut self) {
+        for l in &mut self.lines {
+            l.reset();
+        }
+        self.phase = 0.0;
+    }
+
+    pub fn process(&mut self, frame: Frame, params: &ChorusParams) -> Frame {
+        //Always advance the smoothers, so they don't jump when the bypass is released
+        let mix_amount = params.mix.smoothed.next();
+        let depth = params.depth.smoothed.next();
+        if params.bypass.value() {
+            return frame;
+        }
+
+        self.phase = (self.phase + TWOPI * params.rate.value() / self.sample_rate) % TWOPI;
+
+        let ms_to_samples = self.sample_rate / 1000.0;
+        let mut wet = [0.0; 2];
+        for (ch, (line, input)) in self.lines.iter_mut().zip(frame).enumerate() {
+            line.write(input);
+            let channel_phase = self.phase + ch as f32 * TWOPI / 4.0;
+            for voice in 0..Self::VOICES {
+                let lfo = (channel_phase + voice as f32 * TWOPI / Self::VOICES as f32).sin();
+                let delay = (Self::BASE_DELAY + depth * lfo) * ms_to_samples;
+                wet[ch] += line.read(delay);
+            }
+            wet[ch] /= Self::VOICES as f32;
+        }
+
+        mix(frame, wet, mix_amount)
+    }
+}
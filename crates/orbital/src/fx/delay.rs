@@ -0,0 +1,153 @@
+use std::sync::Arc;
+
+use nih_plug::prelude::{
+    BoolParam, Enum, EnumParam, FloatParam, FloatRange, Params, SmoothingStyle,
+};
+
+use super::{mix, DelayLine, Frame};
+
+///Delay time as a note division.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Enum)]
+pub enum DelaySync {
+    #[name = "1/2"]
+    Half,
+    #[name = "1/4"]
+    Quarter,
+    #[name = "1/4 T"]
+    QuarterTriplet,
+    #[name = "1/8 D"]
+    EighthDotted,
+    #[name = "1/8"]
+    Eighth,
+    #[name = "1/8 T"]
+    EighthTriplet,
+    #[name = "1/16"]
+    Sixteenth,
+}
+
+impl DelaySync {
+    ///Length in beats (quarter notes)
+    pub fn beats(&self) -> f64 {
+        match self {
+            DelaySync::Half => 2.0,
+            DelaySync::Quarter => 1.0,
+            DelaySync::QuarterTriplet => 2.0 / 3.0,
+            DelaySync::EighthDotted => 0.75,
+            DelaySync::Eighth => 0.5,
+            DelaySync::EighthTriplet => 1.0 / 3.0,
+            DelaySync::Sixteenth => 0.25,
+        }
+    }
+}
+
+#[derive(Params)]
+pub struct DelayParams {
+    #[id = "dly_bypass"]
+    pub bypass: BoolParam,
+    #[id = "dly_mix"]
+    pub mix: FloatParam,
+    #[id = "dly_sync"]
+    pub sync: EnumParam<DelaySync>,
+    #[id = "dly_feedback"]
+    pub feedback: FloatParam,
+    ///Alternates the echoes between the left and right channel.
+    #[id = "dly_pingpong"]
+    pub ping_pong: BoolParam,
+}
+
+impl Default for DelayParams {
+    fn default() -> Self {
+        DelayParams {
+            bypass: BoolParam::new("Delay Bypass", true),
+            mix: FloatParam::new("Delay Mix", 0.3, FloatRange::Linear { min: 0.0, max: 1.0 })
+                .with_smoother(SmoothingStyle::Linear(50.0))
+                .with_value_to_string(Arc::new(|v| format!("{:.2}", v))),
+            sync: EnumParam::new("Delay Time", DelaySync::EighthDotted),
+            feedback: FloatParam::new(
+                "Delay Feedback",
+                0.4,
+                FloatRange::Linear {
+                    min: 0.0,
+                    max: 0.95,
+                },
+            )
+            .with_smoother(SmoothingStyle::Linear(50.0))
+            .with_value_to_string(Arc::new(|v| format!("{:.2}", v))),
+            ping_pong: BoolParam::new("Delay Ping-Pong", true),
+        }
+    }
+}
+
+///Tempo synced stereo delay.
+pub struct Delay {
+    lines: [DelayLine; 2],
+    ///Current delay in samples, glides towards `target`, so tempo changes don't click.
+    current: f32,
+    target: f32,
+    sample_rate: f32,
+}
+
+impl Delay {
+    ///Longest possible delay in seconds.
+    const MAX_TIME: f32 = 4.0;
+    ///Tempo used if the host does not report one.
+    const DEFAULT_TEMPO: f64 = 120.0;
+    ///Per sample coefficient of the delay time glide.
+    const GLIDE: f32 = 0.001;
+
+    pub fn new(sample_rate: f32) -> Self {
+        let len = (Self::MAX_TIME * sample_rate) as usize + 2;
+        Delay {
+            lines: [DelayLine::new(len), DelayLine::new(len)],
+            current: 0.0,
+            target: 0.0,
+            sample_rate,
+        }
+    }
+
+    pub fn reset(&mut self) {
+        for l in &mut self.lines {
+            l.reset();
+        }
+        self.current = self.target;
+    }
+
+    ///Updates the delay time from the sync division and the host's `tempo` in bpm.
+    pub fn set_tempo(&mut self, params: &DelayParams, tempo: f64) {
+        let tempo = if tempo > 0.0 {
+            tempo
+        } else {
+            Self::DEFAULT_TEMPO
+        };
+        let seconds = (params.sync.value().beats() * 60.0 / tempo) as f32;
+        self.target = seconds.min(Self::MAX_TIME) * self.sample_rate;
+        if self.current <= 0.0 {
+            self.current = self.target;
+        }
+    }
+
+    pub fn process(&mut self, frame: Frame, params: &DelayParams) -> Frame {
+        let mix_amount = params.mix.smoothed.next();
+        let feedback = params.feedback.smoothed.next();
+        if params.bypass.value() {
+            return frame;
+        }
+
+        self.current += (self.target - self.current) * Self::GLIDE;
+
+        let wet = [
+            self.lines[0].read(self.current),
+            self.lines[1].read(self.current),
+        ];
+        if params.ping_pong.value() {
+            //mono input into the left line, each line feeds the other one
+            self.lines[0].write((frame[0] + frame[1]) * 0.5 + wet[1] * feedback);
+            self.lines[1].write(wet[0] * feedback);
+        } else {
+            self.lines[0].write(frame[0] + wet[0] * feedback);
+            self.lines[1].write(frame[1] + wet[1] * feedback);
+        }
+
+        mix(frame, wet, mix_amount)
+    }
+}
@@ -0,0 +1,186 @@
+use std::sync::Arc;
+
+use nih_plug::prelude::{BoolParam, FloatParam, FloatRange, Params, SmoothingStyle};
+
+use super::{mix, Frame};
+
+#[derive(Params)]
+pub struct ReverbParams {
+    #[id = "rev_bypass"]
+    pub bypass: BoolParam,
+    #[id = "rev_mix"]
+    pub mix: FloatParam,
+    #[id = "rev_size"]
+    pub size: FloatParam,
+    ///High frequency damping of the tail
+    #[id = "rev_damping"]
+    pub damping: FloatParam,
+}
+
+impl Default for ReverbParams {
+    fn default() -> Self {
+        ReverbParams {
+            bypass: BoolParam::new("Reverb Bypass", true),
+            mix: FloatParam::new("Reverb Mix", 0.3, FloatRange::Linear { min: 0.0, max: 1.0 })
+                .with_smoother(SmoothingStyle::Linear(50.0))
+                .with_value_to_string(Arc::new(|v| format!("{:.2}", v))),
+            size: FloatParam::new(
+                "Reverb Size",
+                0.7,
+                FloatRange::Linear { min: 0.0, max: 1.0 },
+            )
+            .with_smoother(SmoothingStyle::Linear(50.0))
+            .with_value_to_string(Arc::new(|v| format!("{:.2}", v))),
+            damping: FloatParam::new(
+                "Reverb Damping",
+                0.5,
+                FloatRange::Linear { min: 0.0, max: 1.0 },
+            )
+            .with_smoother(SmoothingStyle::Linear(50.0))
+            .with_value_to_string(Arc::new(|v| format!("{:.2}", v))),
+        }
+    }
+}
+
+///Feedback comb filter with a one pole low-pass in the loop.
+struct Comb {
+    buffer: Vec<f32>,
+    pos: usize,
+    store: f32,
+}
+
+impl Comb {
+    fn new(len: usize) -> Self {
+        Comb {
+            buffer: vec![0.0; len.max(1)],
+            pos: 0,
+            store: 0.0,
+        }
+    }
+
+    #[inline(always)]
+    fn process(&mut self, x: f32, feedback: f32, damping: f32) -> f32 {
+        let out = self.buffer[self.pos];
+        self.store = out * (1.0 - damping) + self.store * damping;
+        self.buffer[self.pos] = x + self.store * feedback;
+        self.pos = (self.pos + 1) % self.buffer.len();
+        out
+    }
+}
+
+struct Allpass {
+    buffer: Vec<f32>,
+    pos: usize,
+}
+
+impl Allpass {
+    const FEEDBACK: f32 = 0.5;
+
+    fn new(len: usize) -> Self {
+        Allpass {
+            buffer: vec![0.0; len.max(1)],
+            pos: 0,
+        }
+    }
+
+    #[inline(always)]
+    fn process(&mut self, x: f32) -> f32 {
+        let delayed = self.buffer[self.pos];
+        self.buffer[self.pos] = x + delayed * Self::FEEDBACK;
+        self.pos = (self.pos + 1) % self.buffer.len();
+        delayed - x
+    }
+}
+
+///Per channel tank of the reverb.
+struct Tank {
+    combs: Vec<Comb>,
+    allpasses: Vec<Allpass>,
+}
+
+impl Tank {
+    fn new(sample_rate: f32, spread: usize) -> Self {
+        let scale = sample_rate / Reverb::TUNING_RATE;
+        Tank {
+            combs: Reverb::COMB_TUNING
+                .iter()
+                .map(|t| Comb::new(((t + spread) as f32 * scale) as usize))
+                .collect(),
+            allpasses: Reverb::ALLPASS_TUNING
+                .iter()
+                .map(|t| Allpass::new(((t + spread) as f32 * scale) as usize))
+                .collect(),
+        }
+    }
+
+    fn reset(&mut self) {
+        for c in &mut self.combs {
+            c.buffer.iter_mut().for_each(|s| *s = 0.0);
+            c.store = 0.0;
+        }
+        for a in &mut self.allpasses {
+            a.buffer.iter_mut().for_each(|s| *s = 0.0);
+        }
+    }
+
+    #[inline]
+    fn process(&mut self, x: f32, feedback: f32, damping: f32) -> f32 {
+        let mut out = 0.0;
+        for c in &mut self.combs {
+            out += c.process(x, feedback, damping);
+        }
+        for a in &mut self.allpasses {
+            out = a.process(out);
+        }
+        out
+    }
+}
+
+///Schroeder/Moorer style algorithmic reverb (after the public domain "Freeverb").
+pub struct Reverb {
+    tanks: [Tank; 2],
+}
+
+impl Reverb {
+    ///Sample rate the delay tunings are specified at.
+    const TUNING_RATE: f32 = 44100.0;
+    const COMB_TUNING: [usize; 8] = [1116, 1188, 1277, 1356, 1422, 1491, 1557, 1617];
+    const ALLPASS_TUNING: [usize; 4] = [556, 441, 341, 225];
+    ///Offset of the right channel's delays, decorrelates the channels.
+    const STEREO_SPREAD: usize = 23;
+    const INPUT_GAIN: f32 = 0.015;
+
+    pub fn new(sample_rate: f32) -> Self {
+        Reverb {
+            tanks: [
+                Tank::new(sample_rate, 0),
+                Tank::new(sample_rate, Self::STEREO_SPREAD),
+            ],
+        }
+    }
+
+    pub fn reset(&mut self) {
+        for t in &mut self.tanks {
+            t.reset();
+        }
+    }
+
+    pub fn process(&mut self, frame: Frame, params: &ReverbParams) -> Frame {
+        let mix_amount = params.mix.smoothed.next();
+        let size = params.size.smoothed.next();
+        let damping = params.damping.smoothed.next();
+        if params.bypass.value() {
+            return frame;
+        }
+
+        let feedback = 0.7 + 0.28 * size;
+        let damping = damping * 0.4;
+        let input = (frame[0] + frame[1]) * Self::INPUT_GAIN;
+        let wet = [
+            self.tanks[0].process(input, feedback, damping),
+            self.tanks[1].process(input, feedback, damping),
+        ];
+
+        mix(frame, wet, mix_amount)
+    }
+}
@@ -1,43 +1,74 @@
-#![feature(portable_simd)]
-
-use com::{ComMsg, GainType};
+use com::{ComMsg, SolarTopology};
 use crossbeam::channel::{Receiver, Sender, TryRecvError};
-use envelope::EnvelopeParams;
+use filter::FilterParams;
+use fx::{FxChain, FxOrder, FxParams};
+use macros::{MacroAssignment, MacroParams, MACRO_COUNT};
 use nih_plug::{
-    nih_error, nih_export_clap, nih_export_vst3, nih_log,
+    nih_export_clap, nih_export_vst3, nih_log,
     prelude::{
         AsyncExecutor, AudioIOLayout, AuxiliaryBuffers, BoolParam, Buffer, BufferConfig,
-        ClapFeature, ClapPlugin, Editor, FloatParam, FloatRange, InitContext, MidiConfig,
-        NoteEvent, Params, Plugin, ProcessContext, ProcessStatus, Vst3Plugin, Vst3SubCategory,
+        ClapFeature, ClapPlugin, Editor, EnumParam, FloatParam, FloatRange, InitContext,
+        MidiConfig, NoteEvent, Params, Plugin, ProcessContext, ProcessMode, ProcessStatus,
+        Vst3Plugin, Vst3SubCategory,
     },
+    wrapper::state::PluginState,
 };
 use nih_plug_egui::{create_egui_editor, EguiState};
-use osc::ModulationType;
-use osc_array::OscArray;
+use orbital_core::{
+    envelope::EnvelopeParams,
+    gain::GainType,
+    mod_matrix::ModMatrix,
+    osc::{ModulationType, OscillatorBank},
+    oversampling::OversamplingFactor,
+    state::SolarState,
+    tuning::Tuning,
+    Engine, Event,
+};
+use output::{OutputParams, OutputStage};
 use renderer::{solar_system::SolarSystem, Renderer};
+use slots::{ModulatorSlotParams, PrimarySlotParams, SlotValues};
 use std::{
     num::NonZeroU32,
-    sync::{Arc, Mutex, RwLock},
+    sync::{Arc, RwLock},
 };
 
 mod com;
-mod envelope;
-mod osc;
-mod osc_array;
+mod filter;
+mod fx;
+mod macros;
+mod migrate;
+pub mod offline;
+mod output;
+pub mod preset;
 mod renderer;
-
-pub type Time = f64;
+mod slots;
 
 /// This is mostly identical to the gain example, minus some fluff, and with a GUI.
 pub struct Orbital {
     params: Arc<OrbitalParams>,
 
     com_channel: (Sender<ComMsg>, Receiver<ComMsg>),
-    ///in audio-thread osc bank
-    synth: OscArray,
-
-    ///last known time (in sec.)
-    transport_time: Time,
+    ///in audio-thread synth, with the sample clock
+    engine: Engine,
+    ///Effects between the shaping and the output gain of the master section
+    fx: FxChain,
+    ///Master section after the synth
+    output: OutputStage,
+    ///Realtime or offline, selects which oscillator oversampling setting is used.
+    process_mode: ProcessMode,
+    ///Structure of the solar system as last reported by the renderer.
+    topology: SolarTopology,
+    ///State built from `topology` and the slot parameters. Kept around to not allocate while processing.
+    solar_state: SolarState,
+    ///Macro assignments that are applied on top of `solar_state`.
+    macro_assignments: Vec<MacroAssignment>,
+    ///Slot values `solar_state` was last built from.
+    applied_primaries: [SlotValues; OscillatorBank::PRIMARY_OSC_COUNT],
+    applied_modulators: [SlotValues; OscillatorBank::MOD_OSC_COUNT],
+    ///Macro values last applied on top of `solar_state`.
+    applied_macros: [f32; MACRO_COUNT],
+    ///Forces a rebuild of the bank's state, even if no slot or macro value changed.
+    state_dirty: bool,
 
     #[cfg(feature = "profile")]
     server: Option<puffin_http::Server>,
@@ -45,6 +76,28 @@ pub struct Orbital {
 
 impl Orbital {
     const NUM_CHANNELS: u32 = 2;
+    ///The slot parameters are smoothed in steps of this many samples, the bank's block size.
+    const SMOOTHING_BLOCK: usize = 64;
+
+    fn with_params(params: Arc<OrbitalParams>) -> Self {
+        Self {
+            params,
+            com_channel: crossbeam::channel::unbounded(),
+            engine: Engine::default(),
+            fx: FxChain::default(),
+            output: OutputStage::default(),
+            process_mode: ProcessMode::Realtime,
+            topology: SolarSystem::new().get_topology(),
+            solar_state: SolarState::default(),
+            macro_assignments: Vec::new(),
+            applied_primaries: Default::default(),
+            applied_modulators: Default::default(),
+            applied_macros: [0.0; MACRO_COUNT],
+            state_dirty: true,
+            #[cfg(feature = "profile")]
+            server: None,
+        }
+    }
 
     fn get_adsr_settings(&self) -> EnvelopeParams {
         EnvelopeParams {
@@ -56,6 +109,183 @@ impl Orbital {
             release: self.params.release.value() as f64,
         }
     }
+
+    ///Advances the slot parameters' smoothers by `samples`. Rebuilds the bank's state from the topology and
+    /// the slot values, and applies the macros on top, if any of them changed since the last rebuild.
+    fn update_solar_state(&mut self, samples: usize) {
+        let mut changed = std::mem::take(&mut self.state_dirty);
+        for (applied, params) in self
+            .applied_primaries
+            .iter_mut()
+            .zip(&self.params.primaries)
+        {
+            let values = SlotValues::next_primary(params, samples as u32);
+            changed |= *applied != values;
+            *applied = values;
+        }
+        for (applied, params) in self
+            .applied_modulators
+            .iter_mut()
+            .zip(&self.params.modulators)
+        {
+            let values = SlotValues::next_modulator(params, samples as u32);
+            changed |= *applied != values;
+            *applied = values;
+        }
+        for (applied, params) in self.applied_macros.iter_mut().zip(&self.params.macros) {
+            let value = params.value.value();
+            changed |= *applied != value;
+            *applied = value;
+        }
+
+        if changed {
+            self.topology.fill_state(
+                &mut self.solar_state,
+                &self.applied_primaries,
+                &self.applied_modulators,
+            );
+            self.engine.synth.bank.on_state_change(&self.solar_state);
+            self.apply_macros();
+        }
+    }
+
+    ///Renders the next block through the whole signal chain: synth, output stage and effects. `tempo` is the
+    /// host's tempo in bpm, used by the synced delay.
+    fn render(&mut self, left: &mut [f32], right: &mut [f32], tempo: f64) {
+        self.render_synth(left, right);
+        self.output.shape(
+            left,
+            right,
+            &self.params.output,
+            &self.params.gain_ty.value(),
+        );
+        self.fx.process(left, right, &self.params.fx, tempo);
+        self.output.finish(left, right, &self.params.output);
+    }
+
+    ///Renders the synth into both channels. The solar state is updated in between, so smoothed slot
+    /// parameters don't step once per host buffer.
+    fn render_synth(&mut self, left: &mut [f32], right: &mut [f32]) {
+        for (left, right) in left
+            .chunks_mut(Self::SMOOTHING_BLOCK)
+            .zip(right.chunks_mut(Self::SMOOTHING_BLOCK))
+        {
+            self.update_solar_state(left.len());
+            self.engine.render(left, right);
+        }
+    }
+
+    ///Oscillator oversampling for the current process mode.
+    fn oscillator_oversampling(&self) -> OversamplingFactor {
+        match self.process_mode {
+            ProcessMode::Offline => self.params.oversampling_offline.value(),
+            _ => self.params.oversampling.value(),
+        }
+    }
+
+    ///Latency of the whole signal chain in samples.
+    fn latency(&self) -> u32 {
+        self.engine.latency() + self.output.latency()
+    }
+
+    ///Copies the parameters into the synth. Returns true if the latency changed.
+    fn apply_params(&mut self) -> bool {
+        //Overwrite ADSR
+        //TODO: Find out if anything changed. We have two sources for that:
+        //      1. From ui (we can track that)
+        //      2. From DAW (no idea how to track that)
+        self.engine.synth.set_envelopes(self.get_adsr_settings());
+        self.engine
+            .synth
+            .set_filter(self.params.filter.settings(), self.params.filter.envelope());
+        self.engine
+            .synth
+            .set_reference_pitch(self.params.reference_pitch.value());
+        self.engine.synth.bank.reset_phase = self.params.reset_phase.value();
+        self.engine.synth.bank.free_running = self.params.free_running.value();
+        self.engine.synth.bank.mod_ty = self.params.mod_ty.value();
+        let output_changed = self
+            .output
+            .set_oversampling(self.params.output.oversampling.value());
+        let bank_changed = self
+            .engine
+            .synth
+            .bank
+            .set_oversampling(self.oscillator_oversampling());
+        output_changed || bank_changed
+    }
+
+    ///Sets up the signal chain for `sample_rate` and initializes it from the persisted state.
+    fn prepare(&mut self, sample_rate: f32, process_mode: ProcessMode) {
+        self.fx = FxChain::new(sample_rate);
+        self.fx.order = self
+            .params
+            .fx_order
+            .try_read()
+            .map(|o| *o)
+            .unwrap_or_default();
+        self.output = OutputStage::new(sample_rate);
+        self.output
+            .set_oversampling(self.params.output.oversampling.value());
+        self.process_mode = process_mode;
+        self.engine
+            .synth
+            .bank
+            .set_oversampling(self.oscillator_oversampling());
+        self.engine.set_sample_rate(sample_rate);
+        self.restore_state();
+    }
+
+    ///Initializes the synth from the persisted state.
+    fn restore_state(&mut self) {
+        self.topology = self
+            .params
+            .solar_system
+            .try_read()
+            .map(|lck| lck.get_topology())
+            .unwrap_or(SolarSystem::new().get_topology());
+        self.state_dirty = true;
+        self.macro_assignments = self
+            .params
+            .macro_assignments
+            .try_read()
+            .map(|m| m.clone())
+            .unwrap_or_default();
+        self.engine.synth.set_envelopes(self.get_adsr_settings());
+        self.engine
+            .synth
+            .set_filter(self.params.filter.settings(), self.params.filter.envelope());
+        self.engine.synth.bank.mod_ty = self.params.mod_ty.value();
+        self.engine.synth.set_tuning(
+            self.params
+                .tuning
+                .try_read()
+                .map(|t| t.clone())
+                .unwrap_or_default(),
+        );
+        self.engine
+            .synth
+            .set_reference_pitch(self.params.reference_pitch.value());
+        self.engine.synth.matrix = self
+            .params
+            .mod_matrix
+            .try_read()
+            .map(|m| m.clone())
+            .unwrap_or(ModMatrix::default());
+    }
+
+    ///Writes the last applied macro values into the bank.
+    fn apply_macros(&mut self) {
+        for assignment in &self.macro_assignments {
+            if let Some(value) = self.applied_macros.get(assignment.macro_idx) {
+                self.engine.synth.bank.set_orbital_property(
+                    assignment.target,
+                    assignment.property,
+                    assignment.map(*value),
+                );
+            }
+        }
+    }
 }
 
 #[derive(Params)]
@@ -66,15 +296,54 @@ pub struct OrbitalParams {
     editor_state: Arc<EguiState>,
     #[id = "reset_phase"]
     pub reset_phase: BoolParam,
-
-    #[persist = "modty"]
-    pub mod_ty: Arc<Mutex<ModulationType>>,
-    #[persist = "gainty"]
-    pub gain_ty: Arc<Mutex<GainType>>,
-    #[persist = "Synth"]
-    pub synth: Arc<Mutex<OscArray>>,
+    ///Lets the modulators, which are shared by all voices in absolute modulation mode, run continuously.
+    #[id = "free_run"]
+    pub free_running: BoolParam,
+
+    #[id = "mod_ty"]
+    pub mod_ty: EnumParam<ModulationType>,
+    #[id = "gain_ty"]
+    pub gain_ty: EnumParam<GainType>,
+    ///Oscillator oversampling while playing in realtime.
+    #[id = "osc_os"]
+    pub oversampling: EnumParam<OversamplingFactor>,
+    ///Oscillator oversampling while rendering offline (bouncing).
+    #[id = "osc_os_offline"]
+    pub oversampling_offline: EnumParam<OversamplingFactor>,
+    ///Pauses the solar system's animation
+    #[id = "paused"]
+    pub paused: BoolParam,
     #[persist = "SolarSystem"]
     pub solar_system: Arc<RwLock<SolarSystem>>,
+    #[persist = "ModMatrix"]
+    pub mod_matrix: Arc<RwLock<ModMatrix>>,
+    #[persist = "MacroAssignments"]
+    pub macro_assignments: Arc<RwLock<Vec<MacroAssignment>>>,
+    #[persist = "FxOrder"]
+    pub fx_order: Arc<RwLock<FxOrder>>,
+    ///Scala scale and keyboard mapping.
+    #[persist = "Tuning"]
+    pub tuning: Arc<RwLock<Tuning>>,
+    ///Frequency of A4
+    #[id = "ref_pitch"]
+    pub reference_pitch: FloatParam,
+
+    #[nested(array, group = "Macros")]
+    pub macros: [MacroParams; MACRO_COUNT],
+
+    #[nested(group = "Filter")]
+    pub filter: FilterParams,
+
+    #[nested(group = "Effects")]
+    pub fx: FxParams,
+
+    #[nested(group = "Output")]
+    pub output: OutputParams,
+
+    #[nested(array, group = "Primary Slots")]
+    pub primaries: [PrimarySlotParams; OscillatorBank::PRIMARY_OSC_COUNT],
+    #[nested(array, group = "Modulator Slots")]
+    pub modulators: [ModulatorSlotParams; OscillatorBank::MOD_OSC_COUNT],
 
     #[id = "Delay"]
     pub delay: FloatParam,
@@ -92,14 +361,7 @@ pub struct OrbitalParams {
 
 impl Default for Orbital {
     fn default() -> Self {
-        Self {
-            params: Arc::new(OrbitalParams::default()),
-            com_channel: crossbeam::channel::unbounded(),
-            synth: OscArray::default(),
-            transport_time: 0.0,
-            #[cfg(feature = "profile")]
-            server: None,
-        }
+        Self::with_params(Arc::new(OrbitalParams::default()))
     }
 }
 
@@ -108,13 +370,36 @@ impl Default for OrbitalParams {
         Self {
             editor_state: EguiState::from_size(800, 800),
             // See the main gain example for more details
-            mod_ty: Arc::new(Mutex::new(ModulationType::default())),
+            mod_ty: EnumParam::new("Modulation Type", ModulationType::default()),
             reset_phase: BoolParam::new("Reset Phase", true),
-            gain_ty: Arc::new(Mutex::new(GainType::default())),
-            synth: Arc::new(Mutex::new(OscArray::default())),
+            free_running: BoolParam::new("Free Running Modulators", false),
+            gain_ty: EnumParam::new("Gain Type", GainType::default()),
+            oversampling: EnumParam::new("Oversampling", OversamplingFactor::None),
+            oversampling_offline: EnumParam::new("Offline Oversampling", OversamplingFactor::X4),
+            paused: BoolParam::new("Pause Animation", true),
             solar_system: Arc::new(RwLock::new(SolarSystem::new())),
-
-            delay: FloatParam::new("Gain", 0.0, FloatRange::Linear { min: 0.0, max: 1.0 })
+            mod_matrix: Arc::new(RwLock::new(ModMatrix::default())),
+            macro_assignments: Arc::new(RwLock::new(Vec::new())),
+            fx_order: Arc::new(RwLock::new(FxOrder::default())),
+            tuning: Arc::new(RwLock::new(Tuning::default())),
+            reference_pitch: FloatParam::new(
+                "Reference Pitch",
+                orbital_core::ABS_BASE_FREQ,
+                FloatRange::Linear {
+                    min: 400.0,
+                    max: 480.0,
+                },
+            )
+            .with_unit(" Hz")
+            .with_value_to_string(Arc::new(|v| format!("{:.1}", v))),
+            macros: std::array::from_fn(MacroParams::new),
+            filter: FilterParams::default(),
+            fx: FxParams::default(),
+            output: OutputParams::default(),
+            primaries: std::array::from_fn(PrimarySlotParams::new),
+            modulators: std::array::from_fn(ModulatorSlotParams::new),
+
+            delay: FloatParam::new("Delay", 0.0, FloatRange::Linear { min: 0.0, max: 1.0 })
                 .with_value_to_string(Arc::new(|v| format!("{:.2}", v))),
             //Otherwise we get such a pesky *clicking* on attack
             attack: FloatParam::new(
@@ -152,7 +437,8 @@ impl Plugin for Orbital {
         ..AudioIOLayout::const_default()
     }];
 
-    const MIDI_INPUT: MidiConfig = MidiConfig::Basic;
+    //Needed for the mod wheel and channel pressure
+    const MIDI_INPUT: MidiConfig = MidiConfig::MidiCCs;
 
     const SAMPLE_ACCURATE_AUTOMATION: bool = true;
 
@@ -163,6 +449,12 @@ impl Plugin for Orbital {
         self.params.clone()
     }
 
+    fn filter_state(state: &mut PluginState) {
+        for (id, value) in migrate::migrate_fields(&mut state.fields) {
+            state.params.entry(id).or_insert(value);
+        }
+    }
+
     fn editor(&self, _async_executor: AsyncExecutor<Self>) -> Option<Box<dyn Editor>> {
         let params = self.params.clone();
         let renderer = Renderer::new(params, self.com_channel.0.clone());
@@ -177,7 +469,7 @@ impl Plugin for Orbital {
     fn initialize(
         &mut self,
         _audio_io_layout: &AudioIOLayout,
-        _buffer_config: &BufferConfig,
+        buffer_config: &BufferConfig,
         context: &mut impl InitContext<Self>,
     ) -> bool {
         nih_log!("Init");
@@ -185,6 +477,14 @@ impl Plugin for Orbital {
         //signal polyphony.
         context.set_current_voice_capacity(10);
 
+        //init synth to current state, or default
+        self.prepare(buffer_config.sample_rate, buffer_config.process_mode);
+        context.set_latency_samples(self.latency());
+        nih_log!(
+            "Oscillator kernel: {}",
+            self.engine.synth.bank.kernel.name()
+        );
+
         //if profiling, add server
         #[cfg(feature = "profile")]
         {
@@ -195,32 +495,16 @@ impl Plugin for Orbital {
             self.server = Some(puffin_http::Server::new(&server_addr).unwrap());
         }
 
-        //init synth to current state, or default
-        self.synth.bank.on_state_change(
-            self.params
-                .solar_system
-                .try_read()
-                .map(|lck| lck.get_solar_state())
-                .unwrap_or(SolarSystem::new().get_solar_state()),
-        );
-        self.synth.set_envelopes(self.get_adsr_settings());
-        self.synth.bank.mod_ty = self
-            .params
-            .mod_ty
-            .lock()
-            .map(|m| m.clone())
-            .unwrap_or(ModulationType::default());
         true
     }
 
+    fn reset(&mut self) {
+        self.fx.reset();
+        self.output.reset();
+    }
+
     fn deactivate(&mut self) {
-        self.transport_time = 0.0;
-        //feed back current parameter state
-        if let Ok(mut lck) = self.params.synth.lock() {
-            *lck = self.synth.clone();
-        } else {
-            nih_error!("Failed to serialize osc bank");
-        }
+        self.engine.reset();
     }
 
     fn process(
@@ -235,27 +519,24 @@ impl Plugin for Orbital {
         #[cfg(feature = "profile")]
         puffin::profile_function!();
 
-        let buffer_length = buffer.samples() as Time / context.transport().sample_rate as f64;
-        let sample_time = 1.0 / context.transport().sample_rate as Time;
-
         //try at most 10
         // TODO: check if we maybe should do that async
         for _try in 0..10 {
             match self.com_channel.1.try_recv() {
                 Ok(msg) => match msg {
-                    ComMsg::StateChange(s) => self.synth.bank.on_state_change(s),
-                    ComMsg::ModRelationChanged(new) => {
-                        if let Ok(mut mr) = self.params.mod_ty.try_lock() {
-                            *mr = new.clone();
-                        }
-                        self.synth.bank.mod_ty = new
+                    ComMsg::TopologyChange(t) => {
+                        self.topology = t;
+                        self.state_dirty = true;
                     }
-                    ComMsg::GainChange(new_gain) => {
-                        if let Ok(mut p) = self.params.gain_ty.try_lock() {
-                            *p = new_gain.clone();
-                        }
-                        self.synth.bank.gain_ty = new_gain;
+                    ComMsg::ModMatrixChange(new_matrix) => {
+                        self.engine.synth.matrix.update_from(new_matrix);
+                    }
+                    ComMsg::MacroChange(new_assignments) => {
+                        self.macro_assignments = new_assignments;
+                        self.state_dirty = true;
                     }
+                    ComMsg::FxOrderChange(order) => self.fx.order = order,
+                    ComMsg::TuningChange(tuning) => self.engine.synth.set_tuning(tuning),
                 },
                 Err(e) => {
                     match e {
@@ -268,29 +549,30 @@ impl Plugin for Orbital {
             }
         }
 
-        //Overwrite ADSR
-        //TODO: Find out if anything changed. We have two sources for that:
-        //      1. From ui (we can track that)
-        //      2. From DAW (no idea how to track that)
-        self.synth.set_envelopes(self.get_adsr_settings());
-        self.synth.bank.reset_phase = self.params.reset_phase.value();
+        if self.apply_params() {
+            context.set_latency_samples(self.latency());
+        }
 
+        let tempo = context.transport().tempo.unwrap_or(0.0);
+        self.engine.set_sample_rate(context.transport().sample_rate);
         while let Some(ev) = context.next_event() {
-            match ev {
-                NoteEvent::NoteOn { note, timing, .. } => self
-                    .synth
-                    .note_on(note, self.transport_time + timing as Time * sample_time),
-                NoteEvent::NoteOff { note, timing, .. } => self
-                    .synth
-                    .note_off(note, self.transport_time + timing as Time * sample_time),
-                _ => {}
-            }
+            let timing = ev.timing() as usize;
+            let event = match ev {
+                NoteEvent::NoteOn { note, velocity, .. } => Event::NoteOn { note, velocity },
+                NoteEvent::NoteOff { note, .. } => Event::NoteOff { note },
+                NoteEvent::PolyPressure { note, pressure, .. } => {
+                    Event::PolyPressure { note, pressure }
+                }
+                NoteEvent::MidiChannelPressure { pressure, .. } => Event::ChannelPressure(pressure),
+                NoteEvent::MidiCC { cc: 1, value, .. } => Event::ModWheel(value),
+                _ => continue,
+            };
+            self.engine.handle(event, timing);
         }
 
-        self.synth
-            .process(buffer, context.transport().sample_rate, self.transport_time);
-        //update time
-        self.transport_time += buffer_length;
+        if let [left, right] = buffer.as_slice() {
+            self.render(left, right, tempo);
+        }
 
         ProcessStatus::Normal
     }
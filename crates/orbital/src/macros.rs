@@ -0,0 +1,57 @@
+use nih_plug::prelude::{FloatParam, FloatRange, Params};
+use serde::{Deserialize, Serialize};
+
+use orbital_core::{osc::modulator::ParentIndex, state::OrbitalProperty};
+
+///Number of host-visible macro parameters.
+pub const MACRO_COUNT: usize = 8;
+
+///Single macro knob. The value is mapped onto orbital properties by [MacroAssignment]s.
+#[derive(Params)]
+pub struct MacroParams {
+    #[id = "macro"]
+    pub value: FloatParam,
+}
+
+impl MacroParams {
+    pub fn new(index: usize) -> Self {
+        MacroParams {
+            value: FloatParam::new(
+                format!("Macro {}", index + 1),
+                0.0,
+                FloatRange::Linear { min: 0.0, max: 1.0 },
+            )
+            .with_value_to_string(std::sync::Arc::new(|v| format!("{:.2}", v))),
+        }
+    }
+}
+
+///Maps a macro's value (0..1) linearly into `min..max` of an orbital's property.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct MacroAssignment {
+    ///Index of the macro (0..MACRO_COUNT)
+    pub macro_idx: usize,
+    pub target: ParentIndex,
+    pub property: OrbitalProperty,
+    pub min: f32,
+    pub max: f32,
+}
+
+impl MacroAssignment {
+    pub fn new(macro_idx: usize, target: ParentIndex) -> Self {
+        let (min, max) = OrbitalProperty::Radius.range();
+        MacroAssignment {
+            macro_idx,
+            target,
+            property: OrbitalProperty::Radius,
+            min,
+            max,
+        }
+    }
+
+    ///Returns the property value for the macro `value`.
+    pub fn map(&self, value: f32) -> f32 {
+        let (lower, upper) = self.property.range();
+        (self.min + (self.max - self.min) * value).clamp(lower, upper)
+    }
+}
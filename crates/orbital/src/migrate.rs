@@ -0,0 +1,114 @@
+//!Migrates plugin states saved by older versions. Called by the wrapper before the state is loaded, see
+//! [Plugin::filter_state](nih_plug::prelude::Plugin::filter_state). The solar system migrates itself when it
+//! is deserialized (see [orbital_core::schema]), this handles the persisted fields that were removed since.
+
+use std::collections::BTreeMap;
+
+use nih_plug::{nih_error, nih_log, prelude::Enum, wrapper::state::ParamValue};
+use orbital_core::{
+    gain::GainType,
+    osc::ModulationType,
+    schema::{PersistedOrbital, PersistedSolarSystem},
+};
+use serde::de::DeserializeOwned;
+
+///Reads a removed enum field and returns the variant's index.
+fn take_enum<T: Enum + DeserializeOwned>(
+    fields: &mut BTreeMap<String, String>,
+    key: &str,
+) -> Option<ParamValue> {
+    let src = fields.remove(key)?;
+    match serde_json::from_str::<T>(&src) {
+        Ok(value) => Some(ParamValue::I32(value.to_index() as i32)),
+        Err(e) => {
+            nih_error!("Could not migrate field {}: {}", key, e);
+            None
+        }
+    }
+}
+
+///Reads the pause state, that was persisted as part of the solar system.
+fn paused(fields: &BTreeMap<String, String>) -> Option<ParamValue> {
+    let system: serde_json::Value = serde_json::from_str(fields.get("SolarSystem")?).ok()?;
+    system.get("is_paused")?.as_bool().map(ParamValue::Bool)
+}
+
+///Ids of a primary slot's `on`, volume, speed and offset parameters, see
+/// [PrimarySlotParams](crate::slots::PrimarySlotParams).
+const PRIMARY_IDS: [&str; 4] = ["prim_on", "prim_volume", "prim_speed", "prim_offset"];
+///Ids of a modulator slot's `on`, range, speed and offset parameters, see
+/// [ModulatorSlotParams](crate::slots::ModulatorSlotParams).
+const MODULATOR_IDS: [&str; 4] = ["mod_on", "mod_range", "mod_speed", "mod_offset"];
+
+///Pushes the slot parameters of `orbital`. nih-plug suffixes the ids of nested parameter arrays with the
+/// 1-based index.
+fn push_slot(params: &mut Vec<(String, ParamValue)>, ids: [&str; 4], orbital: &PersistedOrbital) {
+    let [on, radius, speed, offset] = ids.map(|id| format!("{}_{}", id, orbital.slot + 1));
+    params.push((on, ParamValue::Bool(!orbital.muted)));
+    params.push((radius, ParamValue::F32(orbital.radius)));
+    params.push((speed, ParamValue::F32(orbital.speed)));
+    params.push((offset, ParamValue::F32(orbital.offset.to_degrees())));
+}
+
+fn push_moons(params: &mut Vec<(String, ParamValue)>, moons: &[PersistedOrbital]) {
+    for moon in moons {
+        push_slot(params, MODULATOR_IDS, moon);
+        push_moons(params, &moon.moons);
+    }
+}
+
+///Reads the slot parameters from the orbitals of the persisted solar system. Before the slots were host
+/// parameters, their values were only stored in the solar system.
+fn slot_params(fields: &BTreeMap<String, String>) -> Vec<(String, ParamValue)> {
+    let mut params = Vec::new();
+    let src = match fields.get("SolarSystem") {
+        Some(src) => src,
+        None => return params,
+    };
+    let mut system = match PersistedSolarSystem::from_json(src) {
+        Ok(system) => system,
+        Err(e) => {
+            nih_error!("Could not migrate the slot parameters: {}", e);
+            return params;
+        }
+    };
+    //Same repairs as when the solar system is loaded, so the slots match
+    if system.validate().is_err() {
+        return params;
+    }
+
+    for planet in &system.planets {
+        push_slot(&mut params, PRIMARY_IDS, planet);
+        push_moons(&mut params, &planet.moons);
+    }
+    params
+}
+
+///Removes persisted fields of older versions from `fields`. Returns the parameters that were persisted as
+/// fields before, as `(parameter id, value)`. The caller only inserts parameters that are missing in the
+/// state, so values saved by newer versions win.
+///
+/// Fields removed since 0.2:
+/// - `Synth`: The whole synth, including its voices. It is rebuilt from the solar system and the parameters.
+/// - `modty`, `gainty`: Modulation and gain type, host parameters since.
+/// - `is_paused` of `SolarSystem`: Pause state of the animation, host parameter since.
+/// - The orbit values of `SolarSystem`'s orbitals are still persisted, but the slot parameters drive the
+///   synth and GUI since.
+pub fn migrate_fields(fields: &mut BTreeMap<String, String>) -> Vec<(String, ParamValue)> {
+    if fields.remove("Synth").is_some() {
+        nih_log!("Dropped the persisted synth of an older version");
+    }
+
+    let mut params = Vec::new();
+    if let Some(value) = take_enum::<ModulationType>(fields, "modty") {
+        params.push(("mod_ty".to_owned(), value));
+    }
+    if let Some(value) = take_enum::<GainType>(fields, "gainty") {
+        params.push(("gain_ty".to_owned(), value));
+    }
+    if let Some(value) = paused(fields) {
+        params.push(("paused".to_owned(), value));
+    }
+    params.extend(slot_params(fields));
+    params
+}
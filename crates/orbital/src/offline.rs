@@ -0,0 +1,337 @@
+use std::{collections::BTreeMap, fmt::Display, path::Path, sync::Arc};
+
+use midly::{MetaMessage, MidiMessage, Smf, Timing, TrackEventKind};
+use nih_plug::prelude::{Params, ProcessMode};
+use serde::Deserialize;
+use serde_json::Value;
+
+use orbital_core::Event;
+
+use crate::{migrate::migrate_fields, Orbital, OrbitalParams};
+
+#[derive(Debug)]
+pub enum RenderError {
+    Io(String),
+    ///The plugin state could not be parsed.
+    State(String),
+    ///The midi file could not be parsed.
+    Midi(String),
+}
+
+impl Display for RenderError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RenderError::Io(e) => write!(f, "Could not read file: {}", e),
+            RenderError::State(e) => write!(f, "Invalid plugin state: {}", e),
+            RenderError::Midi(e) => write!(f, "Invalid midi file: {}", e),
+        }
+    }
+}
+
+///Plugin state as saved by the host. Parameters are stored by id, persisted fields as JSON strings.
+#[derive(Deserialize)]
+struct SavedState {
+    params: BTreeMap<String, Value>,
+    #[serde(default)]
+    fields: BTreeMap<String, String>,
+}
+
+///Plain value of a saved parameter. Values are either stored bare, or tagged with their type.
+fn plain_value(value: &Value) -> Option<f32> {
+    let value = match value {
+        Value::Object(tagged) => tagged.values().next()?,
+        bare => bare,
+    };
+    match value {
+        Value::Bool(b) => Some(if *b { 1.0 } else { 0.0 }),
+        Value::Number(n) => n.as_f64().map(|n| n as f32),
+        _ => None,
+    }
+}
+
+///Loads the parameters and persisted fields (solar system, modulation matrix...) from a saved plugin state.
+/// States of older versions are migrated like the plugin does.
+pub fn load_params(path: &Path) -> Result<OrbitalParams, RenderError> {
+    let src = std::fs::read(path).map_err(|e| RenderError::Io(e.to_string()))?;
+    let mut state: SavedState =
+        serde_json::from_slice(&src).map_err(|e| RenderError::State(e.to_string()))?;
+    for (id, value) in migrate_fields(&mut state.fields) {
+        //Serialized like the host saves it, tagged with its type
+        let value = serde_json::to_value(value).expect("Parameter values are always serializable");
+        state.params.entry(id).or_insert(value);
+    }
+
+    let params = OrbitalParams::default();
+    for (id, ptr, _group) in params.param_map() {
+        let plain = match state.params.get(&id).and_then(plain_value) {
+            Some(p) => p,
+            None => continue,
+        };
+        //Safety: the parameters are not shared with any other thread yet.
+        unsafe {
+            ptr.set_normalized_value(ptr.preview_normalized(plain));
+        }
+    }
+    params.deserialize_fields(&state.fields);
+
+    Ok(params)
+}
+
+///Converts a midi file event into an event the synth reacts to.
+fn track_event(kind: &TrackEventKind) -> Option<Event> {
+    let message = match kind {
+        TrackEventKind::Midi { message, .. } => message,
+        _ => return None,
+    };
+    let event = match *message {
+        //Note on with velocity 0 is a note off
+        MidiMessage::NoteOn { key, vel } if vel.as_int() == 0 => {
+            Event::NoteOff { note: key.as_int() }
+        }
+        MidiMessage::NoteOn { key, vel } => Event::NoteOn {
+            note: key.as_int(),
+            velocity: vel.as_int() as f32 / 127.0,
+        },
+        MidiMessage::NoteOff { key, .. } => Event::NoteOff { note: key.as_int() },
+        MidiMessage::Aftertouch { key, vel } => Event::PolyPressure {
+            note: key.as_int(),
+            pressure: vel.as_int() as f32 / 127.0,
+        },
+        MidiMessage::ChannelAftertouch { vel } => {
+            Event::ChannelPressure(vel.as_int() as f32 / 127.0)
+        }
+        MidiMessage::Controller { controller, value } if controller.as_int() == 1 => {
+            Event::ModWheel(value.as_int() as f32 / 127.0)
+        }
+        _ => return None,
+    };
+    Some(event)
+}
+
+///Parses a standard midi file. Returns the events of all tracks with their time in seconds, sorted by time.
+pub fn midi_events(src: &[u8]) -> Result<Vec<(f64, Event)>, RenderError> {
+    let smf = Smf::parse(src).map_err(|e| RenderError::Midi(e.to_string()))?;
+
+    //Merge all tracks by absolute tick. The sort is stable, so events on the same tick keep their order.
+    let mut merged = Vec::new();
+    for track in &smf.tracks {
+        let mut tick = 0u64;
+        for event in track {
+            tick += event.delta.as_int() as u64;
+            merged.push((tick, event.kind));
+        }
+    }
+    merged.sort_by_key(|(tick, _)| *tick);
+
+    let mut events = Vec::new();
+    match smf.header.timing {
+        Timing::Metrical(ticks_per_beat) => {
+            let ticks_per_beat = ticks_per_beat.as_int() as f64;
+            //Microseconds per beat, 120 bpm until the first tempo event
+            let mut tempo = 500_000.0;
+            let mut last_tick = 0;
+            let mut seconds = 0.0;
+            for (tick, kind) in merged {
+                seconds += (tick - last_tick) as f64 * tempo / (ticks_per_beat * 1_000_000.0);
+                last_tick = tick;
+                if let TrackEventKind::Meta(MetaMessage::Tempo(t)) = kind {
+                    tempo = t.as_int() as f64;
+                } else if let Some(event) = track_event(&kind) {
+                    events.push((seconds, event));
+                }
+            }
+        }
+        Timing::Timecode(fps, subframes) => {
+            let ticks_per_second = fps.as_f32() as f64 * subframes as f64;
+            for (tick, kind) in merged {
+                if let Some(event) = track_event(&kind) {
+                    events.push((tick as f64 / ticks_per_second, event));
+                }
+            }
+        }
+    }
+
+    Ok(events)
+}
+
+///Plays events through the plugin without a host or GUI. The output goes through the same signal chain as in
+/// a host: synth, output stage and effects.
+pub struct OfflineRenderer {
+    plugin: Orbital,
+}
+
+impl OfflineRenderer {
+    ///Number of samples rendered at once, if no event happens in between. The parameters and modulation are
+    /// updated once per block, like they would be for a host's buffer.
+    const BLOCK_SIZE: usize = 512;
+    ///Host tempo passed to the effects. None is given, so the synced delay uses its default tempo. Tempo
+    /// changes of the midi file are not followed.
+    const TEMPO: f64 = 0.0;
+
+    pub fn new(params: OrbitalParams, sample_rate: f32) -> Self {
+        reset_smoothers(&params, sample_rate);
+        let mut plugin = Orbital::with_params(Arc::new(params));
+        plugin.prepare(sample_rate, ProcessMode::Offline);
+        plugin.apply_params();
+        OfflineRenderer { plugin }
+    }
+
+    ///Latency of the whole signal chain in samples, as the plugin reports it to the host.
+    pub fn latency(&self) -> u32 {
+        self.plugin.latency()
+    }
+
+    ///Applies `event` at the current position.
+    pub fn handle(&mut self, event: Event) {
+        self.plugin.engine.handle(event, 0);
+    }
+
+    ///Renders the next `left.len()` samples into both channels. `right` has to be as long as `left`.
+    pub fn render(&mut self, left: &mut [f32], right: &mut [f32]) {
+        self.plugin.apply_params();
+        self.plugin.render(left, right, Self::TEMPO);
+    }
+
+    ///Renders `events` (time in seconds), followed by `tail` seconds for the releases and effects. Returns
+    /// the left and right channel. The result is latency compensated, so events are heard at their time.
+    pub fn render_events(&mut self, events: &[(f64, Event)], tail: f64) -> [Vec<f32>; 2] {
+        let sample_rate = self.plugin.engine.sample_rate() as f64;
+        let to_sample = |seconds: f64| (seconds * sample_rate).round().max(0.0) as usize;
+        let latency = self.latency() as usize;
+        let end = events.last().map(|(t, _)| *t).unwrap_or(0.0) + tail;
+
+        let len = to_sample(end) + latency;
+        let (mut left, mut right) = (vec![0.0; len], vec![0.0; len]);
+        let mut pending = events.iter().peekable();
+        let mut pos = 0;
+        loop {
+            //Apply all events that are due, then render until the next one
+            while let Some((_, event)) = pending.next_if(|(t, _)| to_sample(*t) <= pos) {
+                self.handle(*event);
+            }
+            if pos == len {
+                break;
+            }
+
+            let next_event = pending
+                .peek()
+                .map(|(t, _)| to_sample(*t))
+                .unwrap_or(usize::MAX);
+            let block_end = next_event.min(pos + Self::BLOCK_SIZE).min(len);
+            self.render(&mut left[pos..block_end], &mut right[pos..block_end]);
+            pos = block_end;
+        }
+
+        for channel in [&mut left, &mut right] {
+            channel.drain(0..latency.min(len));
+        }
+        [left, right]
+    }
+}
+
+///Starts the parameters' smoothers at their current values, like a host does when the plugin is activated.
+fn reset_smoothers(params: &OrbitalParams, sample_rate: f32) {
+    for (_id, ptr, _group) in params.param_map() {
+        //Safety: the parameters are not shared with any other thread yet.
+        unsafe {
+            ptr.update_smoother(sample_rate, true);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use nih_plug::prelude::{Params, ProcessMode};
+    use orbital_core::Event;
+
+    use super::{reset_smoothers, OfflineRenderer};
+    use crate::{Orbital, OrbitalParams};
+
+    const SAMPLE_RATE: f32 = 48000.0;
+    ///Buffer size of the simulated host, not a multiple of the offline renderer's blocks
+    const HOST_BLOCK: usize = 300;
+    const EVENTS: [(usize, Event); 4] = [
+        (
+            480,
+            Event::NoteOn {
+                note: 57,
+                velocity: 0.8,
+            },
+        ),
+        (
+            1000,
+            Event::NoteOn {
+                note: 64,
+                velocity: 0.5,
+            },
+        ),
+        (12000, Event::NoteOff { note: 57 }),
+        (15000, Event::NoteOff { note: 64 }),
+    ];
+
+    ///Default state with the drive turned up and all effects on, so a render that skips the output stage or
+    /// the effects can't match.
+    fn params() -> OrbitalParams {
+        let params = OrbitalParams::default();
+        let values = [
+            ("drive", 4.0),
+            ("cho_bypass", 0.0),
+            ("dly_bypass", 0.0),
+            ("rev_bypass", 0.0),
+        ];
+        for (id, ptr, _group) in params.param_map() {
+            if let Some((_, plain)) = values.iter().find(|(v, _)| *v == id) {
+                //Safety: the parameters are not shared with any other thread yet.
+                unsafe {
+                    ptr.set_normalized_value(ptr.preview_normalized(*plain));
+                }
+            }
+        }
+        params
+    }
+
+    ///Plays `EVENTS` like a host calls `process`: the parameters are applied per buffer and the events are
+    /// handled at their offset into the buffer. Not latency compensated.
+    fn process_render(len: usize) -> [Vec<f32>; 2] {
+        let params = params();
+        reset_smoothers(&params, SAMPLE_RATE);
+        let mut plugin = Orbital::with_params(Arc::new(params));
+        plugin.prepare(SAMPLE_RATE, ProcessMode::Offline);
+
+        let (mut left, mut right) = (vec![0.0; len], vec![0.0; len]);
+        let mut start = 0;
+        while start < len {
+            let end = (start + HOST_BLOCK).min(len);
+            plugin.apply_params();
+            for (at, event) in EVENTS.iter().filter(|(at, _)| (start..end).contains(at)) {
+                plugin.engine.handle(*event, at - start);
+            }
+            plugin.render(&mut left[start..end], &mut right[start..end], 0.0);
+            start = end;
+        }
+        [left, right]
+    }
+
+    #[test]
+    fn offline_matches_process() {
+        let events: Vec<(f64, Event)> = EVENTS
+            .iter()
+            .map(|(at, event)| (*at as f64 / SAMPLE_RATE as f64, *event))
+            .collect();
+        let mut renderer = OfflineRenderer::new(params(), SAMPLE_RATE);
+        let latency = renderer.latency() as usize;
+        let offline = renderer.render_events(&events, 0.5);
+        let processed = process_render(offline[0].len() + latency);
+
+        let mut loudest = 0.0f32;
+        for (offline, processed) in offline.iter().zip(&processed) {
+            for (o, p) in offline.iter().zip(&processed[latency..]) {
+                assert!((o - p).abs() < 1e-4, "{} != {}", o, p);
+                loudest = loudest.max(o.abs());
+            }
+        }
+        assert!(loudest > 0.1);
+    }
+}
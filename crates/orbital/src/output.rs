@@ -0,0 +1,315 @@
+use nih_plug::{
+    prelude::{BoolParam, EnumParam, FloatParam, FloatRange, Params, SmoothingStyle},
+    util,
+};
+use std::{collections::VecDeque, sync::Arc};
+
+use orbital_core::{
+    gain::GainType,
+    oversampling::{Oversampler, OversamplingFactor},
+    TWOPI,
+};
+
+///Master section parameters. All of them are smoothed, so they can be automated sample accurate.
+#[derive(Params)]
+pub struct OutputParams {
+    ///Final output gain, applied after the composition curve.
+    #[id = "out_gain"]
+    pub gain: FloatParam,
+    ///Pre-gain into the voice composition curve.
+    #[id = "drive"]
+    pub drive: FloatParam,
+    ///Oversampling around the composition curve, reduces aliasing of the harsher curves.
+    #[id = "shaper_os"]
+    pub oversampling: EnumParam<OversamplingFactor>,
+    #[id = "dc_block"]
+    pub dc_block: BoolParam,
+    #[id = "limiter"]
+    pub limiter: BoolParam,
+}
+
+impl Default for OutputParams {
+    fn default() -> Self {
+        OutputParams {
+            gain: FloatParam::new(
+                "Output Gain",
+                util::db_to_gain(0.0),
+                FloatRange::Skewed {
+                    min: util::db_to_gain(-60.0),
+                    max: util::db_to_gain(12.0),
+                    factor: FloatRange::gain_skew_factor(-60.0, 12.0),
+                },
+            )
+            .with_smoother(SmoothingStyle::Logarithmic(50.0))
+            .with_unit(" dB")
+            .with_value_to_string(Arc::new(|v| format!("{:.1}", util::gain_to_db(v)))),
+            drive: FloatParam::new(
+                "Drive",
+                util::db_to_gain(0.0),
+                FloatRange::Skewed {
+                    min: util::db_to_gain(0.0),
+                    max: util::db_to_gain(24.0),
+                    factor: FloatRange::gain_skew_factor(0.0, 24.0),
+                },
+            )
+            .with_smoother(SmoothingStyle::Logarithmic(50.0))
+            .with_unit(" dB")
+            .with_value_to_string(Arc::new(|v| format!("{:.1}", util::gain_to_db(v)))),
+            oversampling: EnumParam::new("Shaper Oversampling", OversamplingFactor::None),
+            dc_block: BoolParam::new("DC Blocker", true),
+            limiter: BoolParam::new("Limiter", false),
+        }
+    }
+}
+
+///One pole high-pass that removes DC offsets introduced by asymmetric modulation.
+#[derive(Clone, Copy, Default)]
+struct DcBlocker {
+    last_in: f32,
+    last_out: f32,
+}
+
+impl DcBlocker {
+    #[inline(always)]
+    fn process(&mut self, coeff: f32, x: f32) -> f32 {
+        let y = x - self.last_in + coeff * self.last_out;
+        self.last_in = x;
+        self.last_out = y;
+        y
+    }
+}
+
+///Look-ahead peak limiter. Delays the signal by `LOOK_AHEAD` seconds, so the gain reduction
+/// can be ramped in before a peak arrives. All channels share the same gain reduction.
+struct Limiter {
+    delay: Vec<[f32; OutputStage::NUM_CHANNELS]>,
+    pos: usize,
+    ///Current gain reduction
+    gain: f32,
+    ///Gains needed by the peaks within the look-ahead window, as `(gain, samples until the peak leaves the
+    /// delay line)`. Ordered by arrival, each needs less gain than the ones before it.
+    targets: VecDeque<(f32, usize)>,
+    ///Samples until the last peak leaves the delay line, the gain is released after
+    hold: usize,
+    release_coeff: f32,
+}
+
+impl Limiter {
+    ///Look-ahead time in seconds
+    const LOOK_AHEAD: f32 = 0.005;
+    ///Release time in seconds
+    const RELEASE: f32 = 0.1;
+    ///Maximum output amplitude (-0.3dB)
+    const CEILING: f32 = 0.966;
+
+    fn new(sample_rate: f32) -> Self {
+        let len = ((Self::LOOK_AHEAD * sample_rate) as usize).max(1);
+        Limiter {
+            delay: vec![[0.0; OutputStage::NUM_CHANNELS]; len],
+            pos: 0,
+            gain: 1.0,
+            //Never holds more targets than samples in the window, so it doesn't allocate while processing
+            targets: VecDeque::with_capacity(len + 1),
+            hold: 0,
+            release_coeff: (-1.0 / (Self::RELEASE * sample_rate)).exp(),
+        }
+    }
+
+    ///Latency in samples
+    fn latency(&self) -> usize {
+        self.delay.len()
+    }
+
+    fn reset(&mut self) {
+        for f in &mut self.delay {
+            *f = [0.0; OutputStage::NUM_CHANNELS];
+        }
+        self.gain = 1.0;
+        self.targets.clear();
+        self.hold = 0;
+    }
+
+    ///Limits `frame`. If not `enabled`, the frame is only delayed, so the latency stays the same.
+    fn process(&mut self, frame: &mut [f32; OutputStage::NUM_CHANNELS], enabled: bool) {
+        let len = self.delay.len();
+        if !enabled {
+            self.gain = 1.0;
+            self.targets.clear();
+            self.hold = 0;
+            *frame = std::mem::replace(&mut self.delay[self.pos], *frame);
+            self.pos = (self.pos + 1) % len;
+            return;
+        }
+
+        let peak = frame.iter().fold(0.0f32, |p, s| p.max(s.abs()));
+        if peak > Self::CEILING {
+            let target = Self::CEILING / peak;
+            //The frame leaves the delay line after `len` more frames. A peak that needs no more reduction
+            // than an earlier one only has to hold the gain until then.
+            self.hold = len + 1;
+            if self.targets.back().is_none_or(|(t, _)| target < *t) {
+                self.targets.push_back((target, len + 1));
+            }
+        }
+
+        if self.hold == 0 {
+            self.gain = 1.0 + (self.gain - 1.0) * self.release_coeff;
+        } else {
+            self.hold -= 1;
+            //Ramp down fast enough to reach every target by the time its peak is played
+            let step = self.targets.iter().fold(0.0f32, |step, (t, left)| {
+                step.max((self.gain - t) / *left as f32)
+            });
+            self.gain -= step;
+            for (_, left) in &mut self.targets {
+                *left -= 1;
+            }
+            while self.targets.front().is_some_and(|(_, left)| *left == 0) {
+                self.targets.pop_front();
+            }
+        }
+
+        let delayed = std::mem::replace(&mut self.delay[self.pos], *frame);
+        self.pos = (self.pos + 1) % len;
+        for (out, d) in frame.iter_mut().zip(delayed) {
+            //The clamp is only a safety net for rounding errors in the ramp
+            *out = (d * self.gain).clamp(-Self::CEILING, Self::CEILING);
+        }
+    }
+}
+
+///Post-mix master stage: drive -> composition curve -> dc blocker -> [effects] -> gain -> limiter.
+pub struct OutputStage {
+    shaper_os: [Oversampler; Self::NUM_CHANNELS],
+    dc: [DcBlocker; Self::NUM_CHANNELS],
+    dc_coeff: f32,
+    limiter: Limiter,
+}
+
+impl Default for OutputStage {
+    fn default() -> Self {
+        Self::new(44100.0)
+    }
+}
+
+impl OutputStage {
+    pub const NUM_CHANNELS: usize = 2;
+    ///Cutoff of the DC blocker in Hz
+    const DC_CUTOFF: f32 = 10.0;
+
+    pub fn new(sample_rate: f32) -> Self {
+        OutputStage {
+            shaper_os: [Oversampler::default(); Self::NUM_CHANNELS],
+            dc: [DcBlocker::default(); Self::NUM_CHANNELS],
+            dc_coeff: 1.0 - (TWOPI * Self::DC_CUTOFF / sample_rate),
+            limiter: Limiter::new(sample_rate),
+        }
+    }
+
+    ///Latency introduced by the shaper's oversampling and the limiter's look-ahead, in samples.
+    pub fn latency(&self) -> u32 {
+        self.shaper_os[0].latency() + self.limiter.latency() as u32
+    }
+
+    ///Switches the shaper's oversampling factor. Returns true if the latency changed.
+    pub fn set_oversampling(&mut self, factor: OversamplingFactor) -> bool {
+        if self.shaper_os[0].factor() == factor {
+            return false;
+        }
+        for os in &mut self.shaper_os {
+            os.set_factor(factor);
+        }
+        true
+    }
+
+    pub fn reset(&mut self) {
+        for os in &mut self.shaper_os {
+            *os = Oversampler::new(os.factor());
+        }
+        self.dc = [DcBlocker::default(); Self::NUM_CHANNELS];
+        self.limiter.reset();
+    }
+
+    ///First half of the stage: drive, composition curve and dc blocker. Effects are inserted between
+    /// [shape](Self::shape) and [finish](Self::finish).
+    pub fn shape(
+        &mut self,
+        left: &mut [f32],
+        right: &mut [f32],
+        params: &OutputParams,
+        gain_ty: &GainType,
+    ) {
+        #[cfg(feature = "profile")]
+        puffin::profile_function!();
+
+        let dc_block = params.dc_block.value();
+
+        for (l, r) in left.iter_mut().zip(right.iter_mut()) {
+            let drive = params.drive.smoothed.next();
+
+            for (ch, s) in [l, r].into_iter().enumerate() {
+                let mut v = self.shaper_os[ch].process(*s * drive, |x| gain_ty.map(x));
+                if dc_block {
+                    v = self.dc[ch].process(self.dc_coeff, v);
+                }
+                *s = v;
+            }
+        }
+    }
+
+    ///Second half of the stage: output gain and limiter.
+    pub fn finish(&mut self, left: &mut [f32], right: &mut [f32], params: &OutputParams) {
+        #[cfg(feature = "profile")]
+        puffin::profile_function!();
+
+        let limit = params.limiter.value();
+
+        for (l, r) in left.iter_mut().zip(right.iter_mut()) {
+            let gain = params.gain.smoothed.next();
+
+            let mut frame = [*l * gain, *r * gain];
+            self.limiter.process(&mut frame, limit);
+            [*l, *r] = frame;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Limiter, OutputStage};
+
+    ///Two peaks less than one window apart, the second one a bit smaller, so it needs a little less gain
+    /// reduction. Every sample must be attenuated by the ramped gain alone, not by the clamp.
+    #[test]
+    fn close_peaks() {
+        let mut limiter = Limiter::new(44100.0);
+        let window = limiter.latency();
+        let mut input = vec![0.5; 4 * window];
+        let first = window;
+        let second = 2 * window - 1;
+        input[first] = 4.0;
+        input[second] = 3.8;
+
+        //Gain applied to each input sample, it is played `window` frames later
+        let mut gains = Vec::new();
+        for &s in input.iter().chain(&vec![0.0; window]) {
+            let mut frame = [s; OutputStage::NUM_CHANNELS];
+            limiter.process(&mut frame, true);
+            gains.push(limiter.gain);
+        }
+        let gains = &gains[window..];
+
+        for (i, (s, gain)) in input.iter().zip(gains).enumerate() {
+            assert!(
+                s * gain <= Limiter::CEILING + 1e-5,
+                "{} clipped at {}",
+                s * gain,
+                i
+            );
+        }
+        //Ramped in just enough for the first peak
+        assert!((input[first] * gains[first] - Limiter::CEILING).abs() < 1e-4);
+        //Released after the second peak
+        assert!(gains[3 * window] > gains[second]);
+    }
+}
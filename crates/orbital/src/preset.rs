@@ -0,0 +1,391 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Display,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use nih_plug::prelude::{Param, ParamPtr, ParamSetter, Params};
+use orbital_core::{
+    envelope::EnvelopeParams, gain::GainType, mod_matrix::ModMatrix, osc::ModulationType,
+    tuning::Tuning,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    fx::FxOrder, macros::MacroAssignment, renderer::solar_system::SolarSystem,
+    slots::set_if_changed, OrbitalParams,
+};
+
+pub mod factory;
+
+#[derive(Debug)]
+pub enum PresetError {
+    Io(String),
+    ///The file is not a valid preset.
+    Parse(String),
+    ///The preset was saved by a newer version of the plugin.
+    UnsupportedFormat(u32),
+}
+
+impl Display for PresetError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PresetError::Io(e) => write!(f, "Could not access file: {}", e),
+            PresetError::Parse(e) => write!(f, "Invalid preset: {}", e),
+            PresetError::UnsupportedFormat(v) => {
+                write!(f, "Preset format {} is newer than this plugin", v)
+            }
+        }
+    }
+}
+
+///A patch, stored as pretty printed JSON. Contains everything that makes up the sound: the orbit tree, envelope,
+/// modulation, macros, filter, effects, output and tuning. The slot parameters are part of the orbit tree.
+///
+/// Format 1 presets only stored the orbit tree, envelope and types. Everything else is loaded as its default
+/// from them, so nothing of the previous patch is left over.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Preset {
+    ///Version of the preset format, see [Preset::FORMAT].
+    pub format: u32,
+    ///Version of the plugin that saved the preset.
+    pub plugin_version: String,
+    pub name: String,
+    ///Category the browser groups presets by, like "Bass" or "Pad".
+    #[serde(default)]
+    pub category: String,
+    pub solar_system: SolarSystem,
+    pub envelope: EnvelopeParams,
+    pub mod_ty: ModulationType,
+    pub gain_ty: GainType,
+    #[serde(default)]
+    pub mod_matrix: ModMatrix,
+    #[serde(default)]
+    pub macro_assignments: Vec<MacroAssignment>,
+    ///Plain value of each macro.
+    #[serde(default)]
+    pub macros: Vec<f32>,
+    ///Plain values of the filter, effect and output parameters by id.
+    #[serde(default)]
+    pub params: BTreeMap<String, f32>,
+    #[serde(default)]
+    pub fx_order: FxOrder,
+    #[serde(default)]
+    pub tuning: Tuning,
+}
+
+impl Preset {
+    ///Current version of the preset format. Increase on incompatible changes.
+    pub const FORMAT: u32 = 2;
+    pub const EXTENSION: &'static str = "json";
+
+    ///Captures the current patch.
+    pub fn capture(name: &str, category: &str, params: &OrbitalParams) -> Self {
+        Preset {
+            format: Self::FORMAT,
+            plugin_version: env!("CARGO_PKG_VERSION").to_string(),
+            name: name.to_string(),
+            category: category.to_string(),
+            solar_system: params
+                .solar_system
+                .read()
+                .map(|s| s.clone())
+                .unwrap_or_else(|_| SolarSystem::new()),
+            envelope: EnvelopeParams {
+                delay: params.delay.value() as f64,
+                attack: params.attack.value() as f64,
+                hold: params.hold.value() as f64,
+                decay: params.decay.value() as f64,
+                sustain_level: params.sustain.value(),
+                release: params.release.value() as f64,
+            },
+            mod_ty: params.mod_ty.value(),
+            gain_ty: params.gain_ty.value(),
+            mod_matrix: params
+                .mod_matrix
+                .read()
+                .map(|m| m.clone())
+                .unwrap_or_default(),
+            macro_assignments: params
+                .macro_assignments
+                .read()
+                .map(|m| m.clone())
+                .unwrap_or_default(),
+            macros: params.macros.iter().map(|m| m.value.value()).collect(),
+            params: sound_params(params)
+                .into_iter()
+                //Safety: the parameters outlive the pointers.
+                .map(|(id, ptr)| (id, unsafe { ptr.unmodulated_plain_value() }))
+                .collect(),
+            fx_order: params.fx_order.read().map(|o| *o).unwrap_or_default(),
+            tuning: params.tuning.read().map(|t| t.clone()).unwrap_or_default(),
+        }
+    }
+
+    ///Writes the host parameters of this preset, parameters the preset does not store are reset to their
+    /// default. The solar system is returned, so the caller can swap it in and push its slots to the host. The
+    /// persisted modulation, effect order and tuning are left to the caller as well.
+    pub fn apply(&self, params: &OrbitalParams, setter: &ParamSetter) -> SolarSystem {
+        set_if_changed(setter, &params.delay, self.envelope.delay as f32);
+        set_if_changed(setter, &params.attack, self.envelope.attack as f32);
+        set_if_changed(setter, &params.hold, self.envelope.hold as f32);
+        set_if_changed(setter, &params.decay, self.envelope.decay as f32);
+        set_if_changed(setter, &params.sustain, self.envelope.sustain_level);
+        set_if_changed(setter, &params.release, self.envelope.release as f32);
+        set_if_changed(setter, &params.mod_ty, self.mod_ty.clone());
+        set_if_changed(setter, &params.gain_ty, self.gain_ty);
+        for (idx, m) in params.macros.iter().enumerate() {
+            let value = self
+                .macros
+                .get(idx)
+                .copied()
+                .unwrap_or_else(|| m.value.default_plain_value());
+            set_if_changed(setter, &m.value, value);
+        }
+        for (id, ptr) in sound_params(params) {
+            //Safety: the parameters outlive the pointers, and are only written through the host's context.
+            unsafe {
+                let normalized = match self.params.get(&id) {
+                    Some(plain) => ptr.preview_normalized(*plain),
+                    None => ptr.default_normalized_value(),
+                };
+                if ptr.unmodulated_normalized_value() != normalized {
+                    setter.raw_context.raw_begin_set_parameter(ptr);
+                    setter
+                        .raw_context
+                        .raw_set_parameter_normalized(ptr, normalized);
+                    setter.raw_context.raw_end_set_parameter(ptr);
+                }
+            }
+        }
+        self.solar_system.clone()
+    }
+
+    pub fn from_json(src: &str) -> Result<Self, PresetError> {
+        //Check the format first, so newer presets are reported as such, and not as a parse error.
+        #[derive(Deserialize)]
+        struct Header {
+            format: u32,
+        }
+        let header: Header =
+            serde_json::from_str(src).map_err(|e| PresetError::Parse(e.to_string()))?;
+        if header.format > Self::FORMAT {
+            return Err(PresetError::UnsupportedFormat(header.format));
+        }
+        serde_json::from_str(src).map_err(|e| PresetError::Parse(e.to_string()))
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("Preset is always serializable")
+    }
+
+    pub fn load(path: &Path) -> Result<Self, PresetError> {
+        let src = fs::read_to_string(path).map_err(|e| PresetError::Io(e.to_string()))?;
+        Self::from_json(&src)
+    }
+
+    ///Path of this preset's file in `dir`.
+    pub fn path_in(&self, dir: &Path) -> PathBuf {
+        dir.join(format!("{}.{}", file_stem(&self.name), Self::EXTENSION))
+    }
+
+    ///Writes the preset into `dir`, named after the preset. Returns the file's path.
+    pub fn save(&self, dir: &Path) -> Result<PathBuf, PresetError> {
+        fs::create_dir_all(dir).map_err(|e| PresetError::Io(e.to_string()))?;
+        let path = self.path_in(dir);
+        fs::write(&path, self.to_json()).map_err(|e| PresetError::Io(e.to_string()))?;
+        Ok(path)
+    }
+}
+
+///Filter, effect and output parameters by id. These are stored by id, so new parameters don't need a new
+/// preset format.
+fn sound_params(params: &OrbitalParams) -> Vec<(String, ParamPtr)> {
+    [
+        params.filter.param_map(),
+        params.fx.param_map(),
+        params.output.param_map(),
+    ]
+    .into_iter()
+    .flatten()
+    .map(|(id, ptr, _group)| (id, ptr))
+    .collect()
+}
+
+///File name for a preset name. Characters that are not allowed in file names on some systems are replaced.
+fn file_stem(name: &str) -> String {
+    let stem: String = name
+        .trim()
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect();
+    if stem.is_empty() {
+        "Untitled".to_string()
+    } else {
+        stem
+    }
+}
+
+///Directory the user's presets are stored in.
+pub fn user_preset_dir() -> PathBuf {
+    dirs::data_dir()
+        .unwrap_or_else(std::env::temp_dir)
+        .join("Orbital")
+        .join("Presets")
+}
+
+///Preset file as listed by the browser.
+#[derive(Clone, Debug)]
+pub struct PresetEntry {
+    pub path: PathBuf,
+    pub name: String,
+    pub category: String,
+}
+
+///Lists all presets in `dir`, sorted by category and name. Files that are not valid presets are skipped.
+pub fn scan_presets(dir: &Path) -> Vec<PresetEntry> {
+    let mut entries: Vec<PresetEntry> = fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|e| e == Preset::EXTENSION))
+        .filter_map(|path| {
+            let preset = Preset::load(&path).ok()?;
+            Some(PresetEntry {
+                path,
+                name: preset.name,
+                category: preset.category,
+            })
+        })
+        .collect();
+    entries.sort_by(|a, b| (&a.category, &a.name).cmp(&(&b.category, &b.name)));
+    entries
+}
+
+///Renames the preset at `path`. The file is renamed too, the new path is returned.
+pub fn rename_preset(path: &Path, name: &str) -> Result<PathBuf, PresetError> {
+    let mut preset = Preset::load(path)?;
+    preset.name = name.to_string();
+    let dir = path.parent().unwrap_or_else(|| Path::new("."));
+    if preset.path_in(dir) == path {
+        preset.save(dir)
+    } else if preset.path_in(dir).exists() {
+        Err(PresetError::Io(format!(
+            "A preset named \"{}\" exists",
+            name
+        )))
+    } else {
+        let new_path = preset.save(dir)?;
+        fs::remove_file(path).map_err(|e| PresetError::Io(e.to_string()))?;
+        Ok(new_path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::PathBuf};
+
+    use super::{factory::factory_presets, file_stem, rename_preset, Preset, PresetError};
+
+    fn preset(name: &str) -> Preset {
+        let mut preset = factory_presets().remove(0);
+        preset.name = name.to_string();
+        preset
+    }
+
+    ///Empty directory for a test, removed by the test.
+    fn test_dir(test: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("orbital-preset-{}-{}", test, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn json_roundtrip() {
+        let json = preset("Roundtrip").to_json();
+        let loaded = Preset::from_json(&json).unwrap();
+        assert_eq!(loaded.name, "Roundtrip");
+        assert_eq!(loaded.to_json(), json);
+    }
+
+    #[test]
+    fn newer_format_is_rejected() {
+        let mut newer = preset("Newer");
+        newer.format = Preset::FORMAT + 1;
+        assert!(matches!(
+            Preset::from_json(&newer.to_json()),
+            Err(PresetError::UnsupportedFormat(v)) if v == Preset::FORMAT + 1
+        ));
+        assert!(matches!(
+            Preset::from_json("{\"name\": \"No format\"}"),
+            Err(PresetError::Parse(_))
+        ));
+    }
+
+    ///Format 1 presets don't have the modulation and the sound parameters, they load as defaults.
+    #[test]
+    fn format_1_loads() {
+        let mut json: serde_json::Value = serde_json::from_str(&preset("Old").to_json()).unwrap();
+        let fields = json.as_object_mut().unwrap();
+        for field in [
+            "mod_matrix",
+            "macro_assignments",
+            "macros",
+            "params",
+            "fx_order",
+            "tuning",
+        ] {
+            assert!(fields.remove(field).is_some(), "{} is not stored", field);
+        }
+        fields.insert("format".to_string(), 1.into());
+
+        let loaded = Preset::from_json(&json.to_string()).unwrap();
+        assert_eq!(loaded.format, 1);
+        assert!(loaded.mod_matrix.routings.is_empty());
+        assert!(loaded.macros.is_empty());
+        assert!(loaded.params.is_empty());
+    }
+
+    #[test]
+    fn file_stems_are_sanitised() {
+        assert_eq!(file_stem("Bass 1"), "Bass 1");
+        assert_eq!(file_stem("  Pad  "), "Pad");
+        assert_eq!(file_stem("a/b\\c:d*e?f\"g<h>i|j"), "a_b_c_d_e_f_g_h_i_j");
+        assert_eq!(file_stem("tab\there"), "tab_here");
+        assert_eq!(file_stem(""), "Untitled");
+        assert_eq!(file_stem("   "), "Untitled");
+    }
+
+    #[test]
+    fn rename() {
+        let dir = test_dir("rename");
+        let first = preset("First").save(&dir).unwrap();
+        let second = preset("Second").save(&dir).unwrap();
+
+        //Another preset's name is refused, both files are kept
+        assert!(matches!(
+            rename_preset(&first, "Second"),
+            Err(PresetError::Io(_))
+        ));
+        assert_eq!(Preset::load(&first).unwrap().name, "First");
+        assert_eq!(Preset::load(&second).unwrap().name, "Second");
+
+        //Same file name, only the stored name changes
+        assert_eq!(rename_preset(&first, " First ").unwrap(), first);
+        assert_eq!(Preset::load(&first).unwrap().name, " First ");
+
+        let renamed = rename_preset(&first, "Third").unwrap();
+        assert_eq!(renamed, dir.join("Third.json"));
+        assert!(!first.exists());
+        assert_eq!(Preset::load(&renamed).unwrap().name, "Third");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
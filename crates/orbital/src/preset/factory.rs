@@ -0,0 +1,274 @@
+//!Factory presets that are compiled into the plugin. Each one is built from planets and moons, like a user would
+//! build it in the GUI, instead of being stored as a file, so they can't get out of sync with the preset format.
+
+use std::collections::BTreeMap;
+
+use orbital_core::{
+    envelope::EnvelopeParams, gain::GainType, mod_matrix::ModMatrix, osc::modulator::ParentIndex,
+    osc::ModulationType, tuning::Tuning,
+};
+
+use crate::{fx::FxOrder, renderer::solar_system::SolarSystem};
+
+use super::Preset;
+
+///Speed index of a frequency ratio. The speed is counted in octaves.
+fn ratio(r: f32) -> f32 {
+    r.log2()
+}
+
+fn planet(system: &mut SolarSystem, speed: f32, volume: f32) -> ParentIndex {
+    system
+        .add_planet(speed, volume, 0.0)
+        .expect("Factory preset uses too many planets")
+}
+
+fn moon(system: &mut SolarSystem, parent: ParentIndex, speed: f32, range: f32) -> ParentIndex {
+    system
+        .add_moon(parent, speed, range, 0.0)
+        .expect("Factory preset uses too many moons")
+}
+
+fn envelope(attack: f64, decay: f64, sustain_level: f32, release: f64) -> EnvelopeParams {
+    EnvelopeParams {
+        delay: 0.0,
+        attack,
+        hold: 0.0,
+        decay,
+        sustain_level,
+        release,
+    }
+}
+
+fn preset(
+    name: &str,
+    category: &str,
+    solar_system: SolarSystem,
+    envelope: EnvelopeParams,
+    mod_ty: ModulationType,
+    gain_ty: GainType,
+) -> Preset {
+    Preset {
+        format: Preset::FORMAT,
+        plugin_version: env!("CARGO_PKG_VERSION").to_string(),
+        name: name.to_string(),
+        category: category.to_string(),
+        solar_system,
+        envelope,
+        mod_ty,
+        gain_ty,
+        //Everything else starts from its default
+        mod_matrix: ModMatrix::default(),
+        macro_assignments: Vec::new(),
+        macros: Vec::new(),
+        params: BTreeMap::new(),
+        fx_order: FxOrder::default(),
+        tuning: Tuning::default(),
+    }
+}
+
+///Inharmonic 1:3.5 modulator, the classic FM bell.
+fn tubular_bell() -> Preset {
+    let mut system = SolarSystem::empty();
+    let root = planet(&mut system, 0.0, 0.6);
+    moon(&mut system, root, ratio(3.5), 0.45);
+    let shimmer = planet(&mut system, ratio(2.0), 0.2);
+    moon(&mut system, shimmer, ratio(7.11), 0.3);
+    preset(
+        "Tubular Bell",
+        "Bell",
+        system,
+        envelope(0.001, 1.0, 0.0, 1.0),
+        ModulationType::Relative,
+        GainType::Sigmoid,
+    )
+}
+
+///A moon around the modulator makes the bell's overtones wobble.
+fn glass_bell() -> Preset {
+    let mut system = SolarSystem::empty();
+    let root = planet(&mut system, ratio(2.0), 0.5);
+    let bell = moon(&mut system, root, ratio(1.41), 0.4);
+    moon(&mut system, bell, ratio(0.5), 0.25);
+    preset(
+        "Glass Bell",
+        "Bell",
+        system,
+        envelope(0.001, 0.8, 0.1, 0.9),
+        ModulationType::Relative,
+        GainType::Tanh,
+    )
+}
+
+///Tine (1:14) for the attack and a 1:1 moon for the body of the note.
+fn tine_piano() -> Preset {
+    let mut system = SolarSystem::empty();
+    let body = planet(&mut system, 0.0, 0.55);
+    moon(&mut system, body, 0.0, 0.2);
+    let tine = planet(&mut system, 0.0, 0.15);
+    moon(&mut system, tine, ratio(14.0), 0.35);
+    preset(
+        "Tine Piano",
+        "E-Piano",
+        system,
+        envelope(0.002, 0.9, 0.3, 0.3),
+        ModulationType::Relative,
+        GainType::Sigmoid,
+    )
+}
+
+fn tube_keys() -> Preset {
+    let mut system = SolarSystem::empty();
+    let body = planet(&mut system, 0.0, 0.5);
+    moon(&mut system, body, ratio(2.0), 0.15);
+    planet(&mut system, ratio(2.0), 0.1);
+    preset(
+        "Tube Keys",
+        "E-Piano",
+        system,
+        envelope(0.005, 0.6, 0.4, 0.25),
+        ModulationType::Relative,
+        GainType::Tube,
+    )
+}
+
+///Sub octave planet under a strongly modulated one.
+fn growl_bass() -> Preset {
+    let mut system = SolarSystem::empty();
+    planet(&mut system, -1.0, 0.6);
+    let growl = planet(&mut system, 0.0, 0.35);
+    let modulator = moon(&mut system, growl, 0.0, 0.6);
+    moon(&mut system, modulator, -1.0, 0.3);
+    preset(
+        "Growl Bass",
+        "Bass",
+        system,
+        envelope(0.002, 0.3, 0.6, 0.08),
+        ModulationType::Relative,
+        GainType::Cubic,
+    )
+}
+
+fn pluck_bass() -> Preset {
+    let mut system = SolarSystem::empty();
+    let root = planet(&mut system, -1.0, 0.7);
+    moon(&mut system, root, ratio(2.0), 0.35);
+    preset(
+        "Pluck Bass",
+        "Bass",
+        system,
+        envelope(0.001, 0.25, 0.2, 0.1),
+        ModulationType::Relative,
+        GainType::Sigmoid,
+    )
+}
+
+///Three slightly detuned planets, each with a slow moon.
+fn nebula_pad() -> Preset {
+    let mut system = SolarSystem::empty();
+    for detune in [-0.01, 0.0, 0.012] {
+        let voice = planet(&mut system, detune, 0.3);
+        moon(&mut system, voice, ratio(2.0) + detune, 0.15);
+    }
+    preset(
+        "Nebula Pad",
+        "Pad",
+        system,
+        envelope(0.9, 1.0, 0.8, 1.0),
+        ModulationType::Relative,
+        GainType::Tanh,
+    )
+}
+
+///Moons with a fixed, very low speed sweep the planets like an LFO.
+fn orbit_sweep() -> Preset {
+    let mut system = SolarSystem::empty();
+    let low = planet(&mut system, 0.0, 0.4);
+    moon(&mut system, low, -8.0, 0.2);
+    let high = planet(&mut system, 1.0, 0.25);
+    moon(&mut system, high, -7.5, 0.25);
+    preset(
+        "Orbit Sweep",
+        "Pad",
+        system,
+        envelope(0.7, 1.0, 0.9, 1.0),
+        ModulationType::Absolute,
+        GainType::Sigmoid,
+    )
+}
+
+///Low planets in fifths, modulated by a chain of slow moons.
+fn deep_space() -> Preset {
+    let mut system = SolarSystem::empty();
+    let root = planet(&mut system, -2.0, 0.5);
+    let chain = moon(&mut system, root, -7.0, 0.3);
+    moon(&mut system, chain, -9.0, 0.5);
+    let fifth = planet(&mut system, -2.0 + ratio(1.5), 0.3);
+    moon(&mut system, fifth, -6.5, 0.2);
+    preset(
+        "Deep Space",
+        "Drone",
+        system,
+        envelope(1.0, 1.0, 1.0, 1.0),
+        ModulationType::Absolute,
+        GainType::Tanh,
+    )
+}
+
+fn folded_drone() -> Preset {
+    let mut system = SolarSystem::empty();
+    let root = planet(&mut system, -1.0, 0.8);
+    let modulator = moon(&mut system, root, ratio(1.5), 0.5);
+    moon(&mut system, modulator, -8.0, 0.4);
+    preset(
+        "Folded Drone",
+        "Drone",
+        system,
+        envelope(0.8, 1.0, 1.0, 1.0),
+        ModulationType::Relative,
+        GainType::Foldback,
+    )
+}
+
+///All factory presets, sorted by category.
+pub fn factory_presets() -> Vec<Preset> {
+    let mut presets = vec![
+        tubular_bell(),
+        glass_bell(),
+        tine_piano(),
+        tube_keys(),
+        growl_bass(),
+        pluck_bass(),
+        nebula_pad(),
+        orbit_sweep(),
+        deep_space(),
+        folded_drone(),
+    ];
+    presets.sort_by(|a, b| (&a.category, &a.name).cmp(&(&b.category, &b.name)));
+    presets
+}
+
+#[cfg(test)]
+mod tests {
+    use orbital_core::schema::PersistedSolarSystem;
+
+    use super::{factory_presets, Preset};
+
+    ///Building the presets panics if one uses more orbitals than there are slots. Each one has to survive a
+    /// JSON round-trip and persist a solar system that needs no repairs.
+    #[test]
+    fn factory_presets_are_valid() {
+        let presets = factory_presets();
+        assert!(!presets.is_empty());
+        for preset in presets {
+            let json = preset.to_json();
+            let loaded = Preset::from_json(&json)
+                .unwrap_or_else(|e| panic!("{} does not load: {}", preset.name, e));
+            assert_eq!(loaded.to_json(), json, "{} changed", preset.name);
+
+            let mut persisted = PersistedSolarSystem::from(preset.solar_system);
+            let repairs = persisted.validate().unwrap();
+            assert!(repairs.is_empty(), "{}: {:?}", preset.name, repairs);
+        }
+    }
+}
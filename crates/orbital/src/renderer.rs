@@ -1,36 +1,56 @@
 use std::{sync::Arc, time::Instant};
 
-use crate::{
-    com::{ComMsg, GainType},
-    osc::ModulationType,
-    OrbitalParams,
-};
+use crate::{com::ComMsg, preset::Preset, OrbitalParams};
 use crossbeam::channel::Sender;
-use egui::{Color32, Context, Label, Painter, Response, Slider, Stroke, Vec2};
-use nih_plug::{nih_error, prelude::ParamSetter};
+use egui::{Color32, ComboBox, Context, Label, Painter, Response, Slider, Stroke, Vec2};
+use nih_plug::{
+    nih_error,
+    prelude::{EnumParam, ParamSetter},
+};
 use nih_plug_egui::egui::Sense;
+use orbital_core::oversampling::OversamplingFactor;
 
 use self::{
     adsrgui::{GainSwitch, Knob},
+    filter::FilterResponse,
+    fx::FxEditor,
+    macros::MacroEditor,
+    matrix::MatrixEditor,
     modswitch::ModSwitch,
     painter_button::PainterButton,
     ppbutton::PPButton,
+    presets::PresetBrowser,
     switch::Switch,
+    tuning::TuningEditor,
 };
 
 pub mod adsrgui;
+pub mod filter;
+pub mod fx;
+pub mod macros;
+pub mod matrix;
 pub mod modswitch;
 pub mod orbital;
 pub mod painter_button;
 pub mod ppbutton;
+pub mod presets;
 pub mod solar_system;
 pub mod switch;
+pub mod tuning;
 
 pub struct Renderer {
     pub params: Arc<OrbitalParams>,
     pub last_update: Instant,
     pub msg_sender: Sender<ComMsg>,
     show_help: bool,
+    show_matrix: bool,
+    show_macros: bool,
+    show_filter: bool,
+    show_fx: bool,
+    show_tuning: bool,
+    show_presets: bool,
+    tuning_editor: TuningEditor,
+    preset_browser: PresetBrowser,
 }
 
 impl Renderer {
@@ -38,20 +58,6 @@ impl Renderer {
         //setup egui ui context as you usually would. But we gain the `setter` param which we cant
         // access if we implement `ui()` in egui's Widget trait.
 
-        let mut mod_ty = self
-            .params
-            .mod_ty
-            .lock()
-            .map(|t| t.clone())
-            .unwrap_or(ModulationType::default());
-
-        let mut gain_ty = self
-            .params
-            .gain_ty
-            .lock()
-            .map(|g| g.clone())
-            .unwrap_or(GainType::default());
-
         let _tp = egui::TopBottomPanel::top("Toppanel")
             .max_height(50.0)
             .resizable(false)
@@ -61,9 +67,27 @@ impl Renderer {
                     ui.horizontal(|ui| {
                         ui.vertical(|ui| {
                             ui.spacing();
+                            if ui.link("Presets").clicked() {
+                                self.show_presets = !self.show_presets;
+                            }
                             if ui.link("Help").clicked() {
                                 self.show_help = !self.show_help;
                             }
+                            if ui.link("Modulation").clicked() {
+                                self.show_matrix = !self.show_matrix;
+                            }
+                            if ui.link("Macros").clicked() {
+                                self.show_macros = !self.show_macros;
+                            }
+                            if ui.link("Filter").clicked() {
+                                self.show_filter = !self.show_filter;
+                            }
+                            if ui.link("Effects").clicked() {
+                                self.show_fx = !self.show_fx;
+                            }
+                            if ui.link("Tuning").clicked() {
+                                self.show_tuning = !self.show_tuning;
+                            }
                             if ui.link("Creator").clicked() {
                                 let _ = open::that("https://siebencorgie.rs");
                             }
@@ -78,15 +102,26 @@ impl Renderer {
                         ui.add_space(10.0);
 
                         //ui.add(PPButton::new(&mut self.system.paused));
-                        if ui.add(ModSwitch::new(&mut mod_ty)).changed() {
-                            let _ = self
-                                .msg_sender
-                                .send(ComMsg::ModRelationChanged(mod_ty.clone()));
-                        }
-
-                        if ui.add(GainSwitch::new(&mut gain_ty)).changed() {
-                            let _ = self.msg_sender.send(ComMsg::GainChange(gain_ty));
-                        }
+                        ui.add(ModSwitch::new(&self.params.mod_ty, setter));
+                        ui.add(GainSwitch::new(&self.params.gain_ty, setter));
+                        ui.vertical(|ui| {
+                            oversampling_selector(
+                                ui,
+                                "osc_os",
+                                "RT ",
+                                &self.params.oversampling,
+                                setter,
+                                &OversamplingFactor::ALL,
+                            );
+                            oversampling_selector(
+                                ui,
+                                "osc_os_offline",
+                                "Offline ",
+                                &self.params.oversampling_offline,
+                                setter,
+                                &OversamplingFactor::ALL,
+                            );
+                        });
 
                         ui.add_space(10.0);
 
@@ -111,17 +146,76 @@ impl Renderer {
 
                         ui.add_space(10.0);
 
+                        ui.vertical(|ui| {
+                            ui.add(
+                                Knob::new(&self.params.output.drive, setter).with_label("Drive"),
+                            );
+                            oversampling_selector(
+                                ui,
+                                "shaper_os",
+                                "",
+                                &self.params.output.oversampling,
+                                setter,
+                                &[
+                                    OversamplingFactor::None,
+                                    OversamplingFactor::X2,
+                                    OversamplingFactor::X4,
+                                ],
+                            );
+                        });
+                        ui.vertical(|ui| {
+                            ui.add(Knob::new(&self.params.output.gain, setter).with_label("Gain"))
+                        });
+
+                        ui.add_space(10.0);
+
+                        ui.vertical(|ui| {
+                            ui.add(Switch::new(&self.params.filter.on, setter).with_label("Filter"))
+                        });
+                        ui.add(FilterResponse::new(&self.params.filter, setter));
+                        ui.vertical(|ui| {
+                            ui.add(
+                                Knob::new(&self.params.filter.cutoff, setter).with_label("Cutoff"),
+                            )
+                        });
+                        ui.vertical(|ui| {
+                            ui.add(
+                                Knob::new(&self.params.filter.resonance, setter)
+                                    .with_label("Resonance"),
+                            )
+                        });
+
+                        ui.add_space(10.0);
+
                         ui.vertical(|ui| {
                             ui.add(
                                 Switch::new(&self.params.reset_phase, setter)
                                     .with_label("Reset Phase"),
                             )
                         });
+                        ui.vertical(|ui| {
+                            ui.add(
+                                Switch::new(&self.params.free_running, setter)
+                                    .with_label("Free Run"),
+                            )
+                        });
+                        ui.vertical(|ui| {
+                            ui.add(
+                                Switch::new(&self.params.output.dc_block, setter)
+                                    .with_label("DC Block"),
+                            )
+                        });
+                        ui.vertical(|ui| {
+                            ui.add(
+                                Switch::new(&self.params.output.limiter, setter)
+                                    .with_label("Limiter"),
+                            )
+                        });
                         ui.add_space(20.0);
                         ui.vertical(|ui| {
                             if let Ok(mut system) = self.params.solar_system.try_write() {
                                 ui.add_space(10.0);
-                                if ui.add(PPButton::new(&mut system.is_paused)).clicked() {
+                                if ui.add(PPButton::new(&self.params.paused, setter)).clicked() {
                                     system.reset_anim_state();
                                 }
                             } else {
@@ -269,6 +363,161 @@ All the modification can also be done by selecting a planet (left click), and dr
                 }
             });
         }
+        if self.show_matrix {
+            let slots = self
+                .params
+                .solar_system
+                .read()
+                .map(|s| s.used_slots())
+                .unwrap_or_default();
+            let mut is_open = true;
+            let _ = egui::Window::new("Modulation")
+                .open(&mut is_open)
+                .show(eguictx, |ui| {
+                    if let Ok(mut matrix) = self.params.mod_matrix.write() {
+                        if ui.add(MatrixEditor::new(&mut matrix, &slots)).changed() {
+                            let _ = self
+                                .msg_sender
+                                .send(ComMsg::ModMatrixChange(matrix.clone()));
+                        }
+                    } else {
+                        nih_error!("Could not lock modulation matrix!");
+                    }
+                });
+            self.show_matrix = is_open;
+        }
+
+        if self.show_macros {
+            let slots = self
+                .params
+                .solar_system
+                .read()
+                .map(|s| s.used_slots())
+                .unwrap_or_default();
+            let mut is_open = true;
+            let _ = egui::Window::new("Macros")
+                .open(&mut is_open)
+                .show(eguictx, |ui| {
+                    if let Ok(mut assignments) = self.params.macro_assignments.write() {
+                        if ui
+                            .add(MacroEditor::new(
+                                &self.params.macros,
+                                setter,
+                                &mut assignments,
+                                &slots,
+                            ))
+                            .changed()
+                        {
+                            let _ = self
+                                .msg_sender
+                                .send(ComMsg::MacroChange(assignments.clone()));
+                        }
+                    } else {
+                        nih_error!("Could not lock macro assignments!");
+                    }
+                });
+            self.show_macros = is_open;
+        }
+
+        if self.show_filter {
+            let filter = &self.params.filter;
+            let mut is_open = true;
+            let _ = egui::Window::new("Filter")
+                .open(&mut is_open)
+                .show(eguictx, |ui| {
+                    ui.horizontal(|ui| {
+                        ui.vertical(|ui| {
+                            ui.add(Knob::new(&filter.env_amount, setter).with_label("Env Amount"))
+                        });
+                        ui.vertical(|ui| {
+                            ui.add(Knob::new(&filter.key_track, setter).with_label("Key Track"))
+                        });
+                        ui.vertical(|ui| {
+                            ui.add(Knob::new(&filter.velocity, setter).with_label("Velocity"))
+                        });
+                    });
+                    ui.separator();
+                    ui.horizontal(|ui| {
+                        ui.vertical(|ui| {
+                            ui.add(Knob::new(&filter.attack, setter).with_label("Attack"))
+                        });
+                        ui.vertical(|ui| {
+                            ui.add(Knob::new(&filter.decay, setter).with_label("Decay"))
+                        });
+                        ui.vertical(|ui| {
+                            ui.add(Knob::new(&filter.sustain, setter).with_label("Sustain"))
+                        });
+                        ui.vertical(|ui| {
+                            ui.add(Knob::new(&filter.release, setter).with_label("Release"))
+                        });
+                    });
+                });
+            self.show_filter = is_open;
+        }
+
+        if self.show_fx {
+            let mut is_open = true;
+            let _ = egui::Window::new("Effects")
+                .open(&mut is_open)
+                .show(eguictx, |ui| {
+                    if let Ok(mut order) = self.params.fx_order.write() {
+                        if ui
+                            .add(FxEditor::new(&self.params.fx, setter, &mut order))
+                            .changed()
+                        {
+                            let _ = self.msg_sender.send(ComMsg::FxOrderChange(*order));
+                        }
+                    } else {
+                        nih_error!("Could not lock effect order!");
+                    }
+                });
+            self.show_fx = is_open;
+        }
+
+        if self.show_tuning {
+            let mut is_open = true;
+            let _ = egui::Window::new("Tuning")
+                .open(&mut is_open)
+                .show(eguictx, |ui| {
+                    ui.add(
+                        Knob::new(&self.params.reference_pitch, setter)
+                            .with_label("Reference Pitch"),
+                    );
+                    ui.separator();
+                    if let Ok(mut tuning) = self.params.tuning.write() {
+                        if self.tuning_editor.show(ui, &mut tuning) {
+                            let _ = self.msg_sender.send(ComMsg::TuningChange(tuning.clone()));
+                        }
+                    } else {
+                        nih_error!("Could not lock tuning!");
+                    }
+                });
+            self.show_tuning = is_open;
+        }
+
+        if self.show_presets {
+            let mut is_open = true;
+            let _ = egui::Window::new("Presets")
+                .open(&mut is_open)
+                .show(eguictx, |ui| {
+                    if let Some(preset) = self.preset_browser.show(ui, &self.params) {
+                        let loaded = preset.apply(&self.params, setter);
+                        if let Ok(mut system) = self.params.solar_system.write() {
+                            *system = loaded;
+                            //A loaded system has not synced any slot yet, so all of them are pushed to the host.
+                            system.write_params(&self.params, setter);
+                            let _ = self
+                                .msg_sender
+                                .send(ComMsg::TopologyChange(system.get_topology()));
+                        } else {
+                            nih_error!("Could not set solar state!");
+                        }
+                        self.load_persisted(&preset);
+                    }
+                });
+            self.show_presets = is_open;
+        }
+
         egui::CentralPanel::default().show(eguictx, |ui| {
             let mut rect = ui.clip_rect();
             const RED: f32 = 65f32;
@@ -276,7 +525,18 @@ All the modification can also be done by selecting a planet (left click), and dr
             rect.min.y += RED;
             let (response, painter) = ui.allocate_painter(rect.size(), Sense::click_and_drag());
             if let Ok(mut system) = self.params.solar_system.try_write() {
-                system.handle_response(&mut self.msg_sender, &response, &ui.input());
+                system.is_paused = self.params.paused.value();
+                if system.handle_response(&response, &ui.input()) {
+                    //GUI changed something, record that as parameter change and notify
+                    // the synth of possible structural changes.
+                    system.write_params(&self.params, setter);
+                    let _ = self
+                        .msg_sender
+                        .send(ComMsg::TopologyChange(system.get_topology()));
+                } else {
+                    //Otherwise let the host's automation move the planets
+                    system.read_params(&self.params);
+                }
                 system.paint(rect.center(), &painter);
             } else {
                 nih_error!("Could not set solar state!");
@@ -285,6 +545,31 @@ All the modification can also be done by selecting a planet (left click), and dr
     }
 }
 
+///Combo box for an oversampling parameter, offering the given `factors`.
+fn oversampling_selector(
+    ui: &mut egui::Ui,
+    id: &str,
+    prefix: &str,
+    param: &EnumParam<OversamplingFactor>,
+    setter: &ParamSetter,
+    factors: &[OversamplingFactor],
+) {
+    let mut factor = param.value();
+    ComboBox::from_id_source(id)
+        .width(40.0)
+        .selected_text(format!("{}{}", prefix, factor.name()))
+        .show_ui(ui, |ui| {
+            for f in factors {
+                ui.selectable_value(&mut factor, *f, f.name());
+            }
+        });
+    if factor != param.value() {
+        setter.begin_set_parameter(param);
+        setter.set_parameter(param, factor);
+        setter.end_set_parameter(param);
+    }
+}
+
 impl Renderer {
     pub fn new(params: Arc<OrbitalParams>, com_sender: Sender<ComMsg>) -> Self {
         Renderer {
@@ -292,6 +577,47 @@ impl Renderer {
             last_update: Instant::now(),
             msg_sender: com_sender,
             show_help: false,
+            show_matrix: false,
+            show_macros: false,
+            show_filter: false,
+            show_fx: false,
+            show_tuning: false,
+            show_presets: false,
+            tuning_editor: TuningEditor::default(),
+            preset_browser: PresetBrowser::default(),
+        }
+    }
+
+    ///Swaps in the modulation matrix, macro assignments, effect order and tuning of `preset` and notifies the
+    /// synth.
+    fn load_persisted(&self, preset: &Preset) {
+        if let Ok(mut matrix) = self.params.mod_matrix.write() {
+            *matrix = preset.mod_matrix.clone();
+            let _ = self
+                .msg_sender
+                .send(ComMsg::ModMatrixChange(matrix.clone()));
+        } else {
+            nih_error!("Could not lock modulation matrix!");
+        }
+        if let Ok(mut assignments) = self.params.macro_assignments.write() {
+            *assignments = preset.macro_assignments.clone();
+            let _ = self
+                .msg_sender
+                .send(ComMsg::MacroChange(assignments.clone()));
+        } else {
+            nih_error!("Could not lock macro assignments!");
+        }
+        if let Ok(mut order) = self.params.fx_order.write() {
+            *order = preset.fx_order;
+            let _ = self.msg_sender.send(ComMsg::FxOrderChange(*order));
+        } else {
+            nih_error!("Could not lock effect order!");
+        }
+        if let Ok(mut tuning) = self.params.tuning.write() {
+            *tuning = preset.tuning.clone();
+            let _ = self.msg_sender.send(ComMsg::TuningChange(tuning.clone()));
+        } else {
+            nih_error!("Could not lock tuning!");
         }
     }
 }
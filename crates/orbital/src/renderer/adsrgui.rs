@@ -1,10 +1,7 @@
 use super::orbital::{rotate_vec2, TWOPI};
-use crate::com::GainType;
-use egui::{
-    epaint::CubicBezierShape, Align2, Color32, FontId, Label, Response, Sense, Shape, Stroke, Vec2,
-    Widget,
-};
-use nih_plug::prelude::{Param, ParamSetter};
+use egui::{Align2, Color32, FontId, Label, Response, Sense, Shape, Stroke, Vec2, Widget};
+use nih_plug::prelude::{EnumParam, Param, ParamSetter};
+use orbital_core::gain::GainType;
 
 #[must_use = "You should put this widget in an ui with `ui.add(widget);`"]
 pub struct Knob<'a, P: Param> {
@@ -149,21 +146,27 @@ impl<'a, P: Param> Widget for Knob<'a, P> {
     }
 }
 
+///Selects the voice composition curve. Left click selects the next, right click the previous curve.
+/// Shows the transfer curve of the current selection.
 pub struct GainSwitch<'a> {
-    value: &'a mut GainType,
+    param: &'a EnumParam<GainType>,
+    setter: &'a ParamSetter<'a>,
 }
 
 impl<'a> GainSwitch<'a> {
     pub const SIZE: Vec2 = Vec2 { x: 100.0, y: 65.0 };
-    const XOFF: f32 = 20.0;
-    const YOFF: f32 = 15.0;
+    const XOFF: f32 = 40.0;
+    const YOFF: f32 = 20.0;
+    ///Input range that is drawn, in both directions.
+    const INPUT_RANGE: f32 = 2.0;
+    const SAMPLES: usize = 64;
     pub const COLOR: Color32 = Color32::WHITE;
     pub const STROKE: Stroke = Stroke {
         width: 1.0,
         color: Self::COLOR,
     };
-    pub fn new(value: &'a mut GainType) -> Self {
-        GainSwitch { value }
+    pub fn new(param: &'a EnumParam<GainType>, setter: &'a ParamSetter<'a>) -> Self {
+        GainSwitch { param, setter }
     }
 }
 
@@ -171,8 +174,16 @@ impl<'a> Widget for GainSwitch<'a> {
     fn ui(self, ui: &mut egui::Ui) -> Response {
         let (mut resp, painter) = ui.allocate_painter(Self::SIZE, Sense::click());
 
-        if resp.clicked() {
-            self.value.next();
+        if resp.clicked() || resp.secondary_clicked() {
+            let mut next = self.param.value();
+            if resp.clicked() {
+                next.next();
+            } else {
+                next.prev();
+            }
+            self.setter.begin_set_parameter(self.param);
+            self.setter.set_parameter(self.param, next);
+            self.setter.end_set_parameter(self.param);
             resp.mark_changed();
         }
 
@@ -186,134 +197,29 @@ impl<'a> Widget for GainSwitch<'a> {
             Self::STROKE
         };
 
-        match self.value {
-            GainType::Linear => {
-                painter.line_segment(
-                    [
-                        rect.left_center()
-                            + Vec2 {
-                                x: 0.0,
-                                y: Self::YOFF,
-                            },
-                        rect.center()
-                            + Vec2 {
-                                x: -Self::XOFF,
-                                y: Self::YOFF,
-                            },
-                    ],
-                    stroke,
-                );
-
-                painter.line_segment(
-                    [
-                        rect.center()
-                            + Vec2 {
-                                x: -Self::XOFF,
-                                y: Self::YOFF,
-                            },
-                        rect.center()
-                            + Vec2 {
-                                x: Self::XOFF,
-                                y: -Self::YOFF,
-                            },
-                    ],
-                    stroke,
-                );
-
-                painter.line_segment(
-                    [
-                        rect.center()
-                            + Vec2 {
-                                x: Self::XOFF,
-                                y: -Self::YOFF,
-                            },
-                        rect.right_center()
-                            + Vec2 {
-                                x: 0.0,
-                                y: -Self::YOFF,
-                            },
-                    ],
-                    stroke,
-                );
-
-                painter.text(
-                    rect.center_bottom(),
-                    Align2::CENTER_BOTTOM,
-                    "Linear",
-                    FontId::default(),
-                    Color32::GRAY,
-                );
-            }
-            GainType::Sigmoid => {
-                painter.line_segment(
-                    [
-                        rect.left_center()
-                            + Vec2 {
-                                x: 0.0,
-                                y: Self::YOFF,
-                            },
-                        rect.center()
-                            + Vec2 {
-                                x: -Self::XOFF,
-                                y: Self::YOFF,
-                            },
-                    ],
-                    stroke,
-                );
-
-                painter.add(Shape::CubicBezier(CubicBezierShape::from_points_stroke(
-                    [
-                        rect.center()
-                            + Vec2 {
-                                x: -Self::XOFF,
-                                y: Self::YOFF,
-                            },
-                        rect.center()
-                            + Vec2 {
-                                x: 0.0,
-                                y: Self::YOFF,
-                            },
-                        rect.center()
-                            + Vec2 {
-                                x: 0.0,
-                                y: -Self::YOFF,
-                            },
-                        rect.center()
-                            + Vec2 {
-                                x: Self::XOFF,
-                                y: -Self::YOFF,
-                            },
-                    ],
-                    false,
-                    Color32::TRANSPARENT,
-                    stroke,
-                )));
-
-                painter.line_segment(
-                    [
-                        rect.center()
-                            + Vec2 {
-                                x: Self::XOFF,
-                                y: -Self::YOFF,
-                            },
-                        rect.right_center()
-                            + Vec2 {
-                                x: 0.0,
-                                y: -Self::YOFF,
-                            },
-                    ],
-                    stroke,
-                );
+        let gain_ty = self.param.value();
+        //Center of the curve, leaves some space for the label
+        let center = rect.center() - Vec2 { x: 0.0, y: 5.0 };
+        let points = (0..=Self::SAMPLES)
+            .map(|i| {
+                let t = i as f32 / Self::SAMPLES as f32 * 2.0 - 1.0;
+                let y = gain_ty.map(t * Self::INPUT_RANGE);
+                center
+                    + Vec2 {
+                        x: t * Self::XOFF,
+                        y: -y * Self::YOFF,
+                    }
+            })
+            .collect();
+        painter.add(Shape::line(points, stroke));
 
-                painter.text(
-                    rect.center_bottom(),
-                    Align2::CENTER_BOTTOM,
-                    "Sigmoid",
-                    FontId::default(),
-                    Color32::GRAY,
-                );
-            }
-        }
+        painter.text(
+            rect.center_bottom(),
+            Align2::CENTER_BOTTOM,
+            gain_ty.name(),
+            FontId::default(),
+            Color32::GRAY,
+        );
         resp
     }
 }
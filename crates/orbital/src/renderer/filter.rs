@@ -0,0 +1,97 @@
+use egui::{Align2, Color32, FontId, Pos2, Response, Sense, Shape, Stroke, Vec2, Widget};
+use nih_plug::prelude::{Enum, EnumParam, ParamSetter};
+
+use crate::filter::FilterParams;
+
+///Draws the magnitude response of the filter. Left click selects the next mode, right click toggles
+/// the slope. Drawn gray if the filter is turned off.
+pub struct FilterResponse<'a> {
+    params: &'a FilterParams,
+    setter: &'a ParamSetter<'a>,
+}
+
+impl<'a> FilterResponse<'a> {
+    pub const SIZE: Vec2 = Vec2 { x: 120.0, y: 45.0 };
+    pub const STROKE: Stroke = Stroke {
+        width: 1.0,
+        color: Color32::WHITE,
+    };
+    const MIN_FREQ: f32 = 20.0;
+    const MAX_FREQ: f32 = 20000.0;
+    ///Range of the drawn magnitude
+    const MIN_DB: f32 = -36.0;
+    const MAX_DB: f32 = 18.0;
+    const SAMPLES: usize = 64;
+
+    pub fn new(params: &'a FilterParams, setter: &'a ParamSetter<'a>) -> Self {
+        FilterResponse { params, setter }
+    }
+
+    fn set<T: Enum + PartialEq + 'static>(&self, param: &EnumParam<T>, value: T) {
+        self.setter.begin_set_parameter(param);
+        self.setter.set_parameter(param, value);
+        self.setter.end_set_parameter(param);
+    }
+}
+
+impl<'a> Widget for FilterResponse<'a> {
+    fn ui(self, ui: &mut egui::Ui) -> Response {
+        let (mut resp, painter) = ui.allocate_painter(Self::SIZE, Sense::click());
+
+        if resp.clicked() {
+            self.set(&self.params.mode, self.params.mode.value().next());
+            resp.mark_changed();
+        }
+        if resp.secondary_clicked() {
+            self.set(&self.params.slope, self.params.slope.value().next());
+            resp.mark_changed();
+        }
+
+        let rect = painter.clip_rect();
+        let settings = self.params.settings();
+
+        let mut stroke = Self::STROKE;
+        if resp.hovered() {
+            stroke.width = 2.0;
+        }
+        if !settings.enabled {
+            stroke.color = Color32::DARK_GRAY;
+        }
+
+        //Unity gain line
+        let unity_y = rect.top() + rect.height() * Self::MAX_DB / (Self::MAX_DB - Self::MIN_DB);
+        painter.line_segment(
+            [
+                Pos2::new(rect.left(), unity_y),
+                Pos2::new(rect.right(), unity_y),
+            ],
+            Stroke::new(1.0, Color32::DARK_GRAY),
+        );
+
+        let octaves = (Self::MAX_FREQ / Self::MIN_FREQ).log2();
+        let points = (0..=Self::SAMPLES)
+            .map(|i| {
+                let t = i as f32 / Self::SAMPLES as f32;
+                let freq = Self::MIN_FREQ * 2.0f32.powf(t * octaves);
+                let db = nih_plug::util::gain_to_db(settings.magnitude(settings.cutoff, freq))
+                    .clamp(Self::MIN_DB, Self::MAX_DB);
+                Pos2::new(
+                    rect.left() + t * rect.width(),
+                    rect.top()
+                        + rect.height() * (Self::MAX_DB - db) / (Self::MAX_DB - Self::MIN_DB),
+                )
+            })
+            .collect();
+        painter.add(Shape::line(points, stroke));
+
+        painter.text(
+            rect.right_top(),
+            Align2::RIGHT_TOP,
+            format!("{} {}", settings.mode.name(), settings.slope.name()),
+            FontId::proportional(10.0),
+            Color32::GRAY,
+        );
+
+        resp
+    }
+}
@@ -0,0 +1,84 @@
+use egui::{Grid, Widget};
+use nih_plug::prelude::ParamSetter;
+
+use crate::fx::{FxKind, FxOrder, FxParams};
+
+use super::{adsrgui::Knob, switch::Switch};
+
+///Shows the effect chain in processing order. Effects can be moved up and down in the chain,
+/// which is reported by `changed()`.
+pub struct FxEditor<'a> {
+    params: &'a FxParams,
+    setter: &'a ParamSetter<'a>,
+    order: &'a mut FxOrder,
+}
+
+impl<'a> FxEditor<'a> {
+    pub fn new(params: &'a FxParams, setter: &'a ParamSetter<'a>, order: &'a mut FxOrder) -> Self {
+        FxEditor {
+            params,
+            setter,
+            order,
+        }
+    }
+
+    fn controls(&self, ui: &mut egui::Ui, kind: FxKind) {
+        let setter = self.setter;
+        match kind {
+            FxKind::Chorus => {
+                let p = &self.params.chorus;
+                ui.add(Switch::new(&p.bypass, setter).with_label("Bypass"));
+                ui.add(Knob::new(&p.mix, setter).with_label("Mix"));
+                ui.add(Knob::new(&p.rate, setter).with_label("Rate"));
+                ui.add(Knob::new(&p.depth, setter).with_label("Depth"));
+            }
+            FxKind::Delay => {
+                let p = &self.params.delay;
+                ui.add(Switch::new(&p.bypass, setter).with_label("Bypass"));
+                ui.add(Knob::new(&p.mix, setter).with_label("Mix"));
+                ui.add(Knob::new(&p.sync, setter).with_label("Time"));
+                ui.add(Knob::new(&p.feedback, setter).with_label("Feedback"));
+                ui.add(Switch::new(&p.ping_pong, setter).with_label("Ping-Pong"));
+            }
+            FxKind::Reverb => {
+                let p = &self.params.reverb;
+                ui.add(Switch::new(&p.bypass, setter).with_label("Bypass"));
+                ui.add(Knob::new(&p.mix, setter).with_label("Mix"));
+                ui.add(Knob::new(&p.size, setter).with_label("Size"));
+                ui.add(Knob::new(&p.damping, setter).with_label("Damping"));
+            }
+        }
+    }
+}
+
+impl<'a> Widget for FxEditor<'a> {
+    fn ui(self, ui: &mut egui::Ui) -> egui::Response {
+        let mut swap = None;
+        let mut resp = ui
+            .vertical(|ui| {
+                Grid::new("fx_grid").striped(true).show(ui, |ui| {
+                    let last = self.order.0.len() - 1;
+                    for (idx, kind) in self.order.0.iter().enumerate() {
+                        ui.label(kind.name());
+                        ui.vertical(|ui| {
+                            if ui.add_enabled(idx > 0, egui::Button::new("⏶")).clicked() {
+                                swap = Some((idx, idx - 1));
+                            }
+                            if ui.add_enabled(idx < last, egui::Button::new("⏷")).clicked() {
+                                swap = Some((idx, idx + 1));
+                            }
+                        });
+                        ui.horizontal(|ui| self.controls(ui, *kind));
+                        ui.end_row();
+                    }
+                });
+            })
+            .response;
+
+        if let Some((a, b)) = swap {
+            self.order.swap(a, b);
+            resp.mark_changed();
+        }
+        resp
+    }
+}
@@ -0,0 +1,158 @@
+use egui::{ComboBox, DragValue, Grid, Widget};
+use nih_plug::prelude::ParamSetter;
+
+use orbital_core::{osc::modulator::ParentIndex, state::OrbitalProperty};
+
+use crate::macros::{MacroAssignment, MacroParams, MACRO_COUNT};
+
+use super::adsrgui::Knob;
+
+fn slot_name(slot: &ParentIndex) -> String {
+    match slot {
+        ParentIndex::Primary(s) => format!("Primary {}", s),
+        ParentIndex::Modulator(s) => format!("Modulator {}", s),
+    }
+}
+
+///Shows the macro knobs and lets the user assign them to orbital properties. Reports `changed()`
+/// whenever an assignment was edited.
+pub struct MacroEditor<'a> {
+    macros: &'a [MacroParams; MACRO_COUNT],
+    setter: &'a ParamSetter<'a>,
+    assignments: &'a mut Vec<MacroAssignment>,
+    ///Slots of all orbitals that currently exist.
+    slots: &'a [ParentIndex],
+}
+
+impl<'a> MacroEditor<'a> {
+    ///Maximum number of assignments.
+    pub const MAX_ASSIGNMENTS: usize = 32;
+
+    pub fn new(
+        macros: &'a [MacroParams; MACRO_COUNT],
+        setter: &'a ParamSetter<'a>,
+        assignments: &'a mut Vec<MacroAssignment>,
+        slots: &'a [ParentIndex],
+    ) -> Self {
+        MacroEditor {
+            macros,
+            setter,
+            assignments,
+            slots,
+        }
+    }
+}
+
+impl<'a> Widget for MacroEditor<'a> {
+    fn ui(self, ui: &mut egui::Ui) -> egui::Response {
+        let mut changed = false;
+        let mut resp = ui
+            .vertical(|ui| {
+                ui.horizontal(|ui| {
+                    for (idx, m) in self.macros.iter().enumerate() {
+                        let label = format!("Macro {}", idx + 1);
+                        ui.vertical(|ui| {
+                            ui.add(Knob::new(&m.value, self.setter).with_label(&label))
+                        });
+                    }
+                });
+                ui.separator();
+
+                let mut to_remove = None;
+                Grid::new("macro_grid").striped(true).show(ui, |ui| {
+                    ui.label("Macro");
+                    ui.label("Orbital");
+                    ui.label("Property");
+                    ui.label("Min");
+                    ui.label("Max");
+                    ui.end_row();
+
+                    for (idx, assignment) in self.assignments.iter_mut().enumerate() {
+                        let mut macro_idx = assignment.macro_idx;
+                        ComboBox::from_id_source(("macro_idx", idx))
+                            .selected_text(format!("Macro {}", macro_idx + 1))
+                            .show_ui(ui, |ui| {
+                                for m in 0..MACRO_COUNT {
+                                    ui.selectable_value(
+                                        &mut macro_idx,
+                                        m,
+                                        format!("Macro {}", m + 1),
+                                    );
+                                }
+                            });
+
+                        let mut target = assignment.target;
+                        ComboBox::from_id_source(("macro_target", idx))
+                            .selected_text(slot_name(&target))
+                            .show_ui(ui, |ui| {
+                                for s in self.slots {
+                                    ui.selectable_value(&mut target, *s, slot_name(s));
+                                }
+                            });
+
+                        let mut property = assignment.property;
+                        ComboBox::from_id_source(("macro_property", idx))
+                            .selected_text(property.name())
+                            .show_ui(ui, |ui| {
+                                for p in OrbitalProperty::ALL {
+                                    ui.selectable_value(&mut property, p, p.name());
+                                }
+                            });
+
+                        if macro_idx != assignment.macro_idx || target != assignment.target {
+                            assignment.macro_idx = macro_idx;
+                            assignment.target = target;
+                            changed = true;
+                        }
+                        if property != assignment.property {
+                            //Reset the range, since the old one most likely makes no sense anymore
+                            assignment.property = property;
+                            (assignment.min, assignment.max) = property.range();
+                            changed = true;
+                        }
+
+                        let (lower, upper) = assignment.property.range();
+                        changed |= ui
+                            .add(
+                                DragValue::new(&mut assignment.min)
+                                    .speed(0.01)
+                                    .clamp_range(lower..=upper),
+                            )
+                            .changed();
+                        changed |= ui
+                            .add(
+                                DragValue::new(&mut assignment.max)
+                                    .speed(0.01)
+                                    .clamp_range(lower..=upper),
+                            )
+                            .changed();
+
+                        if ui.button("✖").clicked() {
+                            to_remove = Some(idx);
+                        }
+                        ui.end_row();
+                    }
+                });
+
+                if let Some(idx) = to_remove {
+                    self.assignments.remove(idx);
+                    changed = true;
+                }
+
+                if self.assignments.len() < Self::MAX_ASSIGNMENTS {
+                    if let Some(first) = self.slots.first() {
+                        if ui.button("Add assignment").clicked() {
+                            self.assignments.push(MacroAssignment::new(0, *first));
+                            changed = true;
+                        }
+                    }
+                }
+            })
+            .response;
+
+        if changed {
+            resp.mark_changed();
+        }
+        resp
+    }
+}
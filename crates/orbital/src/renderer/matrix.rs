@@ -0,0 +1,164 @@
+use egui::{ComboBox, Grid, Slider, Widget};
+
+use orbital_core::{
+    envelope::EnvelopeParams,
+    mod_matrix::{Lfo, LfoShape, ModDestination, ModMatrix, ModSource, Routing},
+    osc::modulator::ParentIndex,
+};
+
+///Editor for the modulation matrix. Reports `changed()` whenever any routing or source setting
+/// was edited.
+pub struct MatrixEditor<'a> {
+    matrix: &'a mut ModMatrix,
+    ///Slots of all orbitals that currently exist. Used to build the destination list.
+    slots: &'a [ParentIndex],
+}
+
+impl<'a> MatrixEditor<'a> {
+    pub fn new(matrix: &'a mut ModMatrix, slots: &'a [ParentIndex]) -> Self {
+        MatrixEditor { matrix, slots }
+    }
+
+    fn destinations(&self) -> Vec<ModDestination> {
+        let mut dsts = Vec::with_capacity(self.slots.len() * 2 + ModDestination::GLOBAL.len());
+        for slot in self.slots {
+            dsts.push(ModDestination::Radius(*slot));
+            dsts.push(ModDestination::Speed(*slot));
+        }
+        dsts.extend_from_slice(&ModDestination::GLOBAL);
+        dsts
+    }
+}
+
+fn lfo_ui(ui: &mut egui::Ui, idx: usize, lfo: &mut Lfo) -> bool {
+    let mut changed = false;
+    ui.label(format!("LFO {}", idx + 1));
+    let mut shape = lfo.shape;
+    ComboBox::from_id_source(("lfo_shape", idx))
+        .selected_text(shape.name())
+        .show_ui(ui, |ui| {
+            for s in LfoShape::ALL {
+                ui.selectable_value(&mut shape, s, s.name());
+            }
+        });
+    if shape != lfo.shape {
+        lfo.shape = shape;
+        changed = true;
+    }
+    changed |= ui
+        .add(
+            Slider::new(&mut lfo.rate, 0.01..=Lfo::MAX_RATE)
+                .logarithmic(true)
+                .suffix(" Hz"),
+        )
+        .changed();
+    changed
+}
+
+fn envelope_ui(ui: &mut egui::Ui, env: &mut EnvelopeParams) -> bool {
+    let mut changed = false;
+    ui.label("Mod Envelope");
+    ui.horizontal(|ui| {
+        for (label, value) in [
+            ("D", &mut env.delay),
+            ("A", &mut env.attack),
+            ("H", &mut env.hold),
+            ("D", &mut env.decay),
+            ("R", &mut env.release),
+        ] {
+            ui.label(label);
+            changed |= ui
+                .add(
+                    egui::DragValue::new(value)
+                        .speed(0.01)
+                        .clamp_range(0.0001..=1.0),
+                )
+                .changed();
+        }
+        ui.label("S");
+        changed |= ui
+            .add(
+                egui::DragValue::new(&mut env.sustain_level)
+                    .speed(0.01)
+                    .clamp_range(0.0..=1.0),
+            )
+            .changed();
+    });
+    changed
+}
+
+impl<'a> Widget for MatrixEditor<'a> {
+    fn ui(self, ui: &mut egui::Ui) -> egui::Response {
+        let destinations = self.destinations();
+        let mut changed = false;
+        let mut resp = ui
+            .vertical(|ui| {
+                for (idx, lfo) in self.matrix.lfos.iter_mut().enumerate() {
+                    ui.horizontal(|ui| changed |= lfo_ui(ui, idx, lfo));
+                }
+                changed |= envelope_ui(ui, &mut self.matrix.mod_envelope);
+                ui.separator();
+
+                let mut to_remove = None;
+                Grid::new("matrix_grid").striped(true).show(ui, |ui| {
+                    ui.label("Source");
+                    ui.label("Destination");
+                    ui.label("Amount");
+                    ui.end_row();
+
+                    for (idx, routing) in self.matrix.routings.iter_mut().enumerate() {
+                        let mut source = routing.source;
+                        ComboBox::from_id_source(("routing_src", idx))
+                            .selected_text(source.name())
+                            .show_ui(ui, |ui| {
+                                for s in ModSource::ALL {
+                                    ui.selectable_value(&mut source, s, s.name());
+                                }
+                            });
+
+                        let mut destination = routing.destination;
+                        ComboBox::from_id_source(("routing_dst", idx))
+                            .selected_text(destination.name())
+                            .width(160.0)
+                            .show_ui(ui, |ui| {
+                                for d in &destinations {
+                                    ui.selectable_value(&mut destination, *d, d.name());
+                                }
+                            });
+
+                        if source != routing.source || destination != routing.destination {
+                            routing.source = source;
+                            routing.destination = destination;
+                            changed = true;
+                        }
+
+                        changed |= ui
+                            .add(Slider::new(&mut routing.amount, -1.0..=1.0).fixed_decimals(2))
+                            .changed();
+                        if ui.button("✖").clicked() {
+                            to_remove = Some(idx);
+                        }
+                        ui.end_row();
+                    }
+                });
+
+                if let Some(idx) = to_remove {
+                    self.matrix.routings.remove(idx);
+                    changed = true;
+                }
+
+                if self.matrix.routings.len() < ModMatrix::MAX_ROUTINGS
+                    && ui.button("Add routing").clicked()
+                {
+                    self.matrix.routings.push(Routing::default());
+                    changed = true;
+                }
+            })
+            .response;
+
+        if changed {
+            resp.mark_changed();
+        }
+        resp
+    }
+}
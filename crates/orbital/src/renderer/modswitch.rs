@@ -1,19 +1,21 @@
 use egui::{Align2, Color32, FontId, Sense, Stroke, Vec2, Widget};
+use nih_plug::prelude::{EnumParam, ParamSetter};
 
-use crate::osc::ModulationType;
+use orbital_core::osc::ModulationType;
 
 use super::adsrgui::GainSwitch;
 
 pub struct ModSwitch<'a> {
-    value: &'a mut ModulationType,
+    param: &'a EnumParam<ModulationType>,
+    setter: &'a ParamSetter<'a>,
 }
 
 impl<'a> ModSwitch<'a> {
     const SIZE: Vec2 = GainSwitch::SIZE;
     const SPLIT: f32 = 10.0;
     const STROKE: Stroke = GainSwitch::STROKE;
-    pub fn new(value: &'a mut ModulationType) -> Self {
-        ModSwitch { value }
+    pub fn new(param: &'a EnumParam<ModulationType>, setter: &'a ParamSetter<'a>) -> Self {
+        ModSwitch { param, setter }
     }
 }
 
@@ -24,7 +26,10 @@ impl<'a> Widget for ModSwitch<'a> {
         let rect = painter.clip_rect();
 
         if resp.clicked() {
-            *self.value = self.value.next();
+            self.setter.begin_set_parameter(self.param);
+            self.setter
+                .set_parameter(self.param, self.param.value().next());
+            self.setter.end_set_parameter(self.param);
             resp.mark_changed();
         }
         let stroke = if resp.hovered(){
@@ -35,7 +40,7 @@ impl<'a> Widget for ModSwitch<'a> {
             Self::STROKE
         };
 
-        match self.value {
+        match self.param.value() {
             ModulationType::Absolute => {
                 painter.line_segment(
                     [
@@ -1,16 +1,10 @@
 use colorgrad::Gradient;
 use nih_plug::nih_log;
 use nih_plug_egui::egui::{epaint::CircleShape, Color32, Painter, Pos2, Shape, Stroke, Vec2};
-use serde_derive::{Deserialize, Serialize};
-use std::f32::consts::PI;
-
-use crate::{
-    com::{ModulatorState, PrimaryState, SolarState},
-    osc::{
-        modulator::{ModulatorOsc, ParentIndex},
-        primary::PrimaryOsc,
-    },
-};
+
+use orbital_core::{osc::modulator::ParentIndex, schema::PersistedOrbital};
+
+use crate::{com::SolarTopology, slots::SlotValues};
 
 use super::solar_system::SlotAllocator;
 
@@ -25,7 +19,7 @@ lazy_static::lazy_static! {
     static ref RAMP_ASTROID: Gradient = colorgrad::inferno();
 }
 
-pub const TWOPI: f32 = 2.0 * PI;
+pub use orbital_core::TWOPI;
 pub fn rotate_vec2(src: Vec2, angle: f32) -> Vec2 {
     let cos = angle.cos();
     let sin = angle.sin();
@@ -37,7 +31,7 @@ pub fn rotate_vec2(src: Vec2, angle: f32) -> Vec2 {
     v
 }
 
-#[derive(Clone, Copy, Serialize, Deserialize, Debug)]
+#[derive(Clone, Copy, Debug)]
 pub enum ObjTy {
     Sun,
     Planet,
@@ -166,8 +160,8 @@ impl Interaction {
     }
 }
 
-///Object in an orbit
-#[derive(Serialize, Deserialize, Clone)]
+///Object in an orbit. Persisted as [PersistedOrbital].
+#[derive(Clone)]
 pub struct Orbital {
     //center of orbit, usually parents location or
     // center of frame
@@ -185,12 +179,13 @@ pub struct Orbital {
     //true whenever paint() should highlight
     planet_highlight: bool,
 
-    #[serde(skip)]
     interaction: Interaction,
 
     pub(crate) obj: ObjTy,
     ///Depending on the ObjTy, maps 1:1 into the OscBank's primary or modulator banks
     pub osc_slot: usize,
+    ///Set if the slot's `on` parameter was turned off by the host.
+    pub muted: bool,
     children: Vec<Orbital>,
 }
 
@@ -204,7 +199,6 @@ impl Orbital {
     const MAX_ORBIT_SEC: f32 = 100.0;
     const MAX_ORBIT_PRIM: f32 = 300.0;
     const ZERO_SHIFT: Vec2 = Vec2 { x: 0.0, y: -1.0 };
-    pub const ABS_BASE_FREQ: f32 = 440.0;
 
     pub fn new_primary(at: Pos2, center: Pos2, slot: usize) -> Self {
         let radius = (at - center).length();
@@ -222,6 +216,7 @@ impl Orbital {
             obj: ObjTy::Planet,
             interaction: Interaction::None,
             osc_slot: slot,
+            muted: false,
             children: Vec::new(),
         };
 
@@ -251,7 +246,11 @@ impl Orbital {
                 } else {
                     Self::ORBIT_LINE_WIDTH
                 },
-                Color32::WHITE,
+                if self.muted {
+                    Color32::DARK_GRAY
+                } else {
+                    Color32::WHITE
+                },
             ),
             fill: Color32::TRANSPARENT,
         }));
@@ -261,11 +260,7 @@ impl Orbital {
         }
 
         //if currently dragging out a new one, draw that
-        if let Interaction::DragNewChild { slot, obj, at } = &self.interaction {
-            //build a temp object and paint that
-            let mut tmp = Orbital::new_primary(*at, self.obj_pos(), *slot);
-            tmp.obj = *obj;
-            tmp.radius = tmp.radius.clamp(Self::MIN_ORBIT, tmp.obj.max_orbit());
+        if let Some(tmp) = self.new_child() {
             tmp.paint(painter, selected);
         }
 
@@ -273,11 +268,110 @@ impl Orbital {
             .paint(self.speed_index, self.obj_pos(), force_highlight, painter);
     }
 
+    ///The child that is currently dragged out, as it would be added if the drag ended now.
+    pub(super) fn new_child(&self) -> Option<Orbital> {
+        if let Interaction::DragNewChild { slot, obj, at } = &self.interaction {
+            let mut child = Orbital::new_primary(*at, self.obj_pos(), *slot);
+            child.radius = child.radius.clamp(Self::MIN_ORBIT, obj.max_orbit());
+            child.obj = *obj;
+            Some(child)
+        } else {
+            None
+        }
+    }
+
     fn is_me(&self, idx: ParentIndex) -> bool {
+        idx == self.index()
+    }
+
+    ///Returns the oscillator index of this orbital.
+    pub fn index(&self) -> ParentIndex {
         if let ObjTy::Planet = self.obj {
-            idx == ParentIndex::Primary(self.osc_slot)
+            ParentIndex::Primary(self.osc_slot)
         } else {
-            idx == ParentIndex::Modulator(self.osc_slot)
+            ParentIndex::Modulator(self.osc_slot)
+        }
+    }
+
+    ///Returns the orbit radius mapped to the volume (primary) or range (modulator) of the oscillator.
+    pub fn radius_value(&self) -> f32 {
+        ((self.radius - Self::MIN_ORBIT) / (self.obj.max_orbit() - Self::MIN_ORBIT)).clamp(0.0, 1.0)
+    }
+
+    ///Inverse of `radius_value`.
+    pub fn set_radius_value(&mut self, value: f32) {
+        self.radius =
+            Self::MIN_ORBIT + value.clamp(0.0, 1.0) * (self.obj.max_orbit() - Self::MIN_ORBIT);
+    }
+
+    ///Returns the values of this orbital's slot parameters.
+    pub fn slot_values(&self) -> SlotValues {
+        SlotValues {
+            on: !self.muted,
+            radius: self.radius_value(),
+            speed: self.speed_index,
+            offset: self.offset,
+        }
+    }
+
+    ///Takes over the slot parameter values. Call `update()` on the root orbital afterwards to
+    /// re-layout the children.
+    pub fn apply_slot_values(&mut self, values: &SlotValues) {
+        self.muted = !values.on;
+        self.set_radius_value(values.radius);
+        self.speed_index = values.speed;
+        self.offset = values.offset;
+    }
+
+    ///Converts self and all children into their persisted form.
+    pub fn to_persisted(&self) -> PersistedOrbital {
+        let values = self.slot_values();
+        PersistedOrbital {
+            slot: self.osc_slot,
+            radius: values.radius,
+            speed: values.speed,
+            offset: values.offset,
+            muted: self.muted,
+            moons: self.children.iter().map(|c| c.to_persisted()).collect(),
+        }
+    }
+
+    ///Builds an orbital of type `obj` around `center` from its persisted form. Children are one type lower.
+    pub fn from_persisted(persisted: &PersistedOrbital, obj: ObjTy, center: Pos2) -> Self {
+        let mut orbital = Orbital::new_primary(
+            center + Vec2::new(0.0, -Self::MIN_ORBIT),
+            center,
+            persisted.slot,
+        );
+        orbital.obj = obj;
+        orbital.apply_slot_values(&SlotValues {
+            on: !persisted.muted,
+            radius: persisted.radius,
+            speed: persisted.speed,
+            offset: persisted.offset,
+        });
+        let child_center = orbital.obj_pos();
+        orbital.children = persisted
+            .moons
+            .iter()
+            .map(|m| Orbital::from_persisted(m, obj.lower(), child_center))
+            .collect();
+        orbital
+    }
+
+    ///Calls `f` on self and all children.
+    pub fn visit(&self, f: &mut impl FnMut(&Orbital)) {
+        f(self);
+        for c in &self.children {
+            c.visit(f);
+        }
+    }
+
+    ///Calls `f` on self and all children.
+    pub fn visit_mut(&mut self, f: &mut impl FnMut(&mut Orbital)) {
+        f(self);
+        for c in &mut self.children {
+            c.visit_mut(f);
         }
     }
 
@@ -438,13 +532,12 @@ impl Orbital {
     pub fn on_drag_end(&mut self) -> Option<ParentIndex> {
         if !self.interaction.is_none() {
             match &self.interaction {
-                Interaction::DragNewChild { slot, obj, at } => {
+                Interaction::DragNewChild { slot, obj, at: _ } => {
                     nih_log!("adding {:?} @ {}", obj, slot);
-                    let mut child = Orbital::new_primary(*at, self.obj_pos(), *slot);
-                    child.radius = child.radius.clamp(Self::MIN_ORBIT, obj.max_orbit());
-                    child.obj = *obj;
-                    self.children.push(child);
                     let retslot = *slot;
+                    if let Some(child) = self.new_child() {
+                        self.children.push(child);
+                    }
                     self.interaction = Interaction::None;
                     return Some(ParentIndex::Modulator(retslot));
                 }
@@ -599,56 +692,23 @@ impl Orbital {
         is_interactable
     }
 
-    ///appends self and the children to the state, returns the index self was added at
-    pub fn build_solar_state(&self, builder: &mut SolarState, parent_slot: Option<ParentIndex>) {
-        if let Some(slot) = parent_slot {
-            let dist = self.radius - Self::MIN_ORBIT;
-            //linear blend in orbit range
-            let range = dist / (self.obj.max_orbit() - Self::MIN_ORBIT);
-
-            builder.modulator_states.push(ModulatorState {
-                state: ModulatorOsc {
-                    parent_osc_slot: slot,
-                    speed_index: self.speed_index,
-                    range: range.clamp(0.0, 1.0),
-                    is_on: true,
-                },
-                offset: self.phase,
-                slot: self.osc_slot,
-            });
-        } else {
-            let volume = (self.radius - Self::MIN_ORBIT) / (self.obj.max_orbit() - Self::MIN_ORBIT);
-
-            builder.primary_states.push(PrimaryState {
-                offset: self.phase,
-                slot: self.osc_slot,
-                state: PrimaryOsc {
-                    speed_index: self.speed_index,
-                    volume,
-                    is_on: true,
-                },
-            });
+    ///Registers self and all children in the `topology`.
+    pub fn build_topology(&self, topology: &mut SolarTopology, parent_slot: Option<ParentIndex>) {
+        if let Some(parent) = parent_slot {
+            if let Some(slot) = topology.modulators.get_mut(self.osc_slot) {
+                *slot = Some(parent);
+            }
+        } else if let Some(slot) = topology.primaries.get_mut(self.osc_slot) {
+            *slot = true;
         }
 
-        //If we have a parent, pass self down as modulator, otherwise
-        // as primary.
-        let parent_slot = if parent_slot.is_some() {
-            ParentIndex::Modulator(self.osc_slot)
-        } else {
-            ParentIndex::Primary(self.osc_slot)
-        };
-
-        if let Interaction::DragNewChild { slot, obj, at } = &self.interaction {
-            //add new child already so we can hear it.
-            let mut tmp = Orbital::new_primary(*at, self.obj_pos(), *slot);
-            tmp.obj = *obj;
-            tmp.radius = tmp.radius.clamp(Self::MIN_ORBIT, tmp.obj.max_orbit());
-            tmp.build_solar_state(builder, Some(parent_slot));
+        for c in &self.children {
+            c.build_topology(topology, Some(self.index()));
         }
 
-        //do same with children
-        for c in &self.children {
-            c.build_solar_state(builder, Some(parent_slot));
+        //A child that is dragged out is played already, so it can be heard while dragging.
+        if let Some(child) = self.new_child() {
+            child.build_topology(topology, Some(self.index()));
         }
     }
 }
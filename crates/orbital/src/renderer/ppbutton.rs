@@ -1,9 +1,11 @@
 use egui::{Sense, Stroke, Vec2, Widget};
+use nih_plug::prelude::{BoolParam, ParamSetter};
 
 use super::adsrgui::GainSwitch;
 
 pub struct PPButton<'a> {
-    state: &'a mut bool,
+    param: &'a BoolParam,
+    setter: &'a ParamSetter<'a>,
 }
 impl<'a> PPButton<'a> {
     const SIZE: f32 = 50.0;
@@ -11,8 +13,8 @@ impl<'a> PPButton<'a> {
     const PAUSE_WIDTH: f32 = 5.0;
     const ICOSIZE: f32 = Self::SIZE - Self::REDUCE;
     const STROKE: Stroke = GainSwitch::STROKE;
-    pub fn new(state: &'a mut bool) -> Self {
-        Self { state }
+    pub fn new(param: &'a BoolParam, setter: &'a ParamSetter<'a>) -> Self {
+        Self { param, setter }
     }
 }
 
@@ -21,7 +23,9 @@ impl<'a> Widget for PPButton<'a> {
         let (mut resp, painter) = ui.allocate_painter(Vec2::splat(Self::SIZE), Sense::click());
 
         if resp.clicked() {
-            *self.state = !*self.state;
+            self.setter.begin_set_parameter(self.param);
+            self.setter.set_parameter(self.param, !self.param.value());
+            self.setter.end_set_parameter(self.param);
             resp.mark_changed();
         }
 
@@ -34,7 +38,7 @@ impl<'a> Widget for PPButton<'a> {
         };
 
         let rect = painter.clip_rect();
-        match self.state {
+        match self.param.value() {
             true => {
                 //draw line for play
                 painter.line_segment(
@@ -0,0 +1,237 @@
+use std::path::PathBuf;
+
+use egui::{CollapsingHeader, Color32, Grid, ScrollArea};
+
+use crate::{
+    preset::{
+        factory::factory_presets, rename_preset, scan_presets, user_preset_dir, Preset, PresetEntry,
+    },
+    OrbitalParams,
+};
+
+///Preset selected in the browser.
+#[derive(Clone, PartialEq)]
+enum Selection {
+    User(PathBuf),
+    ///Index into the factory presets
+    Factory(usize),
+}
+
+///Lists the factory presets and the presets in the user's preset directory, grouped by category. Saves the
+/// current patch, loads and renames presets.
+pub struct PresetBrowser {
+    dir: PathBuf,
+    factory: Vec<Preset>,
+    entries: Vec<PresetEntry>,
+    search: String,
+    name: String,
+    category: String,
+    selected: Option<Selection>,
+    error: Option<String>,
+}
+
+impl Default for PresetBrowser {
+    fn default() -> Self {
+        let dir = user_preset_dir();
+        PresetBrowser {
+            factory: factory_presets(),
+            entries: scan_presets(&dir),
+            dir,
+            search: String::new(),
+            name: String::new(),
+            category: String::new(),
+            selected: None,
+            error: None,
+        }
+    }
+}
+
+///Categories of the listed presets, in order. Expects the list to be sorted by category.
+fn categories<'a>(list: impl Iterator<Item = &'a str>) -> Vec<&'a str> {
+    let mut categories: Vec<&str> = list.collect();
+    categories.dedup();
+    categories
+}
+
+impl PresetBrowser {
+    fn refresh(&mut self) {
+        self.entries = scan_presets(&self.dir);
+    }
+
+    fn is_visible(&self, name: &str, category: &str) -> bool {
+        let search = self.search.to_lowercase();
+        name.to_lowercase().contains(&search) || category.to_lowercase().contains(&search)
+    }
+
+    ///Shows the names of `list`, grouped by category. Returns the clicked entry and whether it was double
+    /// clicked.
+    fn show_list<'a>(
+        &self,
+        ui: &mut egui::Ui,
+        section: &str,
+        list: impl Iterator<Item = (Selection, &'a str, &'a str)> + Clone,
+    ) -> Option<(Selection, bool)> {
+        let mut clicked = None;
+        let visible = list.filter(|(_, name, category)| self.is_visible(name, category));
+        for category in categories(visible.clone().map(|(_, _, c)| c)) {
+            let title = if category.is_empty() {
+                "Uncategorized"
+            } else {
+                category
+            };
+            CollapsingHeader::new(title)
+                .id_source((section, title))
+                .default_open(true)
+                .show(ui, |ui| {
+                    for (selection, name, _) in visible.clone().filter(|(_, _, c)| *c == category) {
+                        let is_selected = self.selected.as_ref() == Some(&selection);
+                        let response = ui.selectable_label(is_selected, name);
+                        if response.double_clicked() {
+                            clicked = Some((selection, true));
+                        } else if response.clicked() {
+                            clicked = Some((selection, false));
+                        }
+                    }
+                });
+        }
+        clicked
+    }
+
+    ///Draws the browser. Returns the preset that should be loaded, if any.
+    pub fn show(&mut self, ui: &mut egui::Ui, params: &OrbitalParams) -> Option<Preset> {
+        let mut loaded = None;
+
+        ui.horizontal(|ui| {
+            ui.label("Search");
+            ui.text_edit_singleline(&mut self.search);
+            if ui.button("Refresh").clicked() {
+                self.refresh();
+            }
+        });
+        ui.separator();
+
+        let mut clicked = None;
+        ScrollArea::vertical().max_height(250.0).show(ui, |ui| {
+            CollapsingHeader::new("Factory")
+                .default_open(true)
+                .show(ui, |ui| {
+                    let factory = self.factory.iter().enumerate().map(|(idx, p)| {
+                        (
+                            Selection::Factory(idx),
+                            p.name.as_str(),
+                            p.category.as_str(),
+                        )
+                    });
+                    if let Some(c) = self.show_list(ui, "factory", factory) {
+                        clicked = Some(c);
+                    }
+                });
+            CollapsingHeader::new("User")
+                .default_open(true)
+                .show(ui, |ui| {
+                    let user = self.entries.iter().map(|e| {
+                        (
+                            Selection::User(e.path.clone()),
+                            e.name.as_str(),
+                            e.category.as_str(),
+                        )
+                    });
+                    if let Some(c) = self.show_list(ui, "user", user) {
+                        clicked = Some(c);
+                    }
+                });
+        });
+        ui.separator();
+
+        //Double click loads the preset directly
+        let mut load = false;
+        if let Some((selection, double)) = clicked {
+            let (name, category) = match &selection {
+                Selection::User(path) => self
+                    .entries
+                    .iter()
+                    .find(|e| &e.path == path)
+                    .map(|e| (e.name.clone(), e.category.clone())),
+                Selection::Factory(idx) => self
+                    .factory
+                    .get(*idx)
+                    .map(|p| (p.name.clone(), p.category.clone())),
+            }
+            .unwrap_or_default();
+            self.name = name;
+            self.category = category;
+            self.selected = Some(selection);
+            load = double;
+        }
+
+        Grid::new("preset_grid").show(ui, |ui| {
+            ui.label("Name");
+            ui.text_edit_singleline(&mut self.name);
+            ui.end_row();
+            ui.label("Category");
+            ui.text_edit_singleline(&mut self.category);
+            ui.end_row();
+        });
+
+        let user_selected = if let Some(Selection::User(path)) = &self.selected {
+            Some(path.clone())
+        } else {
+            None
+        };
+        ui.horizontal(|ui| {
+            //Factory presets are saved as user presets
+            if ui.button("Save").clicked() {
+                let preset = Preset::capture(self.name.trim(), self.category.trim(), params);
+                match preset.save(&self.dir) {
+                    Ok(path) => {
+                        self.selected = Some(Selection::User(path));
+                        self.error = None;
+                        self.refresh();
+                    }
+                    Err(e) => self.error = Some(e.to_string()),
+                }
+            }
+            load |= ui
+                .add_enabled(self.selected.is_some(), egui::Button::new("Load"))
+                .clicked();
+            if ui
+                .add_enabled(user_selected.is_some(), egui::Button::new("Rename"))
+                .clicked()
+            {
+                if let Some(path) = &user_selected {
+                    match rename_preset(path, self.name.trim()) {
+                        Ok(path) => {
+                            self.selected = Some(Selection::User(path));
+                            self.error = None;
+                            self.refresh();
+                        }
+                        Err(e) => self.error = Some(e.to_string()),
+                    }
+                }
+            }
+        });
+
+        if load {
+            match &self.selected {
+                Some(Selection::User(path)) => match Preset::load(path) {
+                    Ok(preset) => {
+                        self.error = None;
+                        loaded = Some(preset);
+                    }
+                    Err(e) => self.error = Some(e.to_string()),
+                },
+                Some(Selection::Factory(idx)) => {
+                    self.error = None;
+                    loaded = self.factory.get(*idx).cloned();
+                }
+                None => {}
+            }
+        }
+
+        if let Some(err) = &self.error {
+            ui.colored_label(Color32::RED, err);
+        }
+
+        loaded
+    }
+}
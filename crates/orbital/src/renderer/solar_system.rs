@@ -1,19 +1,24 @@
 use std::time::Instant;
 
-use crossbeam::channel::Sender;
 use egui::{epaint::CircleShape, InputState, Painter, PointerButton, Response, Shape, Stroke};
-use nih_plug::nih_log;
-use nih_plug_egui::egui::Pos2;
+use nih_plug::{nih_log, prelude::ParamSetter};
+use nih_plug_egui::egui::{Pos2, Vec2};
 use serde_derive::{Deserialize, Serialize};
 
-use crate::{
-    com::{ComMsg, SolarState},
+use orbital_core::{
     osc::{modulator::ParentIndex, OscillatorBank},
+    schema::{PersistedSolarSystem, SchemaError},
+};
+
+use crate::{
+    com::SolarTopology,
+    slots::{SlotValues, DEFAULT_VOLUME},
+    OrbitalParams,
 };
 
 use super::orbital::{ObjTy, Orbital};
 
-#[derive(Serialize, Deserialize, Clone)]
+#[derive(Clone)]
 pub struct SlotAllocator {
     primary_slots: [bool; OscillatorBank::PRIMARY_OSC_COUNT],
     mod_slots: [bool; OscillatorBank::MOD_OSC_COUNT],
@@ -66,31 +71,104 @@ impl SlotAllocator {
             self.mod_slots[slot] = false
         }
     }
+
+    ///Marks the slot of `index` as used.
+    fn mark(&mut self, index: ParentIndex) {
+        match index {
+            ParentIndex::Primary(s) if s < OscillatorBank::PRIMARY_OSC_COUNT => {
+                self.primary_slots[s] = true
+            }
+            ParentIndex::Modulator(s) if s < OscillatorBank::MOD_OSC_COUNT => {
+                self.mod_slots[s] = true
+            }
+            _ => {}
+        }
+    }
 }
 
+///Last slot values that were exchanged with the host. Used to find out whether the host or the
+/// GUI changed a slot.
+#[derive(Clone)]
+struct SlotSync {
+    primaries: [Option<SlotValues>; OscillatorBank::PRIMARY_OSC_COUNT],
+    modulators: [Option<SlotValues>; OscillatorBank::MOD_OSC_COUNT],
+}
+
+impl Default for SlotSync {
+    fn default() -> Self {
+        SlotSync {
+            primaries: [None; OscillatorBank::PRIMARY_OSC_COUNT],
+            modulators: [None; OscillatorBank::MOD_OSC_COUNT],
+        }
+    }
+}
+
+///Persisted as [PersistedSolarSystem], so the GUI state can change without breaking saved projects. It is
+/// validated when loaded, so the slots in the tree always match the allocator.
 #[derive(Serialize, Deserialize, Clone)]
+#[serde(try_from = "PersistedSolarSystem", into = "PersistedSolarSystem")]
 pub struct SolarSystem {
     last_center: Pos2,
     //Primary orbitals. Each child is by necessity a modulator
     orbitals: Vec<Orbital>,
     allocator: SlotAllocator,
-    #[serde(default = "Instant::now", skip)]
     pub last_update: Instant,
+    ///Mirrors the `paused` parameter.
     pub is_paused: bool,
-    #[serde(skip)]
     pub selected: Option<ParentIndex>,
 
     //If set from the outside, makes sure everything is redrawn.
-    #[serde(skip)]
     pub is_dirty: bool,
     //If set, adds a new child on next update
-    #[serde(skip)]
     pub is_add_child: bool,
+    synced: SlotSync,
+}
+
+impl From<SolarSystem> for PersistedSolarSystem {
+    fn from(system: SolarSystem) -> Self {
+        PersistedSolarSystem {
+            planets: system.orbitals.iter().map(|o| o.to_persisted()).collect(),
+        }
+    }
+}
+
+impl TryFrom<PersistedSolarSystem> for SolarSystem {
+    type Error = SchemaError;
+
+    fn try_from(mut persisted: PersistedSolarSystem) -> Result<Self, Self::Error> {
+        for repair in persisted.validate()? {
+            nih_log!("Repaired solar system: {}", repair);
+        }
+
+        //The allocator is rebuilt from the tree, which has unique slots now
+        let mut system = SolarSystem::empty();
+        for planet in &persisted.planets {
+            let orbital = Orbital::from_persisted(planet, ObjTy::Planet, system.last_center);
+            orbital.visit(&mut |o| system.allocator.mark(o.index()));
+            system.orbitals.push(orbital);
+        }
+        Ok(system)
+    }
 }
 
 impl SolarSystem {
     pub fn new() -> Self {
-        let mut sys = SolarSystem {
+        let mut sys = Self::empty();
+
+        //setup a base system. New is only called if there is no state at all,
+        // so that should be all right.
+        // NOTE: Must match the default slot parameters
+        sys.insert_primary(Pos2 { x: 50.0, y: 50.0 }, Pos2 { x: 100.0, y: 100.0 });
+        for orb in &mut sys.orbitals {
+            orb.set_radius_value(DEFAULT_VOLUME);
+        }
+
+        sys
+    }
+
+    ///Solar system without any orbitals.
+    pub fn empty() -> Self {
+        SolarSystem {
             last_center: Pos2::ZERO,
             orbitals: Vec::new(),
             allocator: SlotAllocator::default(),
@@ -99,13 +177,61 @@ impl SolarSystem {
             selected: None,
             is_dirty: false,
             is_add_child: false,
-        };
+            synced: SlotSync::default(),
+        }
+    }
 
-        //setup a base system. New is only called if there is no state at all,
-        // so that should be all right.
-        sys.insert_primary(Pos2 { x: 50.0, y: 50.0 }, Pos2 { x: 100.0, y: 100.0 });
+    ///Adds a planet with the given speed, volume (0..1) and offset (in radiant). Returns its index, or `None`
+    /// if all primary slots are in use.
+    pub fn add_planet(&mut self, speed: f32, volume: f32, offset: f32) -> Option<ParentIndex> {
+        let slot = self.allocator.allocate_primary()?;
+        let center = self.last_center;
+        let mut planet = Orbital::new_primary(center + Vec2::new(0.0, -100.0), center, slot);
+        planet.apply_slot_values(&SlotValues {
+            on: true,
+            radius: volume,
+            speed,
+            offset,
+        });
+        self.orbitals.push(planet);
+        Some(ParentIndex::Primary(slot))
+    }
 
-        sys
+    ///Adds a moon around `parent` with the given speed, range (0..1) and offset (in radiant). Returns its index,
+    /// or `None` if the parent does not exist, or all modulator slots are in use.
+    pub fn add_moon(
+        &mut self,
+        parent: ParentIndex,
+        speed: f32,
+        range: f32,
+        offset: f32,
+    ) -> Option<ParentIndex> {
+        let slot = self.allocator.allocate_mod()?;
+        let index = ParentIndex::Modulator(slot);
+        let parent_orbital = if let Some(orbital) = self
+            .orbitals
+            .iter_mut()
+            .find_map(|o| o.find_index_mut(parent))
+        {
+            orbital
+        } else {
+            self.allocator.free_mod(slot);
+            return None;
+        };
+
+        parent_orbital.spawn_child(slot);
+        if let Some(moon) = parent_orbital.find_index_mut(index) {
+            moon.apply_slot_values(&SlotValues {
+                on: true,
+                radius: range,
+                speed,
+                offset,
+            });
+        }
+        for orb in &mut self.orbitals {
+            orb.update();
+        }
+        Some(index)
     }
 
     pub fn paint(&mut self, center: Pos2, painter: &Painter) {
@@ -128,13 +254,8 @@ impl SolarSystem {
         self.last_center = center;
     }
 
-    ///Handles input for the solar systems painting area.
-    pub fn handle_response(
-        &mut self,
-        coms: &mut Sender<ComMsg>,
-        response: &Response,
-        input: &InputState,
-    ) {
+    ///Handles input for the solar systems painting area. Returns true if any orbital was changed.
+    pub fn handle_response(&mut self, response: &Response, input: &InputState) -> bool {
         //handle child add if needed.
         if self.is_add_child {
             self.is_add_child = false;
@@ -194,6 +315,8 @@ impl SolarSystem {
                 for orbital in &mut self.orbitals {
                     if let Some(idx) = orbital.on_drag_end() {
                         self.selected = Some(idx);
+                        //The new child's final position
+                        draw_state_changed = true;
                     }
                 }
             }
@@ -272,9 +395,86 @@ impl SolarSystem {
             }
         }
 
-        if draw_state_changed {
-            //TODO handle breakdown
-            let _ = coms.send(ComMsg::StateChange(self.get_solar_state()));
+        draw_state_changed
+    }
+
+    ///Pulls all slot parameters the host changed since the last sync into the orbitals.
+    pub fn read_params(&mut self, params: &OrbitalParams) {
+        let mut any_changed = false;
+        let synced = &mut self.synced;
+        for orb in &mut self.orbitals {
+            orb.visit_mut(&mut |o| {
+                let (values, last) = match o.index() {
+                    ParentIndex::Primary(s) if s < OscillatorBank::PRIMARY_OSC_COUNT => (
+                        SlotValues::from_primary(&params.primaries[s]),
+                        &mut synced.primaries[s],
+                    ),
+                    ParentIndex::Modulator(s) if s < OscillatorBank::MOD_OSC_COUNT => (
+                        SlotValues::from_modulator(&params.modulators[s]),
+                        &mut synced.modulators[s],
+                    ),
+                    _ => return,
+                };
+
+                if *last != Some(values) {
+                    o.apply_slot_values(&values);
+                    *last = Some(values);
+                    any_changed = true;
+                }
+            });
+        }
+
+        if any_changed {
+            for orb in &mut self.orbitals {
+                orb.update();
+            }
+        }
+    }
+
+    ///Pushes all orbital values the GUI changed since the last sync to the host. Unused slots are turned off.
+    pub fn write_params(&mut self, params: &OrbitalParams, setter: &ParamSetter) {
+        let mut primaries = [None; OscillatorBank::PRIMARY_OSC_COUNT];
+        let mut modulators = [None; OscillatorBank::MOD_OSC_COUNT];
+        for orb in &self.orbitals {
+            orb.visit(&mut |o| {
+                match o.index() {
+                    ParentIndex::Primary(s) if s < primaries.len() => {
+                        primaries[s] = Some(o.slot_values())
+                    }
+                    ParentIndex::Modulator(s) if s < modulators.len() => {
+                        modulators[s] = Some(o.slot_values())
+                    }
+                    _ => {}
+                }
+                //Played while it is dragged out, see `Orbital::build_topology`
+                if let Some(child) = o.new_child() {
+                    if let Some(slot) = modulators.get_mut(child.osc_slot) {
+                        *slot = Some(child.slot_values());
+                    }
+                }
+            });
+        }
+
+        for (slot, values) in primaries.into_iter().enumerate() {
+            let values = values.unwrap_or(SlotValues {
+                on: false,
+                ..SlotValues::from_primary(&params.primaries[slot])
+            });
+            if self.synced.primaries[slot] != Some(values) {
+                values.write_primary(&params.primaries[slot], setter);
+                self.synced.primaries[slot] = Some(values);
+            }
+        }
+
+        for (slot, values) in modulators.into_iter().enumerate() {
+            let values = values.unwrap_or(SlotValues {
+                on: false,
+                ..SlotValues::from_modulator(&params.modulators[slot])
+            });
+            if self.synced.modulators[slot] != Some(values) {
+                values.write_modulator(&params.modulators[slot], setter);
+                self.synced.modulators[slot] = Some(values);
+            }
         }
     }
 
@@ -294,19 +494,35 @@ impl SolarSystem {
         self.selected = Some(ParentIndex::Primary(slot));
     }
 
-    //builds the solar state from the current state. Used mainly to init
-    // the synth when headless
-    pub fn get_solar_state(&self) -> SolarState {
-        let mut builder = SolarState {
-            primary_states: Vec::with_capacity(OscillatorBank::PRIMARY_OSC_COUNT),
-            modulator_states: Vec::with_capacity(OscillatorBank::MOD_OSC_COUNT),
-        };
-
+    ///Builds the topology of the current system. Used to init the synth when headless, and
+    /// to notify the synth of structural changes.
+    pub fn get_topology(&self) -> SolarTopology {
+        let mut topology = SolarTopology::default();
         for orb in &self.orbitals {
-            orb.build_solar_state(&mut builder, None);
+            orb.build_topology(&mut topology, None);
         }
 
-        builder
+        topology
+    }
+
+    ///Returns the slots of all orbitals in the system.
+    pub fn used_slots(&self) -> Vec<ParentIndex> {
+        let primary = self
+            .allocator
+            .primary_slots
+            .iter()
+            .enumerate()
+            .filter(|(_, used)| **used)
+            .map(|(idx, _)| ParentIndex::Primary(idx));
+        let modulator = self
+            .allocator
+            .mod_slots
+            .iter()
+            .enumerate()
+            .filter(|(_, used)| **used)
+            .map(|(idx, _)| ParentIndex::Modulator(idx));
+
+        primary.chain(modulator).collect()
     }
 
     pub fn get_selected_orbital(&mut self) -> Option<&mut Orbital> {
@@ -0,0 +1,62 @@
+use std::path::Path;
+
+use egui::{Color32, Grid};
+
+use orbital_core::tuning::{KeyboardMapping, Scale, Tuning};
+
+///Loads Scala scale and keyboard mapping files into a [Tuning]. Keeps the entered
+/// paths and the last error between frames.
+#[derive(Default)]
+pub struct TuningEditor {
+    scl_path: String,
+    kbm_path: String,
+    error: Option<String>,
+}
+
+impl TuningEditor {
+    ///Draws the editor. Returns true if `tuning` was changed.
+    pub fn show(&mut self, ui: &mut egui::Ui, tuning: &mut Tuning) -> bool {
+        let mut changed = false;
+        ui.label(format!("Current: {}", tuning.name()));
+        Grid::new("tuning_grid").show(ui, |ui| {
+            ui.label("Scale (.scl)");
+            ui.text_edit_singleline(&mut self.scl_path);
+            if ui.button("Load").clicked() {
+                match Scale::load(Path::new(self.scl_path.trim())) {
+                    Ok(scale) => {
+                        tuning.scale = Some(scale);
+                        self.error = None;
+                        changed = true;
+                    }
+                    Err(e) => self.error = Some(e.to_string()),
+                }
+            }
+            ui.end_row();
+
+            ui.label("Mapping (.kbm)");
+            ui.text_edit_singleline(&mut self.kbm_path);
+            if ui.button("Load").clicked() {
+                match KeyboardMapping::load(Path::new(self.kbm_path.trim())) {
+                    Ok(mapping) => {
+                        tuning.mapping = Some(mapping);
+                        self.error = None;
+                        changed = true;
+                    }
+                    Err(e) => self.error = Some(e.to_string()),
+                }
+            }
+            ui.end_row();
+        });
+
+        if ui.button("Reset to 12-TET").clicked() {
+            *tuning = Tuning::default();
+            self.error = None;
+            changed = true;
+        }
+        if let Some(err) = &self.error {
+            ui.colored_label(Color32::RED, err);
+        }
+
+        changed
+    }
+}
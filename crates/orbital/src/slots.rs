@@ -0,0 +1,182 @@
+use std::sync::Arc;
+
+use nih_plug::prelude::{
+    BoolParam, FloatParam, FloatRange, Param, ParamSetter, Params, SmoothingStyle,
+};
+
+///Volume of the planet a new solar system starts with.
+pub const DEFAULT_VOLUME: f32 = 0.25;
+
+///Smoothing of the automatable slot values, same as the effect parameters.
+const SMOOTHING: SmoothingStyle = SmoothingStyle::Linear(50.0);
+
+///Host parameters of a single primary oscillator slot.
+#[derive(Params)]
+pub struct PrimarySlotParams {
+    ///Mutes the slot's planet if turned off.
+    #[id = "prim_on"]
+    pub on: BoolParam,
+    #[id = "prim_volume"]
+    pub volume: FloatParam,
+    #[id = "prim_speed"]
+    pub speed: FloatParam,
+    ///Phase offset in degree
+    #[id = "prim_offset"]
+    pub offset: FloatParam,
+}
+
+impl PrimarySlotParams {
+    pub fn new(index: usize) -> Self {
+        //The default solar system has exactly one planet in the first slot.
+        let is_default = index == 0;
+        PrimarySlotParams {
+            on: BoolParam::new(format!("Primary {} On", index), is_default),
+            volume: FloatParam::new(
+                format!("Primary {} Volume", index),
+                if is_default { DEFAULT_VOLUME } else { 0.0 },
+                FloatRange::Linear { min: 0.0, max: 1.0 },
+            )
+            .with_smoother(SMOOTHING)
+            .with_value_to_string(Arc::new(|v| format!("{:.2}", v))),
+            speed: speed_param(format!("Primary {} Speed", index)),
+            offset: offset_param(format!("Primary {} Offset", index)),
+        }
+    }
+}
+
+///Host parameters of a single modulator oscillator slot.
+#[derive(Params)]
+pub struct ModulatorSlotParams {
+    ///Mutes the slot's moon if turned off.
+    #[id = "mod_on"]
+    pub on: BoolParam,
+    #[id = "mod_range"]
+    pub range: FloatParam,
+    #[id = "mod_speed"]
+    pub speed: FloatParam,
+    ///Phase offset in degree
+    #[id = "mod_offset"]
+    pub offset: FloatParam,
+}
+
+impl ModulatorSlotParams {
+    pub fn new(index: usize) -> Self {
+        ModulatorSlotParams {
+            on: BoolParam::new(format!("Modulator {} On", index), false),
+            range: FloatParam::new(
+                format!("Modulator {} Range", index),
+                0.0,
+                FloatRange::Linear { min: 0.0, max: 1.0 },
+            )
+            .with_smoother(SMOOTHING)
+            .with_value_to_string(Arc::new(|v| format!("{:.2}", v))),
+            speed: speed_param(format!("Modulator {} Speed", index)),
+            offset: offset_param(format!("Modulator {} Offset", index)),
+        }
+    }
+}
+
+fn speed_param(name: String) -> FloatParam {
+    FloatParam::new(
+        name,
+        0.0,
+        FloatRange::Linear {
+            min: -20.0,
+            max: 20.0,
+        },
+    )
+    .with_smoother(SMOOTHING)
+    .with_unit(" va")
+    .with_value_to_string(Arc::new(|v| format!("{:.1}", v)))
+}
+
+fn offset_param(name: String) -> FloatParam {
+    FloatParam::new(
+        name,
+        0.0,
+        FloatRange::Linear {
+            min: 0.0,
+            max: 360.0,
+        },
+    )
+    .with_unit("°")
+    .with_value_to_string(Arc::new(|v| format!("{:.0}", v)))
+}
+
+///Plain values of a slot. Used to track which side (host or GUI) changed a slot last, and which values
+/// the synth was last updated with.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct SlotValues {
+    pub on: bool,
+    ///Volume for primaries, range for modulators (0..1)
+    pub radius: f32,
+    pub speed: f32,
+    ///Offset in radiant
+    pub offset: f32,
+}
+
+impl SlotValues {
+    pub fn from_primary(params: &PrimarySlotParams) -> Self {
+        SlotValues {
+            on: params.on.value(),
+            radius: params.volume.value(),
+            speed: params.speed.value(),
+            offset: params.offset.value().to_radians(),
+        }
+    }
+
+    pub fn from_modulator(params: &ModulatorSlotParams) -> Self {
+        SlotValues {
+            on: params.on.value(),
+            radius: params.range.value(),
+            speed: params.speed.value(),
+            offset: params.offset.value().to_radians(),
+        }
+    }
+
+    ///Values the synth plays over the next `samples`. Volume and speed are advanced by their smoothers.
+    pub fn next_primary(params: &PrimarySlotParams, samples: u32) -> Self {
+        SlotValues {
+            on: params.on.value(),
+            radius: params.volume.smoothed.next_step(samples),
+            speed: params.speed.smoothed.next_step(samples),
+            offset: params.offset.value().to_radians(),
+        }
+    }
+
+    ///Values the synth plays over the next `samples`. Range and speed are advanced by their smoothers.
+    pub fn next_modulator(params: &ModulatorSlotParams, samples: u32) -> Self {
+        SlotValues {
+            on: params.on.value(),
+            radius: params.range.smoothed.next_step(samples),
+            speed: params.speed.smoothed.next_step(samples),
+            offset: params.offset.value().to_radians(),
+        }
+    }
+
+    pub fn write_primary(&self, params: &PrimarySlotParams, setter: &ParamSetter) {
+        set_if_changed(setter, &params.on, self.on);
+        set_if_changed(setter, &params.volume, self.radius);
+        set_if_changed(setter, &params.speed, self.speed);
+        set_if_changed(setter, &params.offset, self.offset.to_degrees());
+    }
+
+    pub fn write_modulator(&self, params: &ModulatorSlotParams, setter: &ParamSetter) {
+        set_if_changed(setter, &params.on, self.on);
+        set_if_changed(setter, &params.range, self.radius);
+        set_if_changed(setter, &params.speed, self.speed);
+        set_if_changed(setter, &params.offset, self.offset.to_degrees());
+    }
+}
+
+///Sets `param` to `value` as a single gesture, unless it already has that value.
+pub fn set_if_changed<P: Param>(setter: &ParamSetter, param: &P, value: P::Plain)
+where
+    P::Plain: PartialEq,
+{
+    if param.modulated_plain_value() != value {
+        setter.begin_set_parameter(param);
+        setter.set_parameter(param, value);
+        setter.end_set_parameter(param);
+    }
+}
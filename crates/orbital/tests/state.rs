@@ -0,0 +1,154 @@
+//!Loads plugin states saved by every released version, like a host restoring an old project. The
+//! repository's history starts at 0.2, so it is the oldest version covered. The solar system's schema itself
+//! is tested in `orbital-core`.
+
+use std::{fs, path::PathBuf};
+
+use nih_plug::{
+    prelude::{Enum, Plugin},
+    wrapper::state::{ParamValue, PluginState},
+};
+use orbital::{offline::load_params, Orbital};
+use orbital_core::{gain::GainType, osc::modulator::ParentIndex, osc::ModulationType};
+
+fn state_path(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("state")
+        .join(name)
+}
+
+fn assert_close(a: f32, b: f32) {
+    assert!((a - b).abs() < 1e-4, "{} != {}", a, b);
+}
+
+#[test]
+fn version_0_2() {
+    let params = load_params(&state_path("0.2.json")).expect("Could not load the 0.2 state");
+
+    //Persisted as fields by 0.2, host parameters since
+    assert_eq!(
+        params.mod_ty.value().to_index(),
+        ModulationType::Absolute.to_index()
+    );
+    assert_eq!(
+        params.gain_ty.value().to_index(),
+        GainType::Linear.to_index()
+    );
+    assert!(!params.reset_phase.value());
+    assert!((params.attack.value() - 0.05).abs() < 1e-6);
+    //Persisted as part of the solar system by 0.2
+    assert!(params.paused.value());
+
+    //The slots are host parameters since, seeded from the orbitals. Offsets are in degrees.
+    assert!(params.primaries[0].on.value());
+    assert!(params.primaries[1].on.value());
+    assert!(!params.primaries[2].on.value());
+    assert_close(params.primaries[0].offset.value(), 1.2f32.to_degrees());
+    assert_close(params.primaries[1].offset.value(), 3.0f32.to_degrees());
+    assert!(params.modulators[0].on.value());
+    assert!(params.modulators[1].on.value());
+    assert!(!params.modulators[2].on.value());
+    assert_close(params.modulators[0].offset.value(), 0.5f32.to_degrees());
+    assert_close(params.modulators[1].offset.value(), 0.0);
+
+    let system = params.solar_system.read().unwrap();
+    assert_eq!(
+        system.used_slots(),
+        vec![
+            ParentIndex::Primary(0),
+            ParentIndex::Primary(1),
+            ParentIndex::Modulator(0),
+            ParentIndex::Modulator(1),
+        ]
+    );
+    let topology = system.get_topology();
+    assert_eq!(topology.modulators[0], Some(ParentIndex::Primary(0)));
+    assert_eq!(topology.modulators[1], Some(ParentIndex::Modulator(0)));
+}
+
+///A migrated state is saved in the current schema and loads again.
+#[test]
+fn migrated_state_roundtrips() {
+    let params = load_params(&state_path("0.2.json")).unwrap();
+    let system = params.solar_system.read().unwrap();
+    let json = serde_json::to_string(&*system).unwrap();
+    let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+    assert_eq!(value["version"], orbital_core::schema::SCHEMA_VERSION);
+}
+
+///States saved before the slots were host parameters only stored the orbit values in the solar system. The
+/// slot parameters, that drive the synth and the GUI, are seeded from them.
+#[test]
+fn slot_params_are_seeded() {
+    let params = load_params(&state_path("0.2.json")).unwrap();
+    assert_close(params.primaries[0].volume.value(), (100.0 - 25.0) / 275.0);
+    assert_close(params.primaries[0].speed.value(), 0.0);
+    assert_close(params.primaries[1].volume.value(), 1.0);
+    assert_close(params.primaries[1].speed.value(), -1.0);
+    assert_close(params.modulators[0].range.value(), (50.0 - 25.0) / 75.0);
+    assert_close(params.modulators[0].speed.value(), 1.0);
+    assert_close(params.modulators[1].range.value(), (40.0 - 25.0) / 75.0);
+    assert_close(params.modulators[1].speed.value(), -2.0);
+}
+
+///Slot parameters saved in the state win over the orbit values of the solar system.
+#[test]
+fn saved_slot_params_are_kept() {
+    let src = fs::read_to_string(state_path("0.2.json")).unwrap();
+    let mut state: serde_json::Value = serde_json::from_str(&src).unwrap();
+    state["params"]["prim_volume_1"] = serde_json::json!({ "F32": 0.9 });
+    let path = std::env::temp_dir().join("orbital-saved-slot-params.json");
+    fs::write(&path, state.to_string()).unwrap();
+
+    let params = load_params(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    assert_close(params.primaries[0].volume.value(), 0.9);
+    assert_close(params.primaries[1].volume.value(), 1.0);
+}
+
+///The wrapper migrates a state with `filter_state`, before it restores the parameters from it.
+#[test]
+fn filter_state_migrates_fields() {
+    let src = fs::read_to_string(state_path("0.2.json")).unwrap();
+    let mut state: PluginState = serde_json::from_str(&src).unwrap();
+    <Orbital as Plugin>::filter_state(&mut state);
+
+    for removed in ["Synth", "modty", "gainty"] {
+        assert!(
+            !state.fields.contains_key(removed),
+            "{} not removed",
+            removed
+        );
+    }
+    assert!(state.fields.contains_key("SolarSystem"));
+
+    let param = |id: &str| state.params.get(id);
+    let float = |id: &str| match param(id) {
+        Some(ParamValue::F32(v)) => *v,
+        _ => panic!("{} is not a float", id),
+    };
+    let index = |id: &str| match param(id) {
+        Some(ParamValue::I32(i)) => *i as usize,
+        _ => panic!("{} is not an enum", id),
+    };
+    assert_eq!(index("mod_ty"), ModulationType::Absolute.to_index());
+    assert_eq!(index("gain_ty"), GainType::Linear.to_index());
+    assert!(matches!(param("paused"), Some(ParamValue::Bool(true))));
+    //Saved parameters are kept
+    assert!(matches!(
+        param("reset_phase"),
+        Some(ParamValue::Bool(false))
+    ));
+
+    assert!(matches!(param("prim_on_1"), Some(ParamValue::Bool(true))));
+    assert_close(float("prim_volume_1"), (100.0 - 25.0) / 275.0);
+    assert_close(float("prim_speed_2"), -1.0);
+    assert_close(float("prim_offset_2"), 3.0f32.to_degrees());
+    assert!(matches!(param("mod_on_2"), Some(ParamValue::Bool(true))));
+    assert_close(float("mod_range_1"), (50.0 - 25.0) / 75.0);
+    assert_close(float("mod_speed_2"), -2.0);
+    //Empty slots keep their defaults
+    assert!(param("prim_on_3").is_none());
+    assert!(param("mod_on_3").is_none());
+}